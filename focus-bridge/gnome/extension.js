@@ -0,0 +1,39 @@
+import Gio from "gi://Gio";
+import GLib from "gi://GLib";
+import { Extension } from "resource:///org/gnome/shell/extensions/extension.js";
+
+export default class OverBindFocusBridge extends Extension {
+  enable() {
+    this._focusHandler = global.display.connect("notify::focus-window", () =>
+      this._reportFocus(),
+    );
+    this._reportFocus();
+  }
+
+  disable() {
+    global.display.disconnect(this._focusHandler);
+    this._focusHandler = null;
+  }
+
+  // The WM_CLASS class for X11 windows and the app id for Wayland ones, calls fail quietly
+  // while OverBind isn't running
+  _reportFocus() {
+    const window = global.display.focus_window;
+    const app = window?.get_wm_class();
+    if (!app) {
+      return;
+    }
+    Gio.DBus.session.call(
+      "io.github.overbind.Focus",
+      "/io/github/overbind/Focus",
+      "io.github.overbind.Focus",
+      "FocusChanged",
+      new GLib.Variant("(s)", [app]),
+      null,
+      Gio.DBusCallFlags.NO_AUTO_START,
+      -1,
+      null,
+      null,
+    );
+  }
+}
@@ -0,0 +1,16 @@
+// Calls fail quietly while OverBind isn't running
+function reportFocus(window) {
+    if (!window) {
+        return;
+    }
+    callDBus(
+        "io.github.overbind.Focus",
+        "/io/github/overbind/Focus",
+        "io.github.overbind.Focus",
+        "FocusChanged",
+        String(window.resourceClass)
+    );
+}
+
+// Plasma 6 renamed clientActivated to windowActivated
+(workspace.windowActivated || workspace.clientActivated).connect(reportFocus);
@@ -0,0 +1,659 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub(crate) const CONFIG_VERSION: u32 = 2;
+
+// Mouse wheel ticks have no windows virtual keycode, they get the keycodes right after that range
+pub(crate) const WHEEL_UP_KEYCODE: u32 = 0x100;
+pub(crate) const WHEEL_DOWN_KEYCODE: u32 = 0x101;
+
+pub(crate) const DEFAULT_CHORD_WINDOW_MS: u32 = 50;
+
+fn default_chord_window_ms() -> u32 {
+    DEFAULT_CHORD_WINDOW_MS
+}
+
+pub(crate) const DEFAULT_TAP_TIMEOUT_MS: u32 = 200;
+
+fn default_tap_timeout_ms() -> u32 {
+    DEFAULT_TAP_TIMEOUT_MS
+}
+
+pub(crate) const DEFAULT_TURBO_RATE_HZ: u16 = 10;
+pub(crate) const DEFAULT_TURBO_DUTY_PERCENT: u8 = 50;
+
+fn default_turbo_rate_hz() -> u16 {
+    DEFAULT_TURBO_RATE_HZ
+}
+
+fn default_turbo_duty_percent() -> u8 {
+    DEFAULT_TURBO_DUTY_PERCENT
+}
+
+pub(crate) const DEFAULT_MOUSE_LOOK_SENSITIVITY: u16 = 2000;
+pub(crate) const DEFAULT_MOUSE_LOOK_SMOOTHING_MS: u32 = 20;
+pub(crate) const DEFAULT_MOUSE_LOOK_DECAY_MS: u32 = 50;
+
+fn default_mouse_look_sensitivity() -> u16 {
+    DEFAULT_MOUSE_LOOK_SENSITIVITY
+}
+
+fn default_mouse_look_smoothing_ms() -> u32 {
+    DEFAULT_MOUSE_LOOK_SMOOTHING_MS
+}
+
+fn default_mouse_look_decay_ms() -> u32 {
+    DEFAULT_MOUSE_LOOK_DECAY_MS
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GamepadButton {
+    #[serde(rename = "dpad_up")]
+    DPadUp,
+    #[serde(rename = "dpad_down")]
+    DPadDown,
+    #[serde(rename = "dpad_left")]
+    DPadLeft,
+    #[serde(rename = "dpad_right")]
+    DPadRight,
+    Start,
+    Back,
+    LeftThumb,
+    RightThumb,
+    LeftShoulder,
+    RightShoulder,
+    Guide,
+    A,
+    B,
+    X,
+    Y,
+}
+
+impl GamepadButton {
+    // Button masks as used by XInput and by the legacy `face_button` config values
+    pub(crate) fn from_xinput_mask(mask: u16) -> Option<Self> {
+        match mask {
+            0x0001 => Some(GamepadButton::DPadUp),
+            0x0002 => Some(GamepadButton::DPadDown),
+            0x0004 => Some(GamepadButton::DPadLeft),
+            0x0008 => Some(GamepadButton::DPadRight),
+            0x0010 => Some(GamepadButton::Start),
+            0x0020 => Some(GamepadButton::Back),
+            0x0040 => Some(GamepadButton::LeftThumb),
+            0x0080 => Some(GamepadButton::RightThumb),
+            0x0100 => Some(GamepadButton::LeftShoulder),
+            0x0200 => Some(GamepadButton::RightShoulder),
+            0x0400 => Some(GamepadButton::Guide),
+            0x1000 => Some(GamepadButton::A),
+            0x2000 => Some(GamepadButton::B),
+            0x4000 => Some(GamepadButton::X),
+            0x8000 => Some(GamepadButton::Y),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn xinput_mask(self) -> u16 {
+        match self {
+            GamepadButton::DPadUp => 0x0001,
+            GamepadButton::DPadDown => 0x0002,
+            GamepadButton::DPadLeft => 0x0004,
+            GamepadButton::DPadRight => 0x0008,
+            GamepadButton::Start => 0x0010,
+            GamepadButton::Back => 0x0020,
+            GamepadButton::LeftThumb => 0x0040,
+            GamepadButton::RightThumb => 0x0080,
+            GamepadButton::LeftShoulder => 0x0100,
+            GamepadButton::RightShoulder => 0x0200,
+            GamepadButton::Guide => 0x0400,
+            GamepadButton::A => 0x1000,
+            GamepadButton::B => 0x2000,
+            GamepadButton::X => 0x4000,
+            GamepadButton::Y => 0x8000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StickAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TriggerSide {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Stick {
+    Left,
+    Right,
+}
+
+// How a stick's two axes are combined when both are pushed, e.g. by 8-way movement keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StickNormalization {
+    // Each axis is reported as is, diagonals reach past the circle a real stick moves in
+    #[default]
+    Square,
+    // Diagonals are pulled back onto the edge of the stick's circle
+    Circle,
+    // Diagonals are this percentage of the larger axis, 100 makes them as long as straight inputs
+    Diagonal(u16),
+}
+
+// How a SOCD pair resolves while both keys are held
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SocdMode {
+    // The most recently pressed key wins, the other one comes back when it is released
+    #[default]
+    LastInput,
+    // The key that was pressed first keeps winning until it is released
+    FirstInput,
+    // Both keys cancel out
+    Neutral,
+    // The given key always wins, e.g. up-priority
+    Priority(u32),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RampCurve {
+    #[default]
+    Linear,
+    // Starts slow and speeds up towards the end
+    Exponential,
+    // Moves in this many equal jumps
+    Stepped(u8),
+}
+
+// Time a stick binding takes to reach its value when pressed and to return to center when released
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct Ramp {
+    #[serde(default)]
+    pub up_ms: u32,
+    #[serde(default)]
+    pub down_ms: u32,
+    #[serde(default)]
+    pub curve: RampCurve,
+}
+
+// Moves the right stick with the mouse, for games that only take camera input from a controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct MouseLook {
+    // Stick value for a mouse moving one count per millisecond
+    #[serde(default = "default_mouse_look_sensitivity")]
+    pub sensitivity: u16,
+    // Where the stick starts when the mouse moves at all, so slow movements get past the game's
+    // own deadzone
+    #[serde(default)]
+    pub deadzone_percent: u8,
+    // Mouse speed is averaged over about this long, smoothing out the gaps between mouse reports
+    #[serde(default = "default_mouse_look_smoothing_ms")]
+    pub smoothing_ms: u32,
+    // Longest the stick takes to return to center once the mouse stops
+    #[serde(default = "default_mouse_look_decay_ms")]
+    pub decay_ms: u32,
+}
+
+impl Default for MouseLook {
+    fn default() -> Self {
+        Self {
+            sensitivity: DEFAULT_MOUSE_LOOK_SENSITIVITY,
+            deadzone_percent: 0,
+            smoothing_ms: DEFAULT_MOUSE_LOOK_SMOOTHING_MS,
+            decay_ms: DEFAULT_MOUSE_LOOK_DECAY_MS,
+        }
+    }
+}
+
+// What a macro does when its key is released while it plays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MacroMode {
+    // Plays to the end
+    #[default]
+    Once,
+    // Starts over after the last step until the key is released, the current run is finished
+    RepeatWhileHeld,
+    // Stops right away and releases everything the macro holds
+    CancelOnRelease,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct MacroStep {
+    // Time since the previous step, or since the key was pressed for the first step
+    #[serde(default)]
+    pub delay_ms: u32,
+    pub action: BindingAction,
+    // Presses the action's output when set, releases it otherwise
+    pub is_down: bool,
+}
+
+// A macro captured from real input, kept in the config so it can be edited and put on a binding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RecordedMacro {
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+// Keycodes are windows virtual keycodes on every platform, the backends translate them when loading
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum BindingAction {
+    Keyboard {
+        key: u32,
+    },
+    GamepadButton {
+        button: GamepadButton,
+    },
+    Axis {
+        axis: StickAxis,
+        value: i16,
+        // Digital when unset, the value is applied instantly
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ramp: Option<Ramp>,
+    },
+    Trigger {
+        side: TriggerSide,
+        value: u8,
+    },
+    // Scales every stick output while held, e.g. 50 to walk
+    StickScale {
+        percent: u16,
+    },
+    // Switches to a layer while held
+    LayerHold {
+        layer: String,
+    },
+    // Switches to a layer until pressed again
+    LayerToggle {
+        layer: String,
+    },
+    // `tap` when released within the timeout, `hold` once held for longer
+    TapHold {
+        tap: Box<BindingAction>,
+        hold: Box<BindingAction>,
+        #[serde(default = "default_tap_timeout_ms")]
+        timeout_ms: u32,
+    },
+    // Plays a sequence of presses and releases
+    Macro {
+        steps: Vec<MacroStep>,
+        #[serde(default)]
+        mode: MacroMode,
+    },
+    // Pulses `action` while held, on for `duty_percent` of every cycle
+    Turbo {
+        action: Box<BindingAction>,
+        #[serde(default = "default_turbo_rate_hz")]
+        rate_hz: u16,
+        #[serde(default = "default_turbo_duty_percent")]
+        duty_percent: u8,
+    },
+    Socd {
+        opposite: u32,
+        #[serde(default)]
+        mode: SocdMode,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Binding {
+    pub keycode: u32,
+    // Other keys that have to be pressed together with `keycode`, the binding is a chord when set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chord: Vec<u32>,
+    // Input device the binding is limited to, any device triggers it when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    pub action: BindingAction,
+}
+
+// Bindings that take over from the layers below while the layer is on, keys without a binding
+// here fall through to the layer below
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Layer {
+    pub name: String,
+    pub bindings: Vec<Binding>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct BindingConfig {
+    pub version: u32,
+    // The base layer, always on
+    pub bindings: Vec<Binding>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<Layer>,
+    // How keys pushing a stick axis in opposite directions are resolved, last input when unset
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub axis_socd: HashMap<StickAxis, SocdMode>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub stick_normalization: HashMap<Stick, StickNormalization>,
+    // How close together the keys of a chord have to be pressed
+    #[serde(default = "default_chord_window_ms")]
+    pub chord_window_ms: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recorded_macros: Vec<RecordedMacro>,
+    // Off when unset, the mouse is then passed through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mouse_look: Option<MouseLook>,
+}
+
+impl Default for BindingConfig {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            bindings: Vec::new(),
+            layers: Vec::new(),
+            axis_socd: HashMap::new(),
+            stick_normalization: HashMap::new(),
+            chord_window_ms: DEFAULT_CHORD_WINDOW_MS,
+            recorded_macros: Vec::new(),
+            mouse_look: None,
+        }
+    }
+}
+
+// Format used before typed bindings, a flat list where `keycode` is a hex string
+#[derive(Debug, Deserialize)]
+struct LegacyKeyConfig {
+    keycode: String,
+    result_type: String,
+    result_value: i32,
+}
+
+impl TryFrom<LegacyKeyConfig> for Binding {
+    type Error = String;
+
+    fn try_from(legacy: LegacyKeyConfig) -> Result<Self, Self::Error> {
+        let keycode = u32::from_str_radix(&legacy.keycode, 16)
+            .map_err(|_| format!("invalid hexadecimal keycode {:?}", legacy.keycode))?;
+        let value = legacy.result_value;
+
+        let stick_value = |axis| {
+            i16::try_from(value)
+                .map(|value| BindingAction::Axis {
+                    axis,
+                    value,
+                    ramp: None,
+                })
+                .map_err(|_| format!("stick value {} is out of range", value))
+        };
+        let trigger_value = |side| {
+            u8::try_from(value)
+                .map(|value| BindingAction::Trigger { side, value })
+                .map_err(|_| format!("trigger value {} is out of range", value))
+        };
+        let keycode_value =
+            || u32::try_from(value).map_err(|_| format!("invalid keycode {}", value));
+
+        let action = match legacy.result_type.as_str() {
+            "keyboard" => BindingAction::Keyboard {
+                key: keycode_value()?,
+            },
+            "socd" => BindingAction::Socd {
+                opposite: keycode_value()?,
+                mode: SocdMode::LastInput,
+            },
+            "face_button" => u16::try_from(value)
+                .ok()
+                .and_then(GamepadButton::from_xinput_mask)
+                .map(|button| BindingAction::GamepadButton { button })
+                .ok_or_else(|| format!("invalid controller button code {}", value))?,
+            "thumb_lx" => stick_value(StickAxis::LeftX)?,
+            "thumb_ly" => stick_value(StickAxis::LeftY)?,
+            "thumb_rx" => stick_value(StickAxis::RightX)?,
+            "thumb_ry" => stick_value(StickAxis::RightY)?,
+            "trigger_l" => trigger_value(TriggerSide::Left)?,
+            "trigger_r" => trigger_value(TriggerSide::Right)?,
+            other => return Err(format!("unknown result type {:?}", other)),
+        };
+
+        Ok(Binding {
+            keycode,
+            chord: Vec::new(),
+            device: None,
+            action,
+        })
+    }
+}
+
+fn migrate_legacy_config(entries: Vec<LegacyKeyConfig>) -> Result<BindingConfig, String> {
+    let bindings = entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            Binding::try_from(entry).map_err(|e| format!("Binding {}: {}", index + 1, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(BindingConfig {
+        version: CONFIG_VERSION,
+        bindings,
+        ..Default::default()
+    })
+}
+
+// Reads both the current format and the legacy list so existing config files keep working
+pub(crate) fn parse_config(contents: &str) -> Result<BindingConfig, String> {
+    let value: Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+
+    if value.is_array() {
+        let entries: Vec<LegacyKeyConfig> =
+            serde_json::from_value(value).map_err(|e| e.to_string())?;
+        return migrate_legacy_config(entries);
+    }
+
+    let config: BindingConfig = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    if config.version > CONFIG_VERSION {
+        return Err(format!(
+            "Config version {} is newer than this version of OverBind supports ({})",
+            config.version, CONFIG_VERSION
+        ));
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The config shipped before typed bindings, keycodes are hex and result values decimal
+    const LEGACY_CONFIG: &str = r#"[
+        { "keycode": "51", "result_type": "thumb_lx", "result_value": -29000 },
+        { "keycode": "58", "result_type": "thumb_ry", "result_value": 29000 },
+        { "keycode": "27", "result_type": "socd", "result_value": 37 },
+        { "keycode": "25", "result_type": "socd", "result_value": 39 },
+        { "keycode": "46", "result_type": "keyboard", "result_value": 27 },
+        { "keycode": "20", "result_type": "face_button", "result_value": 4096 },
+        { "keycode": "A0", "result_type": "trigger_r", "result_value": 255 }
+    ]"#;
+
+    fn binding(keycode: u32, action: BindingAction) -> Binding {
+        Binding {
+            keycode,
+            chord: Vec::new(),
+            device: None,
+            action,
+        }
+    }
+
+    fn legacy_entry(keycode: &str, result_type: &str, result_value: i32) -> String {
+        format!(
+            r#"[{{ "keycode": {:?}, "result_type": {:?}, "result_value": {} }}]"#,
+            keycode, result_type, result_value
+        )
+    }
+
+    #[test]
+    fn legacy_config_is_migrated() {
+        let config = parse_config(LEGACY_CONFIG).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(
+            config.bindings,
+            vec![
+                binding(
+                    0x51,
+                    BindingAction::Axis {
+                        axis: StickAxis::LeftX,
+                        value: -29000,
+                        ramp: None,
+                    },
+                ),
+                binding(
+                    0x58,
+                    BindingAction::Axis {
+                        axis: StickAxis::RightY,
+                        value: 29000,
+                        ramp: None,
+                    },
+                ),
+                binding(
+                    0x27,
+                    BindingAction::Socd {
+                        opposite: 0x25,
+                        mode: SocdMode::LastInput,
+                    },
+                ),
+                binding(
+                    0x25,
+                    BindingAction::Socd {
+                        opposite: 0x27,
+                        mode: SocdMode::LastInput,
+                    },
+                ),
+                binding(0x46, BindingAction::Keyboard { key: 27 }),
+                binding(
+                    0x20,
+                    BindingAction::GamepadButton {
+                        button: GamepadButton::A,
+                    },
+                ),
+                binding(
+                    0xA0,
+                    BindingAction::Trigger {
+                        side: TriggerSide::Right,
+                        value: 255,
+                    },
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_legacy_entries_are_errors() {
+        for (keycode, result_type, result_value) in [
+            ("4G", "keyboard", 27),
+            ("", "keyboard", 27),
+            ("41", "mouse", 1),
+            ("41", "face_button", 3),
+            ("41", "thumb_lx", 40000),
+            ("41", "trigger_l", 256),
+            ("41", "keyboard", -1),
+        ] {
+            let contents = legacy_entry(keycode, result_type, result_value);
+            let error = parse_config(&contents).unwrap_err();
+            assert!(error.starts_with("Binding 1: "), "{}", error);
+        }
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        for contents in [
+            "",
+            "not json",
+            r#"[{ "keycode": "41" }]"#,
+            r#"{ "version": 2, "bindings": [{ "keycode": 65 }] }"#,
+            r#"{ "version": 2, "bindings": [{ "keycode": 65, "action": { "type": "warp" } }] }"#,
+        ] {
+            assert!(parse_config(contents).is_err(), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let contents = format!(r#"{{ "version": {}, "bindings": [] }}"#, CONFIG_VERSION + 1);
+        let error = parse_config(&contents).unwrap_err();
+        assert!(error.contains("newer"), "{}", error);
+    }
+
+    #[test]
+    fn shipped_config_parses() {
+        let config = parse_config(include_str!("../OverBind_conf.json")).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(!config.bindings.is_empty());
+    }
+
+    #[test]
+    fn current_format_round_trips() {
+        let mut config = BindingConfig {
+            bindings: vec![
+                Binding {
+                    chord: vec![0x10],
+                    device: Some("usb-Keypad-event-kbd".to_string()),
+                    ..binding(
+                        0x41,
+                        BindingAction::TapHold {
+                            tap: Box::new(BindingAction::Keyboard { key: 0x1B }),
+                            hold: Box::new(BindingAction::LayerHold {
+                                layer: "Aim".to_string(),
+                            }),
+                            timeout_ms: DEFAULT_TAP_TIMEOUT_MS,
+                        },
+                    )
+                },
+                binding(
+                    0x44,
+                    BindingAction::Axis {
+                        axis: StickAxis::LeftX,
+                        value: 29000,
+                        ramp: Some(Ramp {
+                            up_ms: 100,
+                            down_ms: 50,
+                            curve: RampCurve::Stepped(4),
+                        }),
+                    },
+                ),
+                binding(
+                    0x45,
+                    BindingAction::Macro {
+                        steps: vec![MacroStep {
+                            delay_ms: 20,
+                            action: BindingAction::Keyboard { key: 0x46 },
+                            is_down: true,
+                        }],
+                        mode: MacroMode::RepeatWhileHeld,
+                    },
+                ),
+            ],
+            layers: vec![Layer {
+                name: "Aim".to_string(),
+                bindings: vec![binding(0x57, BindingAction::StickScale { percent: 50 })],
+            }],
+            chord_window_ms: 80,
+            mouse_look: Some(MouseLook::default()),
+            ..Default::default()
+        };
+        config
+            .axis_socd
+            .insert(StickAxis::LeftX, SocdMode::Priority(0x44));
+        config
+            .stick_normalization
+            .insert(Stick::Left, StickNormalization::Diagonal(120));
+
+        let json = serde_json::to_string_pretty(&config).unwrap();
+        assert_eq!(parse_config(&json).unwrap(), config);
+    }
+}
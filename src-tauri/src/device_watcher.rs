@@ -0,0 +1,70 @@
+#![cfg(target_os = "linux")]
+
+use std::ffi::CString;
+use std::io;
+use std::time::Duration;
+
+// Where device nodes and their links show up, by-id and by-path only exist while some device
+// has a link there
+const WATCHED_DIRS: [&str; 3] = ["/dev/input", "/dev/input/by-id", "/dev/input/by-path"];
+
+// Wakes up waiting threads when input devices are added, so an unplugged device is picked up
+// as soon as it is back
+pub(crate) struct DeviceWatcher {
+    fd: i32,
+}
+
+impl DeviceWatcher {
+    pub(crate) fn new() -> Result<Self, String> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(format!(
+                "Failed to watch input devices: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        let watcher = DeviceWatcher { fd };
+        watcher.add_watches();
+        Ok(watcher)
+    }
+
+    // Watching a directory again only updates the watch, so this also picks up link
+    // directories that were created since
+    fn add_watches(&self) {
+        for dir in WATCHED_DIRS {
+            let path = CString::new(dir).unwrap();
+            unsafe {
+                libc::inotify_add_watch(
+                    self.fd,
+                    path.as_ptr(),
+                    libc::IN_CREATE | libc::IN_ATTRIB | libc::IN_MOVED_TO,
+                );
+            }
+        }
+    }
+
+    // Waits until something changed or `timeout` passed, what changed is left to the caller
+    pub(crate) fn wait(&self, timeout: Duration) {
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+        if unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) } <= 0 {
+            return;
+        }
+
+        let mut buffer = [0u8; 4096];
+        while unsafe { libc::read(self.fd, buffer.as_mut_ptr().cast(), buffer.len()) } > 0 {}
+        self.add_watches();
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
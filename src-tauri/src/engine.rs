@@ -0,0 +1,2992 @@
+use log::{debug, warn};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::bindings::{
+    Binding, BindingAction, BindingConfig, GamepadButton, MacroMode, MacroStep, MouseLook, Ramp,
+    RampCurve, SocdMode, Stick, StickAxis, StickNormalization, TriggerSide,
+};
+
+// How often the backends let the engine advance outputs that change with time
+pub(crate) const TICK_INTERVAL: Duration = Duration::from_millis(4);
+
+// Chords are bound to virtual keycodes above every backend's native keycodes
+const CHORD_CODE_BASE: u32 = 0x1_0000;
+
+// Taps are sent from a virtual key of their own, so the physical key is free to be pressed again
+const TAP_CODE_FLAG: u32 = 0x8000_0000;
+
+// How long a tap's output is held, long enough for games that poll the controller to notice it
+const TAP_DURATION: Duration = Duration::from_millis(30);
+
+// Turbo pulses are sent from a virtual key of their own as well
+const TURBO_CODE_FLAG: u32 = 0x4000_0000;
+
+// Every output a macro presses gets a virtual key numbered from here
+const MACRO_CODE_BASE: u32 = 0x2000_0000;
+
+// Keys of bindings limited to an input device get a keycode per device above the chord codes
+const DEVICE_CODE_BASE: u32 = 0x100_0000;
+const DEVICE_CODE_STRIDE: u32 = 0x1_0000;
+
+// The key a device scoped keycode stands for, other keycodes are returned as they are
+fn unscoped_code(code: u32) -> u32 {
+    if (DEVICE_CODE_BASE..MACRO_CODE_BASE).contains(&code) {
+        (code - DEVICE_CODE_BASE) % DEVICE_CODE_STRIDE
+    } else {
+        code
+    }
+}
+
+const STICK_AXES: [Axis; 4] = [Axis::LeftX, Axis::LeftY, Axis::RightX, Axis::RightY];
+
+// Sorted native keys of a chord binding, None if one of them is unknown
+fn chord_keys(binding: &Binding, to_native: impl Fn(u32) -> Option<u32>) -> Option<Vec<u32>> {
+    let mut keys = std::iter::once(binding.keycode)
+        .chain(binding.chord.iter().copied())
+        .map(to_native)
+        .collect::<Option<Vec<_>>>()?;
+    keys.sort_unstable();
+    keys.dedup();
+    Some(keys)
+}
+
+// The dpad is reported as a hat, negative values are up/left like evdev's ABS_HAT0X/ABS_HAT0Y
+fn dpad_hat(button: GamepadButton) -> Option<(Hat, i32)> {
+    match button {
+        GamepadButton::DPadUp => Some((Hat::Y, -1)),
+        GamepadButton::DPadDown => Some((Hat::Y, 1)),
+        GamepadButton::DPadLeft => Some((Hat::X, -1)),
+        GamepadButton::DPadRight => Some((Hat::X, 1)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Axis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Hat {
+    X,
+    Y,
+}
+
+impl From<StickAxis> for Axis {
+    fn from(axis: StickAxis) -> Self {
+        match axis {
+            StickAxis::LeftX => Axis::LeftX,
+            StickAxis::LeftY => Axis::LeftY,
+            StickAxis::RightX => Axis::RightX,
+            StickAxis::RightY => Axis::RightY,
+        }
+    }
+}
+
+impl From<TriggerSide> for Axis {
+    fn from(side: TriggerSide) -> Self {
+        match side {
+            TriggerSide::Left => Axis::LeftTrigger,
+            TriggerSide::Right => Axis::RightTrigger,
+        }
+    }
+}
+
+// Abstract outputs, each backend translates these into its own virtual devices.
+// Stick values use the XInput range and direction (positive y is up), triggers are 0-255.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputAction {
+    Keyboard {
+        code: u32,
+        is_down: bool,
+    },
+    Button {
+        button: GamepadButton,
+        is_down: bool,
+    },
+    Axis {
+        axis: Axis,
+        value: i32,
+    },
+    Hat {
+        hat: Hat,
+        value: i32,
+    },
+}
+
+impl OutputAction {
+    pub(crate) fn is_gamepad(&self) -> bool {
+        !matches!(self, OutputAction::Keyboard { .. })
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct EngineOutput {
+    pub actions: Vec<OutputAction>,
+    // Whether the backend should swallow the physical key event instead of passing it through
+    pub block_input: bool,
+}
+
+impl EngineOutput {
+    pub(crate) fn has_gamepad_actions(&self) -> bool {
+        self.actions.iter().any(|action| action.is_gamepad())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct GamepadState {
+    // XInput button mask without the dpad bits, see hat_x/hat_y for those
+    pub buttons: u16,
+    pub hat_x: i32,
+    pub hat_y: i32,
+    pub left_trigger: u8,
+    pub right_trigger: u8,
+    pub thumb_lx: i16,
+    pub thumb_ly: i16,
+    pub thumb_rx: i16,
+    pub thumb_ry: i16,
+}
+
+impl GamepadState {
+    pub(crate) fn xinput_buttons(&self) -> u16 {
+        let mut buttons = self.buttons;
+        match self.hat_x {
+            -1 => buttons |= GamepadButton::DPadLeft.xinput_mask(),
+            1 => buttons |= GamepadButton::DPadRight.xinput_mask(),
+            _ => (),
+        }
+        match self.hat_y {
+            -1 => buttons |= GamepadButton::DPadUp.xinput_mask(),
+            1 => buttons |= GamepadButton::DPadDown.xinput_mask(),
+            _ => (),
+        }
+        buttons
+    }
+
+    fn axis(&self, axis: Axis) -> i32 {
+        match axis {
+            Axis::LeftX => self.thumb_lx as i32,
+            Axis::LeftY => self.thumb_ly as i32,
+            Axis::RightX => self.thumb_rx as i32,
+            Axis::RightY => self.thumb_ry as i32,
+            Axis::LeftTrigger => self.left_trigger as i32,
+            Axis::RightTrigger => self.right_trigger as i32,
+        }
+    }
+
+    fn set_stick(&mut self, axis: Axis, value: i32) {
+        let value = clamp_stick(value);
+        match axis {
+            Axis::LeftX => self.thumb_lx = value,
+            Axis::LeftY => self.thumb_ly = value,
+            Axis::RightX => self.thumb_rx = value,
+            Axis::RightY => self.thumb_ry = value,
+            Axis::LeftTrigger | Axis::RightTrigger => (),
+        }
+    }
+
+    fn diff(&self, next: &GamepadState) -> Vec<OutputAction> {
+        let mut actions = Vec::new();
+
+        let changed_buttons = self.buttons ^ next.buttons;
+        for bit in 0..16 {
+            let mask = 1u16 << bit;
+            if changed_buttons & mask != 0 {
+                if let Some(button) = GamepadButton::from_xinput_mask(mask) {
+                    actions.push(OutputAction::Button {
+                        button,
+                        is_down: next.buttons & mask != 0,
+                    });
+                }
+            }
+        }
+
+        if self.hat_x != next.hat_x {
+            actions.push(OutputAction::Hat {
+                hat: Hat::X,
+                value: next.hat_x,
+            });
+        }
+        if self.hat_y != next.hat_y {
+            actions.push(OutputAction::Hat {
+                hat: Hat::Y,
+                value: next.hat_y,
+            });
+        }
+
+        for axis in [
+            Axis::LeftX,
+            Axis::LeftY,
+            Axis::RightX,
+            Axis::RightY,
+            Axis::LeftTrigger,
+            Axis::RightTrigger,
+        ] {
+            if self.axis(axis) != next.axis(axis) {
+                actions.push(OutputAction::Axis {
+                    axis,
+                    value: next.axis(axis),
+                });
+            }
+        }
+
+        actions
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyOutput {
+    Keyboard(u32),
+    Button(GamepadButton),
+    Axis(Axis, i32, Option<Ramp>),
+    StickScale(u16),
+    // Index into the engine's layers
+    LayerHold(usize),
+    LayerToggle(usize),
+    // Index into the engine's tap/hold outputs
+    TapHold(usize),
+    // Index into the engine's turbo outputs
+    Turbo(usize),
+    // Index into the engine's macros
+    Macro(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MacroStepOutput {
+    delay: Duration,
+    // Virtual key the step's output is driven on, a press and a release of the same output share it
+    code: u32,
+    output: KeyOutput,
+    is_down: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MacroOutput {
+    steps: Vec<MacroStepOutput>,
+    mode: MacroMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MacroRun {
+    index: usize,
+    step: usize,
+    // When the next step is due, steps are timed from when the previous one was due so delays
+    // don't drift
+    next_at: Instant,
+    is_held: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TurboOutput {
+    output: KeyOutput,
+    period: Duration,
+    // Part of every period the output is on
+    on: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TapHoldOutput {
+    tap: KeyOutput,
+    hold: KeyOutput,
+    timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SocdPair {
+    opposite: u32,
+    // Priority keys are translated to native keycodes like everything else
+    mode: SocdMode,
+}
+
+// Platform independent remapping state. Keycodes are native to the backend that built the engine,
+// the config's windows virtual keycodes are translated once when the bindings are loaded.
+#[derive(Default)]
+pub(crate) struct Engine {
+    // The base layer comes first, layers are looked up by their position in the config
+    layers: Vec<HashMap<u32, KeyOutput>>,
+    layer_names: Vec<String>,
+    // Layers that are on besides the base, latest on top. Each entry is held by a key, or toggled
+    // on when there is no key.
+    layer_stack: Vec<(usize, Option<u32>)>,
+    // Output of each held key as it was when pressed, so layer changes don't affect its release
+    held_outputs: HashMap<u32, Option<KeyOutput>>,
+    // Virtual keycode of every chord's sorted keys, the layers bind chords by these
+    chord_codes: HashMap<Vec<u32>, u32>,
+    chord_window: Duration,
+    // Keys that may be the start of a chord, their own bindings wait until the window runs out
+    pending_chord: Vec<(u32, Instant)>,
+    // Chords that fired and are held, they are released with the first of their keys
+    held_chords: Vec<(u32, Vec<u32>)>,
+    // Keys used up by a chord, their own bindings are suppressed until they are released
+    chord_consumed: HashSet<u32>,
+    tap_hold_outputs: Vec<TapHoldOutput>,
+    // Tap/hold keys that are held but haven't been decided yet, and when they were pressed
+    pending_tap_holds: HashMap<u32, (TapHoldOutput, Instant)>,
+    // Virtual tap keys and when their output is released
+    tap_releases: Vec<(u32, Instant)>,
+    turbo_outputs: Vec<TurboOutput>,
+    // Held turbo keys and when they were pressed, pulses are timed from the press
+    turbo_keys: HashMap<u32, (TurboOutput, Instant)>,
+    macros: Vec<MacroOutput>,
+    // Playing macros by the key that started them
+    macro_runs: HashMap<u32, MacroRun>,
+    // Keys that have no release of their own, like wheel ticks, and when they are released
+    momentary_releases: Vec<(u32, Instant)>,
+    mouse_look: Option<MouseLook>,
+    mouse_motion: MouseMotion,
+    socd_pairs: HashMap<u32, SocdPair>,
+    // Input devices that bindings are limited to, a device's keycodes are offset by its position
+    devices: Vec<String>,
+    // Held keys of each device that were pressed as that device's own keycode
+    device_keys: HashMap<(usize, u32), u32>,
+    // Stick axes without a mode resolve their keys by last input
+    axis_modes: HashMap<Axis, SocdMode>,
+    normalization: HashMap<Stick, StickNormalization>,
+    // Value each stick axis is heading to and the ramp of the key driving it, the output lags
+    // behind while the axis is ramping
+    stick_targets: HashMap<Axis, (i32, Option<Ramp>)>,
+    moving_axes: HashMap<Axis, AxisRamp>,
+    // Physically held keys and the order they were pressed in
+    pressed: HashMap<u32, u64>,
+    // Held keys whose output is currently applied, SOCD can release a key's output while it is held
+    active: HashSet<u32>,
+    press_counter: u64,
+    gamepad: GamepadState,
+    block_kb_on_controller: bool,
+}
+
+impl Engine {
+    pub(crate) fn from_config(
+        config: &BindingConfig,
+        to_native: impl Fn(u32) -> Option<u32>,
+        block_kb_on_controller: bool,
+    ) -> Self {
+        let mut engine = Engine {
+            block_kb_on_controller,
+            chord_window: Duration::from_millis(config.chord_window_ms as u64),
+            mouse_look: config.mouse_look,
+            ..Default::default()
+        };
+
+        let native_mode = |mode: SocdMode| match mode {
+            SocdMode::Priority(key) => match to_native(key) {
+                Some(key) => SocdMode::Priority(key),
+                None => {
+                    warn!("Unknown SOCD priority key {:?}, using last input", key);
+                    SocdMode::LastInput
+                }
+            },
+            mode => mode,
+        };
+
+        for (&axis, &mode) in &config.axis_socd {
+            engine.axis_modes.insert(axis.into(), native_mode(mode));
+        }
+        engine.normalization = config.stick_normalization.clone();
+
+        for binding in config
+            .bindings
+            .iter()
+            .chain(config.layers.iter().flat_map(|layer| &layer.bindings))
+        {
+            if let Some(device) = &binding.device {
+                if !engine.devices.contains(device) {
+                    engine.devices.push(device.clone());
+                }
+            }
+        }
+
+        // SOCD pairs are only read from the base layer, they pair physical keys on every layer
+        for binding in config
+            .bindings
+            .iter()
+            .filter(|binding| binding.chord.is_empty())
+        {
+            if let BindingAction::Socd { opposite, mode } = binding.action {
+                let scoped = |key| engine.device_code(binding.device.as_deref(), to_native(key)?);
+                if let (Some(keycode), Some(opposite)) = (scoped(binding.keycode), scoped(opposite))
+                {
+                    // The priority key is one of the pair, so it is scoped to the same device
+                    let mode = match native_mode(mode) {
+                        SocdMode::Priority(key) => engine
+                            .device_code(binding.device.as_deref(), key)
+                            .map_or(SocdMode::LastInput, SocdMode::Priority),
+                        mode => mode,
+                    };
+                    engine
+                        .socd_pairs
+                        .entry(keycode)
+                        .or_insert(SocdPair { opposite, mode });
+                    debug!(
+                        "Keycode: {:?}, OppositeKeycode: {:?}, Mode: {:?}",
+                        keycode, opposite, mode
+                    );
+                }
+            }
+        }
+
+        let all_bindings = config
+            .bindings
+            .iter()
+            .chain(config.layers.iter().flat_map(|layer| &layer.bindings));
+        for binding in all_bindings.filter(|binding| !binding.chord.is_empty()) {
+            if let Some(keys) = chord_keys(binding, &to_native) {
+                let next_code = CHORD_CODE_BASE + engine.chord_codes.len() as u32;
+                engine.chord_codes.entry(keys).or_insert(next_code);
+            }
+        }
+
+        engine.layer_names = std::iter::once(String::new())
+            .chain(config.layers.iter().map(|layer| layer.name.clone()))
+            .collect();
+        engine.layers = std::iter::once(&config.bindings)
+            .chain(config.layers.iter().map(|layer| &layer.bindings))
+            .map(|bindings| engine.layer_outputs(bindings, &to_native))
+            .collect();
+
+        engine
+    }
+
+    // Keycode a key of `device` is bound by, keys of unknown devices have none
+    fn device_code(&self, device: Option<&str>, code: u32) -> Option<u32> {
+        let Some(device) = device else {
+            return Some(code);
+        };
+        let slot = self.devices.iter().position(|name| name == device)?;
+        Some(DEVICE_CODE_BASE + slot as u32 * DEVICE_CODE_STRIDE + code)
+    }
+
+    fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layer_names
+            .iter()
+            .skip(1)
+            .position(|layer| layer == name)
+            .map(|index| index + 1)
+    }
+
+    fn layer_outputs(
+        &mut self,
+        bindings: &[Binding],
+        to_native: impl Fn(u32) -> Option<u32>,
+    ) -> HashMap<u32, KeyOutput> {
+        let mut outputs = HashMap::new();
+
+        for binding in bindings {
+            let keycode = if binding.chord.is_empty() {
+                to_native(binding.keycode)
+                    .and_then(|keycode| self.device_code(binding.device.as_deref(), keycode))
+            } else {
+                chord_keys(binding, &to_native)
+                    .and_then(|keys| self.chord_codes.get(&keys).copied())
+            };
+            let keycode = match keycode {
+                Some(keycode) => keycode,
+                None => {
+                    warn!(
+                        "Skipping binding with unknown keycode: {:?} {:?}",
+                        binding.keycode, binding.chord
+                    );
+                    continue;
+                }
+            };
+
+            let output = match binding.action {
+                BindingAction::Socd { .. } => continue,
+                BindingAction::TapHold {
+                    ref tap,
+                    ref hold,
+                    timeout_ms,
+                } => self
+                    .action_output(tap, &to_native)
+                    .zip(self.action_output(hold, &to_native))
+                    .map(|(tap, hold)| {
+                        self.tap_hold_outputs.push(TapHoldOutput {
+                            tap,
+                            hold,
+                            timeout: Duration::from_millis(timeout_ms as u64),
+                        });
+                        KeyOutput::TapHold(self.tap_hold_outputs.len() - 1)
+                    }),
+                BindingAction::Turbo {
+                    ref action,
+                    rate_hz,
+                    duty_percent,
+                } if rate_hz > 0 => self.action_output(action, &to_native).map(|output| {
+                    let period = Duration::from_secs(1) / rate_hz as u32;
+                    self.turbo_outputs.push(TurboOutput {
+                        output,
+                        period,
+                        on: period * cmp::min(duty_percent, 100) as u32 / 100,
+                    });
+                    KeyOutput::Turbo(self.turbo_outputs.len() - 1)
+                }),
+                BindingAction::Macro { ref steps, mode } => {
+                    self.macro_output(steps, mode, &to_native)
+                }
+                ref action => self.action_output(action, &to_native),
+            };
+
+            match output {
+                Some(output) => {
+                    let output = *outputs.entry(keycode).or_insert(output);
+                    debug!("Keycode: {:?}, Output: {:?}", keycode, output);
+                }
+                None => warn!(
+                    "Skipping binding for keycode {:?} with unknown output: {:?}",
+                    binding.keycode, binding.action
+                ),
+            }
+        }
+
+        outputs
+    }
+
+    // Output of a single action, tap/hold, turbo and SOCD bindings can't be used inside a tap/hold
+    // or turbo binding
+    fn action_output(
+        &self,
+        action: &BindingAction,
+        to_native: impl Fn(u32) -> Option<u32>,
+    ) -> Option<KeyOutput> {
+        match *action {
+            BindingAction::Keyboard { key } => to_native(key).map(KeyOutput::Keyboard),
+            BindingAction::GamepadButton { button } => Some(KeyOutput::Button(button)),
+            BindingAction::Axis { axis, value, ramp } => {
+                Some(KeyOutput::Axis(axis.into(), value as i32, ramp))
+            }
+            BindingAction::Trigger { side, value } => {
+                Some(KeyOutput::Axis(side.into(), value as i32, None))
+            }
+            BindingAction::StickScale { percent } => Some(KeyOutput::StickScale(percent)),
+            BindingAction::LayerHold { ref layer } => {
+                self.layer_index(layer).map(KeyOutput::LayerHold)
+            }
+            BindingAction::LayerToggle { ref layer } => {
+                self.layer_index(layer).map(KeyOutput::LayerToggle)
+            }
+            BindingAction::TapHold { .. }
+            | BindingAction::Turbo { .. }
+            | BindingAction::Macro { .. }
+            | BindingAction::Socd { .. } => None,
+        }
+    }
+
+    fn macro_output(
+        &mut self,
+        steps: &[MacroStep],
+        mode: MacroMode,
+        to_native: impl Fn(u32) -> Option<u32>,
+    ) -> Option<KeyOutput> {
+        let first_code = MACRO_CODE_BASE
+            + self
+                .macros
+                .iter()
+                .flat_map(|macro_output| &macro_output.steps)
+                .map(|step| step.code - MACRO_CODE_BASE + 1)
+                .max()
+                .unwrap_or(0);
+        let mut outputs: Vec<KeyOutput> = Vec::new();
+
+        let mut step_outputs = Vec::new();
+        for step in steps {
+            let output = self.action_output(&step.action, &to_native)?;
+            let slot = match outputs.iter().position(|&known| known == output) {
+                Some(slot) => slot,
+                None => {
+                    outputs.push(output);
+                    outputs.len() - 1
+                }
+            };
+            step_outputs.push(MacroStepOutput {
+                delay: Duration::from_millis(step.delay_ms as u64),
+                code: first_code + slot as u32,
+                output,
+                is_down: step.is_down,
+            });
+        }
+
+        self.macros.push(MacroOutput {
+            steps: step_outputs,
+            mode,
+        });
+        Some(KeyOutput::Macro(self.macros.len() - 1))
+    }
+
+    pub(crate) fn gamepad(&self) -> &GamepadState {
+        &self.gamepad
+    }
+
+    pub(crate) fn handle_key(&mut self, code: u32, is_down: bool) -> EngineOutput {
+        self.handle_key_at(code, is_down, Instant::now())
+    }
+
+    // Key of a specific input device, bindings limited to that device take over from the others
+    pub(crate) fn handle_device_key(
+        &mut self,
+        device: &str,
+        code: u32,
+        is_down: bool,
+    ) -> EngineOutput {
+        let code = self.device_key(device, code, is_down);
+        self.handle_key(code, is_down)
+    }
+
+    // The keycode the engine knows a device's key by. It is decided on press and kept until the
+    // release, so a key pressed unscoped is released unscoped.
+    fn device_key(&mut self, device: &str, code: u32, is_down: bool) -> u32 {
+        let Some(slot) = self.devices.iter().position(|name| name == device) else {
+            return code;
+        };
+        if !is_down {
+            return self.device_keys.remove(&(slot, code)).unwrap_or(code);
+        }
+        if let Some(&scoped) = self.device_keys.get(&(slot, code)) {
+            return scoped;
+        }
+        let Some(scoped) = self.device_code(Some(device), code) else {
+            return code;
+        };
+        if self.socd_pairs.contains_key(&scoped) || self.resolve_output(scoped).is_some() {
+            self.device_keys.insert((slot, code), scoped);
+            return scoped;
+        }
+        code
+    }
+
+    pub(crate) fn has_mouse_look(&self) -> bool {
+        self.mouse_look.is_some()
+    }
+
+    // Adds mouse movement of one report to the right stick, y is positive downwards like evdev's
+    pub(crate) fn move_mouse(&mut self, x: i32, y: i32) -> Vec<OutputAction> {
+        self.move_mouse_at(x, y, Instant::now())
+    }
+
+    fn move_mouse_at(&mut self, x: i32, y: i32, now: Instant) -> Vec<OutputAction> {
+        if self.mouse_look.is_none() {
+            return Vec::new();
+        }
+        self.mouse_motion.pending.0 += x;
+        self.mouse_motion.pending.1 += y;
+        self.update_mouse_look(now);
+        self.update_gamepad(now)
+    }
+
+    fn update_mouse_look(&mut self, now: Instant) {
+        match self.mouse_look {
+            Some(mouse_look) => self.mouse_motion.update(mouse_look, now),
+            None => self.mouse_motion = MouseMotion::default(),
+        }
+    }
+
+    // Presses a key that only reports presses, it is released again after a tap
+    pub(crate) fn handle_momentary_key(&mut self, code: u32) -> EngineOutput {
+        self.handle_momentary_key_at(code, Instant::now())
+    }
+
+    fn handle_momentary_key_at(&mut self, code: u32, now: Instant) -> EngineOutput {
+        let mut actions = Vec::new();
+
+        // Every press counts on its own, one that comes before the last was released ends it
+        if self.momentary_releases.iter().any(|&(key, _)| key == code) {
+            self.momentary_releases.retain(|&(key, _)| key != code);
+            actions.append(&mut self.handle_key_at(code, false, now).actions);
+        }
+
+        let mut output = self.handle_key_at(code, true, now);
+        self.momentary_releases.push((code, now + TAP_DURATION));
+        actions.append(&mut output.actions);
+        output.actions = actions;
+        output
+    }
+
+    fn handle_key_at(&mut self, code: u32, is_down: bool, now: Instant) -> EngineOutput {
+        let mut actions = self.expire(now);
+
+        // Keys used up by a chord only release it
+        if self.chord_consumed.contains(&code) {
+            if !is_down {
+                self.chord_consumed.remove(&code);
+                actions.extend(self.release_chords(code, now));
+            }
+            return EngineOutput {
+                actions,
+                block_input: true,
+            };
+        }
+
+        let is_pending = self.pending_chord.iter().any(|&(key, _)| key == code);
+        if is_down && !self.pressed.contains_key(&code) {
+            if is_pending {
+                return EngineOutput {
+                    actions,
+                    block_input: true,
+                };
+            }
+
+            let mut keys: Vec<u32> = self.pending_chord.iter().map(|&(key, _)| key).collect();
+            keys.push(code);
+            if self.chord_candidate(&keys).is_none() {
+                // The held back keys can't make a chord with this one, they get their own bindings
+                actions.extend(self.flush_chord(now));
+                keys = vec![code];
+            }
+
+            match self.chord_candidate(&keys) {
+                Some(Some(chord_code)) => {
+                    self.pending_chord.clear();
+                    self.chord_consumed.extend(keys.iter().copied());
+                    self.held_chords.push((chord_code, keys));
+                    let mut output = self.apply_key(chord_code, true, now);
+                    actions.append(&mut output.actions);
+                    return EngineOutput {
+                        actions,
+                        block_input: true,
+                    };
+                }
+                Some(None) => {
+                    self.pending_chord.push((code, now));
+                    return EngineOutput {
+                        actions,
+                        block_input: true,
+                    };
+                }
+                None => (),
+            }
+        } else if !is_down && is_pending {
+            // Released before the chord was complete, the press is sent late
+            actions.extend(self.flush_chord(now));
+        }
+
+        let mut output = self.apply_key(code, is_down, now);
+        actions.append(&mut output.actions);
+        output.actions = actions;
+        output
+    }
+
+    // Whether `keys` can still become a chord, and that chord's keycode if they already are one.
+    // A chord whose other keys are already held on their own can't be completed anymore.
+    fn chord_candidate(&self, keys: &[u32]) -> Option<Option<u32>> {
+        let mut candidate = None;
+        for (chord_keys, &chord_code) in &self.chord_codes {
+            if self.resolve_output(chord_code).is_none()
+                || !keys.iter().all(|key| chord_keys.contains(key))
+            {
+                continue;
+            }
+            if chord_keys.len() == keys.len() {
+                return Some(Some(chord_code));
+            }
+            if chord_keys
+                .iter()
+                .all(|key| keys.contains(key) || !self.pressed.contains_key(key))
+            {
+                candidate = Some(None);
+            }
+        }
+        candidate
+    }
+
+    // Applies the held back presses, keys without a binding are sent as themselves since their
+    // physical presses were swallowed
+    fn flush_chord(&mut self, now: Instant) -> Vec<OutputAction> {
+        let mut actions = Vec::new();
+        for (code, _) in std::mem::take(&mut self.pending_chord) {
+            let mut output = self.apply_key(code, true, now);
+            actions.append(&mut output.actions);
+            if !output.block_input {
+                actions.push(OutputAction::Keyboard {
+                    code,
+                    is_down: true,
+                });
+            }
+        }
+        actions
+    }
+
+    // Presses and holds a tap's output on the key's virtual tap key, `expire` releases it
+    fn tap(&mut self, code: u32, tap: KeyOutput, now: Instant) -> Vec<OutputAction> {
+        let tap_code = code | TAP_CODE_FLAG;
+        let mut actions = self.release_tap(tap_code, now);
+        actions.extend(self.press_virtual_key(tap_code, tap, now));
+        self.tap_releases.push((tap_code, now + TAP_DURATION));
+        actions
+    }
+
+    fn release_tap(&mut self, tap_code: u32, now: Instant) -> Vec<OutputAction> {
+        self.tap_releases.retain(|&(code, _)| code != tap_code);
+        self.release_virtual_key(tap_code, now)
+    }
+
+    // Plays every macro step that is due, in the order the macros were started
+    fn play_macros(&mut self, now: Instant) -> Vec<OutputAction> {
+        let mut actions = Vec::new();
+
+        let mut codes: Vec<u32> = self.macro_runs.keys().copied().collect();
+        codes.sort_by_key(|code| self.pressed.get(code).copied());
+        for code in codes {
+            let mut has_restarted = false;
+            while let Some(mut run) = self.macro_runs.get(&code).copied() {
+                let steps_len = self.macros[run.index].steps.len();
+                if run.step == steps_len {
+                    // A macro without delays would otherwise repeat forever within one call
+                    if self.macros[run.index].mode == MacroMode::RepeatWhileHeld
+                        && run.is_held
+                        && !has_restarted
+                        && steps_len > 0
+                    {
+                        has_restarted = true;
+                        run.step = 0;
+                        run.next_at += self.macros[run.index].steps[0].delay;
+                        self.macro_runs.insert(code, run);
+                        continue;
+                    }
+                    actions.extend(self.stop_macro(code, now));
+                    break;
+                }
+                if now < run.next_at {
+                    break;
+                }
+
+                let step = self.macros[run.index].steps[run.step];
+                if step.is_down {
+                    actions.extend(self.press_virtual_key(step.code, step.output, now));
+                } else {
+                    actions.extend(self.release_virtual_key(step.code, now));
+                }
+                run.step += 1;
+                if let Some(next) = self.macros[run.index].steps.get(run.step) {
+                    run.next_at += next.delay;
+                }
+                self.macro_runs.insert(code, run);
+            }
+        }
+
+        actions
+    }
+
+    // Ends a macro and releases whatever it still holds
+    fn stop_macro(&mut self, code: u32, now: Instant) -> Vec<OutputAction> {
+        let mut actions = Vec::new();
+        if let Some(run) = self.macro_runs.remove(&code) {
+            let mut step_codes: Vec<u32> = self.macros[run.index]
+                .steps
+                .iter()
+                .map(|step| step.code)
+                .collect();
+            step_codes.sort_unstable();
+            step_codes.dedup();
+            for step_code in step_codes {
+                actions.extend(self.release_virtual_key(step_code, now));
+            }
+        }
+        actions
+    }
+
+    // Keys the engine presses on its own, like taps and turbo pulses
+    fn press_virtual_key(
+        &mut self,
+        code: u32,
+        output: KeyOutput,
+        now: Instant,
+    ) -> Vec<OutputAction> {
+        if self.pressed.contains_key(&code) {
+            return Vec::new();
+        }
+        self.press_counter += 1;
+        self.pressed.insert(code, self.press_counter);
+        self.held_outputs.insert(code, Some(output));
+        self.drive_key(code, true, false, Some(output), now).actions
+    }
+
+    fn release_virtual_key(&mut self, code: u32, now: Instant) -> Vec<OutputAction> {
+        if self.pressed.remove(&code).is_none() {
+            return Vec::new();
+        }
+        let output = self.held_outputs.remove(&code).flatten();
+        self.drive_key(code, false, false, output, now).actions
+    }
+
+    // Applies everything that was waiting for time to pass: chord windows, tap/hold timeouts, the
+    // end of taps, turbo pulses and macro steps
+    fn expire(&mut self, now: Instant) -> Vec<OutputAction> {
+        let mut actions = self.flush_expired_chord(now);
+
+        let mut held: Vec<(u32, KeyOutput)> = self
+            .pending_tap_holds
+            .iter()
+            .filter(|(_, (tap_hold, pressed_at))| {
+                now.duration_since(*pressed_at) >= tap_hold.timeout
+            })
+            .map(|(&code, (tap_hold, _))| (code, tap_hold.hold))
+            .collect();
+        held.sort_by_key(|&(code, _)| self.pressed.get(&code).copied());
+        for (code, hold) in held {
+            self.pending_tap_holds.remove(&code);
+            self.held_outputs.insert(code, Some(hold));
+            actions.append(&mut self.drive_key(code, true, false, Some(hold), now).actions);
+        }
+
+        let ended: Vec<u32> = self
+            .tap_releases
+            .iter()
+            .filter(|&&(_, release_at)| now >= release_at)
+            .map(|&(tap_code, _)| tap_code)
+            .collect();
+        for tap_code in ended {
+            actions.extend(self.release_tap(tap_code, now));
+        }
+
+        let mut pulses: Vec<(u32, TurboOutput, bool)> = self
+            .turbo_keys
+            .iter()
+            .map(|(&code, &(turbo, pressed_at))| {
+                let phase = now.duration_since(pressed_at).as_nanos() % turbo.period.as_nanos();
+                (code, turbo, phase < turbo.on.as_nanos())
+            })
+            .collect();
+        pulses.sort_by_key(|&(code, _, _)| self.pressed.get(&code).copied());
+        for (code, turbo, is_on) in pulses {
+            let turbo_code = code | TURBO_CODE_FLAG;
+            if is_on {
+                actions.extend(self.press_virtual_key(turbo_code, turbo.output, now));
+            } else {
+                actions.extend(self.release_virtual_key(turbo_code, now));
+            }
+        }
+
+        actions.extend(self.play_macros(now));
+
+        let (released, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.momentary_releases)
+            .into_iter()
+            .partition(|&(_, release_at)| now >= release_at);
+        self.momentary_releases = held;
+        for (code, _) in released {
+            actions.append(&mut self.handle_key_at(code, false, now).actions);
+        }
+
+        actions
+    }
+
+    fn flush_expired_chord(&mut self, now: Instant) -> Vec<OutputAction> {
+        match self.pending_chord.first() {
+            Some(&(_, pressed_at)) if now.duration_since(pressed_at) >= self.chord_window => {
+                self.flush_chord(now)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn release_chords(&mut self, code: u32, now: Instant) -> Vec<OutputAction> {
+        let mut actions = Vec::new();
+        let (released, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held_chords)
+            .into_iter()
+            .partition(|(_, keys)| keys.contains(&code));
+        self.held_chords = held;
+        for (chord_code, _) in released {
+            actions.append(&mut self.apply_key(chord_code, false, now).actions);
+        }
+        actions
+    }
+
+    fn apply_key(&mut self, code: u32, is_down: bool, now: Instant) -> EngineOutput {
+        let mut output = EngineOutput::default();
+
+        let is_repeat = is_down && self.pressed.contains_key(&code);
+        if is_down {
+            if !is_repeat {
+                self.press_counter += 1;
+                self.pressed.insert(code, self.press_counter);
+                self.held_outputs.insert(code, self.resolve_output(code));
+            }
+        } else {
+            self.pressed.remove(&code);
+        }
+
+        let key_output = self.output(code);
+        if !is_down {
+            self.held_outputs.remove(&code);
+        }
+
+        // Tap/hold keys have no output until they are released or held long enough
+        if let Some(KeyOutput::TapHold(index)) = key_output {
+            if is_down && !is_repeat {
+                let tap_hold = self.tap_hold_outputs[index];
+                self.pending_tap_holds.insert(code, (tap_hold, now));
+            } else if let Some((tap_hold, _)) = self.pending_tap_holds.remove(&code) {
+                output.actions = self.tap(code, tap_hold.tap, now);
+            }
+            output.block_input = true;
+            return output;
+        }
+
+        // Macros are played from `expire`, the key only starts and stops them
+        if let Some(KeyOutput::Macro(index)) = key_output {
+            if is_down && !is_repeat && !self.macro_runs.contains_key(&code) {
+                let first_delay = self.macros[index]
+                    .steps
+                    .first()
+                    .map_or(Duration::ZERO, |step| step.delay);
+                self.macro_runs.insert(
+                    code,
+                    MacroRun {
+                        index,
+                        step: 0,
+                        next_at: now + first_delay,
+                        is_held: true,
+                    },
+                );
+                output.actions = self.play_macros(now);
+            } else if !is_down {
+                if let Some(run) = self.macro_runs.get_mut(&code) {
+                    run.is_held = false;
+                    if self.macros[run.index].mode == MacroMode::CancelOnRelease {
+                        output.actions = self.stop_macro(code, now);
+                    }
+                }
+            }
+            output.block_input = true;
+            return output;
+        }
+
+        // Turbo keys pulse their output from `expire` while held
+        if let Some(KeyOutput::Turbo(index)) = key_output {
+            let turbo_code = code | TURBO_CODE_FLAG;
+            if is_down && !is_repeat {
+                let turbo = self.turbo_outputs[index];
+                self.turbo_keys.insert(code, (turbo, now));
+                output.actions = self.press_virtual_key(turbo_code, turbo.output, now);
+            } else if !is_down {
+                self.turbo_keys.remove(&code);
+                output.actions = self.release_virtual_key(turbo_code, now);
+            }
+            output.block_input = true;
+            return output;
+        }
+
+        self.drive_key(code, is_down, is_repeat, key_output, now)
+    }
+
+    // Sends the output a key has settled on
+    fn drive_key(
+        &mut self,
+        code: u32,
+        is_down: bool,
+        is_repeat: bool,
+        key_output: Option<KeyOutput>,
+        now: Instant,
+    ) -> EngineOutput {
+        let mut output = EngineOutput::default();
+
+        // Layer keys only change which bindings later presses use
+        if let Some(KeyOutput::LayerHold(layer) | KeyOutput::LayerToggle(layer)) = key_output {
+            if !is_repeat {
+                let is_toggle = matches!(key_output, Some(KeyOutput::LayerToggle(_)));
+                self.switch_layer(code, layer, is_toggle, is_down);
+            }
+            output.block_input = true;
+            return output;
+        }
+
+        let was_active = self.active.contains(&code);
+        match self.socd_pairs.get(&code).copied() {
+            Some(socd) => {
+                // Only the opposite key's output is sent from here, this key's is handled below
+                let opposite_was_active = self.active.contains(&socd.opposite);
+                let (is_active, opposite_is_active) = self.resolve_socd(code, socd);
+                self.set_active(code, is_active);
+                self.set_active(socd.opposite, opposite_is_active);
+
+                if opposite_is_active != opposite_was_active {
+                    if let Some(mapped) = self.keyboard_output(socd.opposite) {
+                        output.actions.push(OutputAction::Keyboard {
+                            code: mapped,
+                            is_down: opposite_is_active,
+                        });
+                    }
+                }
+            }
+            None => self.set_active(code, is_down),
+        }
+        let is_active = self.active.contains(&code);
+
+        // Presses that SOCD holds back and releases of outputs that were already released are
+        // swallowed, everything else goes out as the key's output
+        let sends_output = if is_down { is_active } else { was_active };
+        match key_output {
+            Some(KeyOutput::Keyboard(mapped)) => {
+                if sends_output {
+                    output.actions.push(OutputAction::Keyboard {
+                        code: mapped,
+                        is_down,
+                    });
+                }
+                output.block_input = true;
+            }
+            Some(_) => output.block_input = self.block_kb_on_controller,
+            None => output.block_input = !sends_output,
+        }
+
+        output.actions.extend(self.update_gamepad(now));
+        output
+    }
+
+    fn switch_layer(&mut self, code: u32, layer: usize, is_toggle: bool, is_down: bool) {
+        if is_toggle {
+            if is_down {
+                let toggled = self
+                    .layer_stack
+                    .iter()
+                    .position(|&entry| entry == (layer, None));
+                match toggled {
+                    Some(index) => {
+                        self.layer_stack.remove(index);
+                    }
+                    None => self.layer_stack.push((layer, None)),
+                }
+            }
+        } else if is_down {
+            self.layer_stack.push((layer, Some(code)));
+        } else {
+            self.layer_stack.retain(|&(_, key)| key != Some(code));
+        }
+        debug!("Layers: {:?}", self.layer_stack);
+    }
+
+    // Binding of a key on the topmost layer that has one
+    fn resolve_output(&self, code: u32) -> Option<KeyOutput> {
+        self.layer_stack
+            .iter()
+            .rev()
+            .map(|&(layer, _)| layer)
+            .chain(std::iter::once(0))
+            .find_map(|layer| self.layers.get(layer)?.get(&code).copied())
+    }
+
+    // Held keys keep the output they were pressed with
+    fn output(&self, code: u32) -> Option<KeyOutput> {
+        match self.held_outputs.get(&code) {
+            Some(output) => *output,
+            None => self.resolve_output(code),
+        }
+    }
+
+    // Whether something changes with time alone, a ramping stick axis, a chord window, a tap,
+    // a turbo key, a macro or a momentary key
+    pub(crate) fn needs_tick(&self) -> bool {
+        !self.moving_axes.is_empty()
+            || !self.pending_chord.is_empty()
+            || !self.pending_tap_holds.is_empty()
+            || !self.tap_releases.is_empty()
+            || !self.turbo_keys.is_empty()
+            || !self.macro_runs.is_empty()
+            || !self.momentary_releases.is_empty()
+            || self.mouse_motion.is_moving()
+    }
+
+    // How long the backends can wait before the next tick, shorter than the tick interval when a
+    // macro step is due sooner
+    pub(crate) fn next_tick_in(&self) -> Duration {
+        let now = Instant::now();
+        self.macro_runs
+            .values()
+            .map(|run| run.next_at.saturating_duration_since(now))
+            .fold(TICK_INTERVAL, cmp::min)
+    }
+
+    // Moves ramping stick axes to where they should be by now and applies whatever was waiting
+    // for a chord window, a tap/hold timeout, the end of a tap, a turbo pulse, a macro step or
+    // the release of a momentary key
+    pub(crate) fn tick(&mut self) -> Vec<OutputAction> {
+        self.tick_at(Instant::now())
+    }
+
+    fn tick_at(&mut self, now: Instant) -> Vec<OutputAction> {
+        let mut actions = self.expire(now);
+        self.update_mouse_look(now);
+        actions.extend(self.update_gamepad(now));
+        actions
+    }
+
+    // Releases every output the engine is currently holding, used when interception is paused
+    pub(crate) fn release_all(&mut self) -> Vec<OutputAction> {
+        let mut actions = Vec::new();
+
+        let active_keys: Vec<u32> = self.active.drain().collect();
+        for code in active_keys {
+            if let Some(mapped) = self.keyboard_output(code) {
+                actions.push(OutputAction::Keyboard {
+                    code: mapped,
+                    is_down: false,
+                });
+            }
+        }
+        self.pressed.clear();
+        self.held_outputs.clear();
+        self.pending_chord.clear();
+        self.held_chords.clear();
+        self.chord_consumed.clear();
+        self.pending_tap_holds.clear();
+        self.tap_releases.clear();
+        self.turbo_keys.clear();
+        self.macro_runs.clear();
+        self.momentary_releases.clear();
+        self.device_keys.clear();
+        self.mouse_motion = MouseMotion::default();
+        self.layer_stack.retain(|&(_, key)| key.is_none());
+        // Nothing is left to drive the output, so sticks return to center without ramping
+        self.stick_targets.clear();
+        self.moving_axes.clear();
+
+        actions.extend(self.update_gamepad(Instant::now()));
+        actions
+    }
+
+    // Which keys of a SOCD pair should be active given what is physically held
+    fn resolve_socd(&self, code: u32, socd: SocdPair) -> (bool, bool) {
+        let (order, opposite_order) =
+            match (self.pressed.get(&code), self.pressed.get(&socd.opposite)) {
+                (Some(order), Some(opposite_order)) => (order, opposite_order),
+                (order, opposite_order) => return (order.is_some(), opposite_order.is_some()),
+            };
+
+        let code_wins = match socd.mode {
+            SocdMode::LastInput => order > opposite_order,
+            SocdMode::FirstInput => order < opposite_order,
+            SocdMode::Neutral => return (false, false),
+            SocdMode::Priority(key) if key == code => true,
+            SocdMode::Priority(key) if key == socd.opposite => false,
+            // A priority key outside the pair can't be honoured, fall back to the default
+            SocdMode::Priority(_) => order > opposite_order,
+        };
+        (code_wins, !code_wins)
+    }
+
+    fn set_active(&mut self, code: u32, is_active: bool) {
+        if is_active {
+            self.active.insert(code);
+        } else {
+            self.active.remove(&code);
+        }
+    }
+
+    // Swaps in new bindings while keys may be held. Held keys whose binding didn't change keep
+    // their output, everything else the old bindings were holding is released. Toggled layers stay
+    // on if the new bindings still have them.
+    pub(crate) fn replace_bindings(&mut self, mut next: Engine) -> Vec<OutputAction> {
+        let mut actions = self.flush_chord(Instant::now());
+
+        // Chord keycodes aren't stable between bindings, held chords, taps, turbo keys and
+        // macros are released and undecided tap/hold keys are dropped. Keys used up by a chord
+        // stay used up until they are released, momentary keys are still released in time. Keys
+        // of a device keep their keycode as long as bindings are limited to the same devices.
+        let device_keys: Vec<u32> = if self.devices == next.devices {
+            next.device_keys = std::mem::take(&mut self.device_keys);
+            Vec::new()
+        } else {
+            self.device_keys.drain().map(|(_, code)| code).collect()
+        };
+        let virtual_keys: Vec<u32> = std::mem::take(&mut self.held_chords)
+            .into_iter()
+            .map(|(chord_code, _)| chord_code)
+            .chain(self.tap_releases.drain(..).map(|(tap_code, _)| tap_code))
+            .chain(self.pending_tap_holds.drain().map(|(code, _)| code))
+            .chain(
+                self.turbo_keys
+                    .drain()
+                    .flat_map(|(code, _)| [code, code | TURBO_CODE_FLAG]),
+            )
+            .chain(self.macro_runs.drain().flat_map(|(code, run)| {
+                std::iter::once(code)
+                    .chain(self.macros[run.index].steps.iter().map(|step| step.code))
+            }))
+            .chain(device_keys)
+            .collect();
+        for code in virtual_keys {
+            if self.active.remove(&code) {
+                if let Some(mapped) = self.keyboard_output(code) {
+                    actions.push(OutputAction::Keyboard {
+                        code: mapped,
+                        is_down: false,
+                    });
+                }
+            }
+            self.pressed.remove(&code);
+            self.held_outputs.remove(&code);
+            self.layer_stack.retain(|&(_, key)| key != Some(code));
+        }
+        next.chord_consumed = std::mem::take(&mut self.chord_consumed);
+        next.momentary_releases = std::mem::take(&mut self.momentary_releases);
+        next.mouse_motion = self.mouse_motion;
+
+        for &(layer, key) in &self.layer_stack {
+            if let (Some(next_layer), None) = (next.layer_index(&self.layer_names[layer]), key) {
+                next.layer_stack.push((next_layer, None));
+            }
+        }
+
+        for code in self.pressed.keys().copied().collect::<Vec<_>>() {
+            let next_output = next.resolve_output(code);
+            let is_unchanged = self.output(code) == next_output
+                && self.socd_pairs.get(&code) == next.socd_pairs.get(&code);
+            if is_unchanged {
+                next.held_outputs.insert(code, next_output);
+                if self.active.contains(&code) {
+                    next.active.insert(code);
+                }
+            } else if self.active.contains(&code) {
+                if let Some(mapped) = self.keyboard_output(code) {
+                    actions.push(OutputAction::Keyboard {
+                        code: mapped,
+                        is_down: false,
+                    });
+                }
+            }
+        }
+
+        // Held keys stay held so their release is still matched, the gamepad diff releases outputs
+        // that no active key drives anymore
+        next.pressed = std::mem::take(&mut self.pressed);
+        next.press_counter = self.press_counter;
+        next.gamepad = self.gamepad;
+        next.stick_targets = std::mem::take(&mut self.stick_targets);
+        next.moving_axes = std::mem::take(&mut self.moving_axes);
+        actions.extend(next.update_gamepad(Instant::now()));
+
+        *self = next;
+        actions
+    }
+
+    // Keyboard key driven by a key, unbound keys pass through as themselves
+    fn keyboard_output(&self, code: u32) -> Option<u32> {
+        match self.output(code) {
+            Some(KeyOutput::Keyboard(mapped)) => Some(mapped),
+            Some(_) => None,
+            None => Some(code),
+        }
+    }
+
+    fn update_gamepad(&mut self, now: Instant) -> Vec<OutputAction> {
+        let (mut next, drivers) = self.compute_gamepad();
+
+        for axis in STICK_AXES {
+            let target = next.axis(axis);
+            let driver = drivers.get(&axis).copied().flatten();
+            let (previous_target, previous_driver) =
+                self.stick_targets.get(&axis).copied().unwrap_or_default();
+
+            if target != previous_target {
+                let current = match self.moving_axes.get(&axis) {
+                    Some(ramp) => ramp.value_at(now),
+                    None => self.gamepad.axis(axis),
+                };
+                // Heading back towards center uses the ramp of the key that was driving the axis
+                let is_returning = target == 0
+                    || (target.signum() == current.signum() && target.abs() < current.abs());
+                let ramp = if is_returning {
+                    previous_driver
+                } else {
+                    driver
+                };
+                let duration_ms = ramp.map_or(0, |ramp| {
+                    if is_returning {
+                        ramp.down_ms
+                    } else {
+                        ramp.up_ms
+                    }
+                });
+
+                match ramp {
+                    Some(ramp) if duration_ms > 0 && current != target => {
+                        self.moving_axes.insert(
+                            axis,
+                            AxisRamp {
+                                from: current,
+                                to: target,
+                                start: now,
+                                duration: Duration::from_millis(duration_ms as u64),
+                                curve: ramp.curve,
+                            },
+                        );
+                    }
+                    _ => {
+                        self.moving_axes.remove(&axis);
+                    }
+                }
+            }
+            self.stick_targets.insert(axis, (target, driver));
+
+            if let Some(ramp) = self.moving_axes.get(&axis) {
+                if ramp.is_done(now) {
+                    self.moving_axes.remove(&axis);
+                } else {
+                    next.set_stick(axis, ramp.value_at(now));
+                }
+            }
+        }
+
+        // Mouse-look adds to whatever keys push the right stick
+        let (mouse_x, mouse_y) = self.mouse_motion.output;
+        next.set_stick(Axis::RightX, next.axis(Axis::RightX) + mouse_x);
+        next.set_stick(Axis::RightY, next.axis(Axis::RightY) + mouse_y);
+
+        for (stick, (x_axis, y_axis)) in [
+            (Stick::Left, (Axis::LeftX, Axis::LeftY)),
+            (Stick::Right, (Axis::RightX, Axis::RightY)),
+        ] {
+            let mode = self.normalization.get(&stick).copied().unwrap_or_default();
+            let (x, y) = normalize_stick(mode, next.axis(x_axis), next.axis(y_axis));
+            next.set_stick(x_axis, x);
+            next.set_stick(y_axis, y);
+        }
+
+        let actions = self.gamepad.diff(&next);
+        self.gamepad = next;
+        actions
+    }
+
+    // Rebuilds the whole controller state from the active keys: buttons are OR'd, triggers use
+    // the highest value, sticks resolve their keys by the axis' SOCD mode and each dpad axis
+    // follows the latest press. The latest held stick scale applies to every stick. Also returns
+    // the ramp of the key each stick axis follows.
+    fn compute_gamepad(&self) -> (GamepadState, HashMap<Axis, Option<Ramp>>) {
+        let mut state = GamepadState::default();
+        let mut latest_hat_x = 0;
+        let mut latest_hat_y = 0;
+        let mut stick_inputs: HashMap<Axis, Vec<AxisInput>> = HashMap::new();
+        let mut stick_scale = None;
+
+        for code in &self.active {
+            let order = self.pressed.get(code).copied().unwrap_or(0);
+            match self.output(*code) {
+                Some(KeyOutput::Button(button)) => match dpad_hat(button) {
+                    Some((Hat::X, value)) if order >= latest_hat_x => {
+                        latest_hat_x = order;
+                        state.hat_x = value;
+                    }
+                    Some((Hat::Y, value)) if order >= latest_hat_y => {
+                        latest_hat_y = order;
+                        state.hat_y = value;
+                    }
+                    Some(_) => (),
+                    None => state.buttons |= button.xinput_mask(),
+                },
+                Some(KeyOutput::Axis(axis, value, ramp)) => match axis {
+                    Axis::LeftTrigger => {
+                        state.left_trigger = cmp::max(state.left_trigger, clamp_trigger(value))
+                    }
+                    Axis::RightTrigger => {
+                        state.right_trigger = cmp::max(state.right_trigger, clamp_trigger(value))
+                    }
+                    _ => stick_inputs.entry(axis).or_default().push(AxisInput {
+                        order,
+                        code: *code,
+                        value,
+                        ramp,
+                    }),
+                },
+                Some(KeyOutput::StickScale(percent))
+                    if stick_scale.is_none_or(|(latest, _)| order >= latest) =>
+                {
+                    stick_scale = Some((order, percent as i32));
+                }
+                _ => (),
+            }
+        }
+
+        let percent = stick_scale.map_or(100, |(_, percent)| percent);
+        let mut drivers = HashMap::new();
+        for (axis, inputs) in stick_inputs {
+            let mode = self.axis_modes.get(&axis).copied().unwrap_or_default();
+            if let Some(input) = resolve_axis(mode, &inputs) {
+                state.set_stick(axis, input.value * percent / 100);
+                drivers.insert(axis, input.ramp);
+            }
+        }
+
+        (state, drivers)
+    }
+}
+
+fn clamp_trigger(value: i32) -> u8 {
+    value.clamp(0, u8::MAX as i32) as u8
+}
+
+fn clamp_stick(value: i32) -> i16 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+// An active key pushing a stick axis
+#[derive(Debug, Clone, Copy)]
+struct AxisInput {
+    order: u64,
+    code: u32,
+    value: i32,
+    ramp: Option<Ramp>,
+}
+
+// Picks the key that sets one stick axis from every active key bound to it. Keys bound with a value
+// of 0 never win, neutral mode only cancels keys pushing in opposite directions.
+fn resolve_axis(mode: SocdMode, inputs: &[AxisInput]) -> Option<AxisInput> {
+    let mut inputs = inputs
+        .iter()
+        .filter(|input| input.value != 0)
+        .collect::<Vec<_>>();
+    inputs.sort_by_key(|input| input.order);
+    let last_input = inputs.last();
+
+    let input = match mode {
+        SocdMode::LastInput => last_input,
+        SocdMode::FirstInput => inputs.first(),
+        SocdMode::Neutral => {
+            let is_opposed = inputs.iter().any(|input| input.value > 0)
+                && inputs.iter().any(|input| input.value < 0);
+            if is_opposed {
+                None
+            } else {
+                last_input
+            }
+        }
+        // The priority key wins on whichever device it is pressed
+        SocdMode::Priority(key) => inputs
+            .iter()
+            .find(|input| unscoped_code(input.code) == key)
+            .or(last_input),
+    };
+    input.map(|&&input| input)
+}
+
+// Applied to the values sent out, after SOCD, stick scales and ramps
+fn normalize_stick(mode: StickNormalization, x: i32, y: i32) -> (i32, i32) {
+    let length = (x as f64).hypot(y as f64);
+    let target_length = match mode {
+        StickNormalization::Circle if length > i16::MAX as f64 => i16::MAX as f64,
+        StickNormalization::Diagonal(percent) if x != 0 && y != 0 => {
+            cmp::max(x.abs(), y.abs()) as f64 * percent as f64 / 100.0
+        }
+        _ => return (x, y),
+    };
+
+    let scale = target_length / length;
+    (
+        (x as f64 * scale).round() as i32,
+        (y as f64 * scale).round() as i32,
+    )
+}
+
+// Exponential curves follow e^(rate * t), higher rates start slower
+const EXPONENTIAL_RATE: f64 = 4.0;
+
+#[derive(Debug, Clone, Copy)]
+struct AxisRamp {
+    from: i32,
+    to: i32,
+    start: Instant,
+    duration: Duration,
+    curve: RampCurve,
+}
+
+impl AxisRamp {
+    fn is_done(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= self.duration
+    }
+
+    fn value_at(&self, now: Instant) -> i32 {
+        let elapsed = now.saturating_duration_since(self.start).as_secs_f64();
+        let t = (elapsed / self.duration.as_secs_f64()).min(1.0);
+        let progress = match self.curve {
+            RampCurve::Linear => t,
+            RampCurve::Exponential => (EXPONENTIAL_RATE * t).exp_m1() / EXPONENTIAL_RATE.exp_m1(),
+            RampCurve::Stepped(steps) => {
+                let steps = steps.max(1) as f64;
+                (t * steps).floor() / steps
+            }
+        };
+        self.from + ((self.to - self.from) as f64 * progress).round() as i32
+    }
+}
+
+// Mouse reports come at least this often while the mouse moves, movement that arrives later
+// belongs to a new motion
+const MOUSE_REPORT_GAP: Duration = Duration::from_millis(16);
+
+// Stick values below this are centered, otherwise the smoothed speed would never quite reach zero
+// and deadzone compensation would keep the stick off center
+const MOUSE_LOOK_REST: f64 = i16::MAX as f64 / 100.0;
+
+// Right stick output of mouse-look, the speed is an exponential moving average of the movement
+#[derive(Debug, Clone, Copy, Default)]
+struct MouseMotion {
+    // Movement since the last update, in mouse counts
+    pending: (i32, i32),
+    // Smoothed speed in counts per millisecond, y is positive upwards like the stick
+    speed: (f64, f64),
+    output: (i32, i32),
+    updated_at: Option<Instant>,
+}
+
+impl MouseMotion {
+    fn is_moving(&self) -> bool {
+        self.output != (0, 0) || self.pending != (0, 0)
+    }
+
+    fn update(&mut self, settings: MouseLook, now: Instant) {
+        let elapsed = match self.updated_at {
+            Some(updated_at) => now.saturating_duration_since(updated_at),
+            None => MOUSE_REPORT_GAP,
+        };
+        self.updated_at = Some(now);
+        if elapsed >= MOUSE_REPORT_GAP {
+            self.speed = (0.0, 0.0);
+        }
+
+        // Each report adds its counts spread over the smoothing time, the old speed fades over it
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        let smoothing_ms = settings.smoothing_ms.max(1) as f64;
+        let fade = (-elapsed_ms / smoothing_ms).exp();
+        let (x, y) = std::mem::take(&mut self.pending);
+        self.speed = (
+            self.speed.0 * fade + x as f64 / smoothing_ms,
+            self.speed.1 * fade - y as f64 / smoothing_ms,
+        );
+
+        let target = mouse_look_target(settings, self.speed);
+        let current = (self.output.0 as f64).hypot(self.output.1 as f64);
+        let target_length = (target.0 as f64).hypot(target.1 as f64);
+        // Slowing down can't bring the stick back faster than the decay time allows
+        let max_fall = if settings.decay_ms == 0 {
+            f64::INFINITY
+        } else {
+            i16::MAX as f64 * elapsed_ms / settings.decay_ms as f64
+        };
+        self.output = if target_length < current - max_fall {
+            let scale = (current - max_fall) / current;
+            (
+                (self.output.0 as f64 * scale).round() as i32,
+                (self.output.1 as f64 * scale).round() as i32,
+            )
+        } else {
+            target
+        };
+    }
+}
+
+// Stick position for a mouse speed, before the decay limit
+fn mouse_look_target(settings: MouseLook, speed: (f64, f64)) -> (i32, i32) {
+    let sensitivity = settings.sensitivity as f64;
+    let (x, y) = (speed.0 * sensitivity, speed.1 * sensitivity);
+    let length = x.hypot(y);
+    if length < MOUSE_LOOK_REST {
+        return (0, 0);
+    }
+
+    let max = i16::MAX as f64;
+    let deadzone = max * settings.deadzone_percent.min(100) as f64 / 100.0;
+    let target_length = deadzone + length.min(max) * (max - deadzone) / max;
+    let scale = target_length / length;
+    ((x * scale).round() as i32, (y * scale).round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::{Binding, Layer, MacroMode, MacroStep};
+    use std::collections::BTreeSet;
+
+    const LEFT: u32 = 0x41;
+    const RIGHT: u32 = 0x44;
+    const LEFT_OUTPUT: u32 = 0x4A;
+    const RIGHT_OUTPUT: u32 = 0x4C;
+
+    fn binding(keycode: u32, action: BindingAction) -> Binding {
+        Binding {
+            keycode,
+            chord: Vec::new(),
+            device: None,
+            action,
+        }
+    }
+
+    fn keyboard_binding(keycode: u32, key: u32) -> Binding {
+        binding(keycode, BindingAction::Keyboard { key })
+    }
+
+    fn button_binding(keycode: u32, button: GamepadButton) -> Binding {
+        binding(keycode, BindingAction::GamepadButton { button })
+    }
+
+    fn engine_for(bindings: &[Binding]) -> Engine {
+        let config = BindingConfig {
+            bindings: bindings.to_vec(),
+            ..Default::default()
+        };
+        Engine::from_config(&config, Some, false)
+    }
+
+    fn socd_bindings(mode: SocdMode) -> Vec<Binding> {
+        vec![
+            binding(
+                LEFT,
+                BindingAction::Socd {
+                    opposite: RIGHT,
+                    mode,
+                },
+            ),
+            binding(
+                RIGHT,
+                BindingAction::Socd {
+                    opposite: LEFT,
+                    mode,
+                },
+            ),
+        ]
+    }
+
+    // Every order of pressing and releasing both keys, each key goes down before it comes up
+    fn orderings() -> Vec<[(u32, bool); 4]> {
+        let mut orderings = Vec::new();
+        for (first, second) in [(LEFT, RIGHT), (RIGHT, LEFT)] {
+            orderings.push([
+                (first, true),
+                (first, false),
+                (second, true),
+                (second, false),
+            ]);
+            orderings.push([
+                (first, true),
+                (second, true),
+                (first, false),
+                (second, false),
+            ]);
+            orderings.push([
+                (first, true),
+                (second, true),
+                (second, false),
+                (first, false),
+            ]);
+        }
+        orderings
+    }
+
+    // What should be held on the output for the physically held keys, given in press order
+    fn expected_keys(mode: SocdMode, held: &[u32]) -> BTreeSet<u32> {
+        match *held {
+            [] => BTreeSet::new(),
+            [key] => BTreeSet::from([key]),
+            [first, last] => match mode {
+                SocdMode::LastInput => BTreeSet::from([last]),
+                SocdMode::FirstInput => BTreeSet::from([first]),
+                SocdMode::Neutral => BTreeSet::new(),
+                SocdMode::Priority(key) => BTreeSet::from([key]),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    // Feeds an event through the engine like a backend would, the physical event is passed through
+    // unless blocked and the engine's keyboard actions are applied on top. Pressing a held key or
+    // releasing a key that isn't held on the output fails the test.
+    fn send(engine: &mut Engine, output_keys: &mut BTreeSet<u32>, code: u32, is_down: bool) {
+        let output = engine.handle_key(code, is_down);
+
+        let mut keyboard_events = Vec::new();
+        if !output.block_input {
+            keyboard_events.push((code, is_down));
+        }
+        for action in output.actions {
+            if let OutputAction::Keyboard { code, is_down } = action {
+                keyboard_events.push((code, is_down));
+            }
+        }
+
+        for (code, is_down) in keyboard_events {
+            if is_down {
+                assert!(output_keys.insert(code), "{:#X} pressed twice", code);
+            } else {
+                assert!(output_keys.remove(&code), "{:#X} released while up", code);
+            }
+        }
+    }
+
+    fn check_all_orderings(bindings: &[Binding], mode: SocdMode, to_output: impl Fn(u32) -> u32) {
+        for ordering in orderings() {
+            let mut engine = engine_for(bindings);
+            let mut output_keys = BTreeSet::new();
+            let mut held = Vec::new();
+
+            for (code, is_down) in ordering {
+                send(&mut engine, &mut output_keys, code, is_down);
+                if is_down {
+                    held.push(code);
+                } else {
+                    held.retain(|&key| key != code);
+                }
+
+                let expected = expected_keys(mode, &held)
+                    .into_iter()
+                    .map(&to_output)
+                    .collect::<BTreeSet<_>>();
+                assert_eq!(
+                    output_keys,
+                    expected,
+                    "{:?}: after {:#X} {} in {:X?}",
+                    mode,
+                    code,
+                    if is_down { "down" } else { "up" },
+                    ordering
+                );
+            }
+        }
+    }
+
+    fn check_mode(mode: SocdMode) {
+        check_all_orderings(&socd_bindings(mode), mode, |code| code);
+    }
+
+    #[test]
+    fn socd_last_input_wins() {
+        check_mode(SocdMode::LastInput);
+    }
+
+    #[test]
+    fn socd_first_input_wins() {
+        check_mode(SocdMode::FirstInput);
+    }
+
+    #[test]
+    fn socd_neutral_cancels_both() {
+        check_mode(SocdMode::Neutral);
+    }
+
+    #[test]
+    fn socd_absolute_priority() {
+        check_mode(SocdMode::Priority(LEFT));
+        check_mode(SocdMode::Priority(RIGHT));
+    }
+
+    #[test]
+    fn socd_with_remapped_keys() {
+        for mode in [
+            SocdMode::LastInput,
+            SocdMode::FirstInput,
+            SocdMode::Neutral,
+            SocdMode::Priority(RIGHT),
+        ] {
+            let mut bindings = socd_bindings(mode);
+            bindings.push(keyboard_binding(LEFT, LEFT_OUTPUT));
+            bindings.push(keyboard_binding(RIGHT, RIGHT_OUTPUT));
+
+            check_all_orderings(&bindings, mode, |code| match code {
+                LEFT => LEFT_OUTPUT,
+                _ => RIGHT_OUTPUT,
+            });
+        }
+    }
+
+    #[test]
+    fn socd_key_repeat_keeps_resolution() {
+        let mut engine = engine_for(&socd_bindings(SocdMode::LastInput));
+        let mut output_keys = BTreeSet::new();
+
+        send(&mut engine, &mut output_keys, LEFT, true);
+        send(&mut engine, &mut output_keys, RIGHT, true);
+
+        // Repeats of the winning key pass through, repeats of the held back key don't
+        let output = engine.handle_key(RIGHT, true);
+        assert!(!output.block_input && output.actions.is_empty());
+        let output = engine.handle_key(LEFT, true);
+        assert!(output.block_input && output.actions.is_empty());
+        assert_eq!(output_keys, BTreeSet::from([RIGHT]));
+
+        send(&mut engine, &mut output_keys, RIGHT, false);
+        assert_eq!(output_keys, BTreeSet::from([LEFT]));
+    }
+
+    #[test]
+    fn socd_on_gamepad_outputs() {
+        let mut bindings = socd_bindings(SocdMode::Neutral);
+        for (keycode, value) in [(LEFT, -29000), (RIGHT, 29000)] {
+            bindings.push(binding(
+                keycode,
+                BindingAction::Axis {
+                    axis: StickAxis::LeftX,
+                    value,
+                    ramp: None,
+                },
+            ));
+        }
+        let mut engine = engine_for(&bindings);
+
+        engine.handle_key(LEFT, true);
+        assert_eq!(engine.gamepad().thumb_lx, -29000);
+        engine.handle_key(RIGHT, true);
+        assert_eq!(engine.gamepad().thumb_lx, 0);
+        engine.handle_key(LEFT, false);
+        assert_eq!(engine.gamepad().thumb_lx, 29000);
+        engine.handle_key(RIGHT, false);
+        assert_eq!(engine.gamepad().thumb_lx, 0);
+    }
+
+    fn stick_engine(mode: SocdMode) -> Engine {
+        let mut config = BindingConfig::default();
+        for (keycode, value) in [(LEFT, -29000), (RIGHT, 29000)] {
+            config.bindings.push(binding(
+                keycode,
+                BindingAction::Axis {
+                    axis: StickAxis::LeftX,
+                    value,
+                    ramp: None,
+                },
+            ));
+        }
+        config.axis_socd.insert(StickAxis::LeftX, mode);
+        Engine::from_config(&config, Some, false)
+    }
+
+    #[test]
+    fn stick_axis_modes() {
+        for mode in [
+            SocdMode::LastInput,
+            SocdMode::FirstInput,
+            SocdMode::Neutral,
+            SocdMode::Priority(LEFT),
+            SocdMode::Priority(RIGHT),
+        ] {
+            for ordering in orderings() {
+                let mut engine = stick_engine(mode);
+                let mut held = Vec::new();
+
+                for (code, is_down) in ordering {
+                    engine.handle_key(code, is_down);
+                    if is_down {
+                        held.push(code);
+                    } else {
+                        held.retain(|&key| key != code);
+                    }
+
+                    let expected = match expected_keys(mode, &held).into_iter().next() {
+                        Some(LEFT) => -29000,
+                        Some(_) => 29000,
+                        None => 0,
+                    };
+                    assert_eq!(
+                        engine.gamepad().thumb_lx,
+                        expected,
+                        "{:?}: after {:#X} {} in {:X?}",
+                        mode,
+                        code,
+                        if is_down { "down" } else { "up" },
+                        ordering
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stick_axis_returns_to_held_key() {
+        let mut engine = stick_engine(SocdMode::LastInput);
+
+        engine.handle_key(LEFT, true);
+        engine.handle_key(RIGHT, true);
+        assert_eq!(engine.gamepad().thumb_lx, 29000);
+        engine.handle_key(RIGHT, false);
+        assert_eq!(engine.gamepad().thumb_lx, -29000);
+    }
+
+    fn gamepad_engine(actions: Vec<(u32, BindingAction)>) -> Engine {
+        let bindings = actions
+            .into_iter()
+            .map(|(keycode, action)| binding(keycode, action))
+            .collect::<Vec<_>>();
+        engine_for(&bindings)
+    }
+
+    #[test]
+    fn shared_button_held_until_every_key_is_released() {
+        let button = BindingAction::GamepadButton {
+            button: GamepadButton::A,
+        };
+        let mut engine = gamepad_engine(vec![(LEFT, button.clone()), (RIGHT, button)]);
+        let mask = GamepadButton::A.xinput_mask();
+
+        let output = engine.handle_key(LEFT, true);
+        assert_eq!(
+            output.actions,
+            vec![OutputAction::Button {
+                button: GamepadButton::A,
+                is_down: true
+            }]
+        );
+        // The second key changes nothing, so nothing is sent
+        assert!(engine.handle_key(RIGHT, true).actions.is_empty());
+        assert!(engine.handle_key(LEFT, false).actions.is_empty());
+        assert_eq!(engine.gamepad().xinput_buttons() & mask, mask);
+
+        let output = engine.handle_key(RIGHT, false);
+        assert_eq!(
+            output.actions,
+            vec![OutputAction::Button {
+                button: GamepadButton::A,
+                is_down: false
+            }]
+        );
+        assert_eq!(engine.gamepad().xinput_buttons() & mask, 0);
+    }
+
+    #[test]
+    fn shared_trigger_uses_highest_held_value() {
+        let mut engine = gamepad_engine(vec![
+            (
+                LEFT,
+                BindingAction::Trigger {
+                    side: TriggerSide::Left,
+                    value: 255,
+                },
+            ),
+            (
+                RIGHT,
+                BindingAction::Trigger {
+                    side: TriggerSide::Left,
+                    value: 100,
+                },
+            ),
+        ]);
+
+        engine.handle_key(RIGHT, true);
+        assert_eq!(engine.gamepad().left_trigger, 100);
+        engine.handle_key(LEFT, true);
+        assert_eq!(engine.gamepad().left_trigger, 255);
+        engine.handle_key(LEFT, false);
+        assert_eq!(engine.gamepad().left_trigger, 100);
+
+        let output = engine.handle_key(RIGHT, false);
+        assert_eq!(
+            output.actions,
+            vec![OutputAction::Axis {
+                axis: Axis::LeftTrigger,
+                value: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn dpad_follows_latest_press() {
+        let mut engine = gamepad_engine(vec![
+            (
+                LEFT,
+                BindingAction::GamepadButton {
+                    button: GamepadButton::DPadLeft,
+                },
+            ),
+            (
+                RIGHT,
+                BindingAction::GamepadButton {
+                    button: GamepadButton::DPadRight,
+                },
+            ),
+        ]);
+
+        engine.handle_key(LEFT, true);
+        assert_eq!(engine.gamepad().hat_x, -1);
+        engine.handle_key(RIGHT, true);
+        assert_eq!(engine.gamepad().hat_x, 1);
+        engine.handle_key(RIGHT, false);
+        assert_eq!(engine.gamepad().hat_x, -1);
+        engine.handle_key(LEFT, false);
+        assert_eq!(engine.gamepad().hat_x, 0);
+    }
+
+    fn ramp_engine(ramp: Ramp) -> Engine {
+        let mut bindings = Vec::new();
+        for (keycode, value) in [(LEFT, -20000), (RIGHT, 20000)] {
+            bindings.push(binding(
+                keycode,
+                BindingAction::Axis {
+                    axis: StickAxis::LeftX,
+                    value,
+                    ramp: Some(ramp),
+                },
+            ));
+        }
+        engine_for(&bindings)
+    }
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn linear_ramp_up_and_down() {
+        let mut engine = ramp_engine(Ramp {
+            up_ms: 100,
+            down_ms: 50,
+            curve: RampCurve::Linear,
+        });
+        let start = Instant::now();
+
+        engine.handle_key_at(RIGHT, true, start);
+        assert_eq!(engine.gamepad().thumb_lx, 0);
+        assert!(engine.needs_tick());
+        engine.update_gamepad(at(start, 25));
+        assert_eq!(engine.gamepad().thumb_lx, 5000);
+        engine.update_gamepad(at(start, 100));
+        assert_eq!(engine.gamepad().thumb_lx, 20000);
+        assert!(!engine.needs_tick());
+
+        engine.handle_key_at(RIGHT, false, at(start, 200));
+        engine.update_gamepad(at(start, 225));
+        assert_eq!(engine.gamepad().thumb_lx, 10000);
+        engine.update_gamepad(at(start, 250));
+        assert_eq!(engine.gamepad().thumb_lx, 0);
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn release_during_ramp_returns_from_current_value() {
+        let mut engine = ramp_engine(Ramp {
+            up_ms: 100,
+            down_ms: 100,
+            curve: RampCurve::Linear,
+        });
+        let start = Instant::now();
+
+        engine.handle_key_at(RIGHT, true, start);
+        engine.handle_key_at(RIGHT, false, at(start, 50));
+        assert_eq!(engine.gamepad().thumb_lx, 10000);
+        engine.update_gamepad(at(start, 100));
+        assert_eq!(engine.gamepad().thumb_lx, 5000);
+        engine.update_gamepad(at(start, 150));
+        assert_eq!(engine.gamepad().thumb_lx, 0);
+    }
+
+    #[test]
+    fn ramp_curves() {
+        let start = Instant::now();
+        let value_at = |curve: RampCurve, ms: u64| {
+            let mut engine = ramp_engine(Ramp {
+                up_ms: 100,
+                down_ms: 0,
+                curve,
+            });
+            engine.handle_key_at(RIGHT, true, start);
+            engine.update_gamepad(at(start, ms));
+            engine.gamepad().thumb_lx
+        };
+
+        assert!(value_at(RampCurve::Exponential, 50) < value_at(RampCurve::Linear, 50));
+        assert_eq!(value_at(RampCurve::Exponential, 100), 20000);
+        assert_eq!(value_at(RampCurve::Stepped(4), 20), 0);
+        assert_eq!(value_at(RampCurve::Stepped(4), 60), 10000);
+        assert_eq!(value_at(RampCurve::Stepped(4), 100), 20000);
+    }
+
+    #[test]
+    fn release_all_skips_ramp() {
+        let mut engine = ramp_engine(Ramp {
+            up_ms: 0,
+            down_ms: 1000,
+            curve: RampCurve::Linear,
+        });
+
+        engine.handle_key(LEFT, true);
+        assert_eq!(engine.gamepad().thumb_lx, -20000);
+        engine.release_all();
+        assert_eq!(engine.gamepad().thumb_lx, 0);
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn stick_scale_applies_while_held() {
+        const WALK: u32 = 0x10;
+        let mut bindings = vec![binding(WALK, BindingAction::StickScale { percent: 50 })];
+        for (keycode, axis) in [(LEFT, StickAxis::LeftX), (RIGHT, StickAxis::RightY)] {
+            bindings.push(binding(
+                keycode,
+                BindingAction::Axis {
+                    axis,
+                    value: 20000,
+                    ramp: None,
+                },
+            ));
+        }
+        let mut engine = engine_for(&bindings);
+
+        engine.handle_key(LEFT, true);
+        engine.handle_key(WALK, true);
+        assert_eq!(engine.gamepad().thumb_lx, 10000);
+        engine.handle_key(RIGHT, true);
+        assert_eq!(engine.gamepad().thumb_ry, 10000);
+        engine.handle_key(WALK, false);
+        assert_eq!(engine.gamepad().thumb_lx, 20000);
+        assert_eq!(engine.gamepad().thumb_ry, 20000);
+    }
+
+    #[test]
+    fn stick_normalization() {
+        let up = 0x57;
+        let mut bindings = Vec::new();
+        for (keycode, axis) in [(RIGHT, StickAxis::LeftX), (up, StickAxis::LeftY)] {
+            bindings.push(binding(
+                keycode,
+                BindingAction::Axis {
+                    axis,
+                    value: 29000,
+                    ramp: None,
+                },
+            ));
+        }
+
+        for (mode, expected) in [
+            (StickNormalization::Square, (29000, 29000)),
+            (StickNormalization::Circle, (23170, 23170)),
+            (StickNormalization::Diagonal(100), (20506, 20506)),
+        ] {
+            let mut config = BindingConfig {
+                bindings: bindings.clone(),
+                ..Default::default()
+            };
+            config.stick_normalization.insert(Stick::Left, mode);
+            let mut engine = Engine::from_config(&config, Some, false);
+
+            engine.handle_key(RIGHT, true);
+            assert_eq!(engine.gamepad().thumb_lx, 29000, "{:?}", mode);
+            engine.handle_key(up, true);
+            assert_eq!(
+                (engine.gamepad().thumb_lx, engine.gamepad().thumb_ly),
+                expected,
+                "{:?}",
+                mode
+            );
+        }
+    }
+
+    const CAPS: u32 = 0x14;
+    const W: u32 = 0x57;
+
+    fn layer_config(layer_action: BindingAction) -> BindingConfig {
+        BindingConfig {
+            bindings: vec![
+                binding(CAPS, layer_action),
+                binding(
+                    W,
+                    BindingAction::Axis {
+                        axis: StickAxis::LeftY,
+                        value: 29000,
+                        ramp: None,
+                    },
+                ),
+                keyboard_binding(LEFT, LEFT_OUTPUT),
+            ],
+            layers: vec![Layer {
+                name: "dpad".to_string(),
+                bindings: vec![button_binding(W, GamepadButton::DPadUp)],
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn held_layer_keeps_outputs_until_release() {
+        let config = layer_config(BindingAction::LayerHold {
+            layer: "dpad".to_string(),
+        });
+        let mut engine = Engine::from_config(&config, Some, false);
+
+        assert!(engine.handle_key(CAPS, true).block_input);
+        engine.handle_key(W, true);
+        assert_eq!(engine.gamepad().hat_y, -1);
+        assert_eq!(engine.gamepad().thumb_ly, 0);
+
+        // W was pressed on the layer, so it keeps its dpad output after the layer is released
+        engine.handle_key(CAPS, false);
+        assert_eq!(engine.gamepad().hat_y, -1);
+        engine.handle_key(W, false);
+        assert_eq!(engine.gamepad().hat_y, 0);
+
+        engine.handle_key(W, true);
+        assert_eq!(engine.gamepad().thumb_ly, 29000);
+        assert_eq!(engine.gamepad().hat_y, 0);
+    }
+
+    #[test]
+    fn toggled_layer_falls_through_to_base() {
+        let config = layer_config(BindingAction::LayerToggle {
+            layer: "dpad".to_string(),
+        });
+        let mut engine = Engine::from_config(&config, Some, false);
+        let mut output_keys = BTreeSet::new();
+
+        engine.handle_key(CAPS, true);
+        engine.handle_key(CAPS, false);
+        engine.handle_key(W, true);
+        assert_eq!(engine.gamepad().hat_y, -1);
+
+        // LEFT isn't bound on the layer
+        send(&mut engine, &mut output_keys, LEFT, true);
+        assert_eq!(output_keys, BTreeSet::from([LEFT_OUTPUT]));
+        send(&mut engine, &mut output_keys, LEFT, false);
+        engine.handle_key(W, false);
+
+        engine.handle_key(CAPS, true);
+        engine.handle_key(CAPS, false);
+        engine.handle_key(W, true);
+        assert_eq!(engine.gamepad().thumb_ly, 29000);
+    }
+
+    #[test]
+    fn replacing_bindings_keeps_toggled_layers() {
+        let config = layer_config(BindingAction::LayerToggle {
+            layer: "dpad".to_string(),
+        });
+        let mut engine = Engine::from_config(&config, Some, false);
+
+        engine.handle_key(CAPS, true);
+        engine.handle_key(CAPS, false);
+        engine.replace_bindings(Engine::from_config(&config, Some, false));
+        engine.handle_key(W, true);
+        assert_eq!(engine.gamepad().hat_y, -1);
+    }
+
+    const SHIFT: u32 = 0x10;
+    const J: u32 = 0x4A;
+
+    fn chord_engine() -> Engine {
+        engine_for(&[
+            Binding {
+                chord: vec![SHIFT],
+                ..button_binding(J, GamepadButton::Start)
+            },
+            keyboard_binding(J, LEFT_OUTPUT),
+        ])
+    }
+
+    #[test]
+    fn chord_suppresses_its_keys() {
+        let mut engine = chord_engine();
+        let start = Instant::now();
+
+        let output = engine.handle_key_at(SHIFT, true, start);
+        assert!(output.block_input && output.actions.is_empty());
+        let output = engine.handle_key_at(J, true, at(start, 20));
+        assert!(output.block_input);
+        assert_eq!(
+            output.actions,
+            vec![OutputAction::Button {
+                button: GamepadButton::Start,
+                is_down: true,
+            }]
+        );
+
+        let output = engine.handle_key_at(SHIFT, false, at(start, 100));
+        assert!(output.block_input);
+        assert_eq!(engine.gamepad().buttons, 0);
+        let output = engine.handle_key_at(J, false, at(start, 120));
+        assert!(output.block_input && output.actions.is_empty());
+    }
+
+    #[test]
+    fn chord_window_runs_out() {
+        let mut engine = chord_engine();
+        let start = Instant::now();
+
+        engine.handle_key_at(SHIFT, true, start);
+        assert!(engine.needs_tick());
+        assert!(engine.tick_at(at(start, 10)).is_empty());
+        // Shift is unbound, its swallowed press is sent as itself
+        assert_eq!(
+            engine.tick_at(at(start, 50)),
+            vec![OutputAction::Keyboard {
+                code: SHIFT,
+                is_down: true,
+            }]
+        );
+        assert!(!engine.needs_tick());
+
+        let output = engine.handle_key_at(J, true, at(start, 60));
+        assert_eq!(
+            output.actions,
+            vec![OutputAction::Keyboard {
+                code: LEFT_OUTPUT,
+                is_down: true,
+            }]
+        );
+        assert!(
+            !engine
+                .handle_key_at(SHIFT, false, at(start, 70))
+                .block_input
+        );
+    }
+
+    #[test]
+    fn unrelated_key_ends_chord() {
+        let mut engine = chord_engine();
+        let start = Instant::now();
+
+        engine.handle_key_at(SHIFT, true, start);
+        let output = engine.handle_key_at(LEFT, true, at(start, 5));
+        assert_eq!(
+            output.actions,
+            vec![OutputAction::Keyboard {
+                code: SHIFT,
+                is_down: true,
+            }]
+        );
+        assert!(!output.block_input);
+
+        // Shift is held on its own now, so J can't complete the chord
+        let output = engine.handle_key_at(J, true, at(start, 10));
+        assert_eq!(
+            output.actions,
+            vec![OutputAction::Keyboard {
+                code: LEFT_OUTPUT,
+                is_down: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn chord_key_released_early_is_a_tap() {
+        let mut engine = chord_engine();
+        let start = Instant::now();
+
+        engine.handle_key_at(J, true, start);
+        let output = engine.handle_key_at(J, false, at(start, 10));
+        assert_eq!(
+            output.actions,
+            vec![
+                OutputAction::Keyboard {
+                    code: LEFT_OUTPUT,
+                    is_down: true,
+                },
+                OutputAction::Keyboard {
+                    code: LEFT_OUTPUT,
+                    is_down: false,
+                },
+            ]
+        );
+    }
+
+    fn tap_hold_engine() -> Engine {
+        gamepad_engine(vec![(
+            LEFT,
+            BindingAction::TapHold {
+                tap: Box::new(BindingAction::GamepadButton {
+                    button: GamepadButton::A,
+                }),
+                hold: Box::new(BindingAction::Trigger {
+                    side: TriggerSide::Right,
+                    value: 255,
+                }),
+                timeout_ms: 200,
+            },
+        )])
+    }
+
+    #[test]
+    fn tap_sends_tap_output_briefly() {
+        let mut engine = tap_hold_engine();
+        let start = Instant::now();
+
+        let output = engine.handle_key_at(LEFT, true, start);
+        assert!(output.block_input && output.actions.is_empty());
+        let output = engine.handle_key_at(LEFT, false, at(start, 100));
+        assert!(output.block_input);
+        assert_eq!(engine.gamepad().buttons, GamepadButton::A.xinput_mask());
+
+        assert!(engine.needs_tick());
+        engine.tick_at(at(start, 110));
+        assert_eq!(engine.gamepad().buttons, GamepadButton::A.xinput_mask());
+        engine.tick_at(at(start, 130));
+        assert_eq!(engine.gamepad().buttons, 0);
+        assert_eq!(engine.gamepad().right_trigger, 0);
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn hold_sends_hold_output_until_release() {
+        let mut engine = tap_hold_engine();
+        let start = Instant::now();
+
+        engine.handle_key_at(LEFT, true, start);
+        engine.tick_at(at(start, 150));
+        assert_eq!(engine.gamepad().right_trigger, 0);
+        engine.tick_at(at(start, 200));
+        assert_eq!(engine.gamepad().right_trigger, 255);
+
+        // Repeats of the held key don't start a tap
+        engine.handle_key_at(LEFT, true, at(start, 250));
+        engine.handle_key_at(LEFT, false, at(start, 400));
+        assert_eq!(engine.gamepad().right_trigger, 0);
+        assert_eq!(engine.gamepad().buttons, 0);
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn tap_outputs_keyboard_key() {
+        let mut engine = engine_for(&[binding(
+            LEFT,
+            BindingAction::TapHold {
+                tap: Box::new(BindingAction::Keyboard { key: LEFT_OUTPUT }),
+                hold: Box::new(BindingAction::Keyboard { key: RIGHT_OUTPUT }),
+                timeout_ms: 200,
+            },
+        )]);
+        let start = Instant::now();
+
+        engine.handle_key_at(LEFT, true, start);
+        assert_eq!(
+            engine.handle_key_at(LEFT, false, at(start, 50)).actions,
+            vec![OutputAction::Keyboard {
+                code: LEFT_OUTPUT,
+                is_down: true,
+            }]
+        );
+        assert_eq!(
+            engine.tick_at(at(start, 80)),
+            vec![OutputAction::Keyboard {
+                code: LEFT_OUTPUT,
+                is_down: false,
+            }]
+        );
+    }
+
+    fn momentary_engine() -> Engine {
+        engine_for(&[keyboard_binding(LEFT, LEFT_OUTPUT)])
+    }
+
+    fn key(code: u32, is_down: bool) -> OutputAction {
+        OutputAction::Keyboard { code, is_down }
+    }
+
+    #[test]
+    fn momentary_key_is_released_after_a_tap() {
+        let mut engine = momentary_engine();
+        let start = Instant::now();
+
+        let output = engine.handle_momentary_key_at(LEFT, start);
+        assert!(output.block_input);
+        assert_eq!(output.actions, vec![key(LEFT_OUTPUT, true)]);
+        assert!(engine.needs_tick());
+        assert!(engine.tick_at(at(start, 10)).is_empty());
+        assert_eq!(engine.tick_at(at(start, 30)), vec![key(LEFT_OUTPUT, false)]);
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn momentary_key_presses_again_before_release() {
+        let mut engine = momentary_engine();
+        let start = Instant::now();
+
+        engine.handle_momentary_key_at(LEFT, start);
+        assert_eq!(
+            engine.handle_momentary_key_at(LEFT, at(start, 10)).actions,
+            vec![key(LEFT_OUTPUT, false), key(LEFT_OUTPUT, true)]
+        );
+        assert!(engine.tick_at(at(start, 30)).is_empty());
+        assert_eq!(engine.tick_at(at(start, 40)), vec![key(LEFT_OUTPUT, false)]);
+    }
+
+    fn mouse_look_engine(mouse_look: MouseLook) -> Engine {
+        let config = BindingConfig {
+            mouse_look: Some(mouse_look),
+            ..Default::default()
+        };
+        Engine::from_config(&config, Some, false)
+    }
+
+    #[test]
+    fn mouse_look_moves_right_stick() {
+        let mut engine = mouse_look_engine(MouseLook {
+            sensitivity: 1000,
+            deadzone_percent: 0,
+            smoothing_ms: 10,
+            decay_ms: 0,
+        });
+        let start = Instant::now();
+
+        // 10 counts averaged over 10ms is one count per millisecond
+        engine.move_mouse_at(10, 0, start);
+        assert_eq!(engine.gamepad().thumb_rx, 1000);
+        engine.move_mouse_at(0, 10, start);
+        assert_eq!(
+            (engine.gamepad().thumb_rx, engine.gamepad().thumb_ry),
+            (1000, -1000)
+        );
+
+        engine.tick_at(at(start, 4));
+        assert!(engine.gamepad().thumb_rx > 0 && engine.needs_tick());
+        engine.tick_at(at(start, 8));
+        engine.tick_at(at(start, 12));
+        engine.tick_at(at(start, 16));
+        assert_eq!(
+            (engine.gamepad().thumb_rx, engine.gamepad().thumb_ry),
+            (0, 0)
+        );
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn mouse_look_decays_to_center() {
+        let mut engine = mouse_look_engine(MouseLook {
+            sensitivity: 32767,
+            deadzone_percent: 0,
+            smoothing_ms: 1,
+            decay_ms: 100,
+        });
+        let start = Instant::now();
+
+        engine.move_mouse_at(1, 0, start);
+        assert_eq!(engine.gamepad().thumb_rx, 32767);
+        engine.tick_at(at(start, 10));
+        assert_eq!(engine.gamepad().thumb_rx, 29490);
+        engine.tick_at(at(start, 14));
+        assert_eq!(engine.gamepad().thumb_rx, 28179);
+    }
+
+    #[test]
+    fn mouse_look_compensates_deadzone() {
+        let mut engine = mouse_look_engine(MouseLook {
+            sensitivity: 1000,
+            deadzone_percent: 25,
+            smoothing_ms: 10,
+            decay_ms: 0,
+        });
+
+        engine.move_mouse_at(10, 0, Instant::now());
+        assert_eq!(engine.gamepad().thumb_rx, 8942);
+    }
+
+    #[test]
+    fn turbo_pulses_while_held() {
+        let mut engine = gamepad_engine(vec![(
+            LEFT,
+            BindingAction::Turbo {
+                action: Box::new(BindingAction::GamepadButton {
+                    button: GamepadButton::X,
+                }),
+                rate_hz: 10,
+                duty_percent: 50,
+            },
+        )]);
+        let start = Instant::now();
+        let x = GamepadButton::X.xinput_mask();
+
+        engine.handle_key_at(LEFT, true, start);
+        assert_eq!(engine.gamepad().buttons, x);
+        engine.tick_at(at(start, 40));
+        assert_eq!(engine.gamepad().buttons, x);
+        engine.tick_at(at(start, 60));
+        assert_eq!(engine.gamepad().buttons, 0);
+        engine.tick_at(at(start, 104));
+        assert_eq!(engine.gamepad().buttons, x);
+
+        engine.handle_key_at(LEFT, false, at(start, 120));
+        assert_eq!(engine.gamepad().buttons, 0);
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn turbo_keyboard_key_is_released_on_stop() {
+        let mut engine = engine_for(&[binding(
+            LEFT,
+            BindingAction::Turbo {
+                action: Box::new(BindingAction::Keyboard { key: LEFT_OUTPUT }),
+                rate_hz: 20,
+                duty_percent: 25,
+            },
+        )]);
+        let start = Instant::now();
+
+        assert_eq!(
+            engine.handle_key_at(LEFT, true, start).actions,
+            vec![OutputAction::Keyboard {
+                code: LEFT_OUTPUT,
+                is_down: true,
+            }]
+        );
+        assert_eq!(
+            engine.release_all(),
+            vec![OutputAction::Keyboard {
+                code: LEFT_OUTPUT,
+                is_down: false,
+            }]
+        );
+        assert!(!engine.needs_tick());
+    }
+
+    fn macro_engine(mode: MacroMode, first_delay_ms: u32) -> Engine {
+        let button = || BindingAction::GamepadButton {
+            button: GamepadButton::X,
+        };
+        gamepad_engine(vec![(
+            LEFT,
+            BindingAction::Macro {
+                steps: vec![
+                    MacroStep {
+                        delay_ms: first_delay_ms,
+                        action: button(),
+                        is_down: true,
+                    },
+                    MacroStep {
+                        delay_ms: 30,
+                        action: button(),
+                        is_down: false,
+                    },
+                    MacroStep {
+                        delay_ms: 10,
+                        action: BindingAction::Keyboard { key: LEFT_OUTPUT },
+                        is_down: true,
+                    },
+                    MacroStep {
+                        delay_ms: 20,
+                        action: BindingAction::Keyboard { key: LEFT_OUTPUT },
+                        is_down: false,
+                    },
+                ],
+                mode,
+            },
+        )])
+    }
+
+    #[test]
+    fn macro_plays_after_release() {
+        let mut engine = macro_engine(MacroMode::Once, 0);
+        let start = Instant::now();
+        let x = GamepadButton::X.xinput_mask();
+
+        let output = engine.handle_key_at(LEFT, true, start);
+        assert!(output.block_input);
+        assert_eq!(engine.gamepad().buttons, x);
+        engine.handle_key_at(LEFT, false, at(start, 5));
+        engine.tick_at(at(start, 29));
+        assert_eq!(engine.gamepad().buttons, x);
+        engine.tick_at(at(start, 30));
+        assert_eq!(engine.gamepad().buttons, 0);
+
+        // Late ticks still play every step that is due, in order
+        assert_eq!(
+            engine.tick_at(at(start, 70)),
+            vec![
+                OutputAction::Keyboard {
+                    code: LEFT_OUTPUT,
+                    is_down: true,
+                },
+                OutputAction::Keyboard {
+                    code: LEFT_OUTPUT,
+                    is_down: false,
+                },
+            ]
+        );
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn macro_repeats_while_held() {
+        let mut engine = macro_engine(MacroMode::RepeatWhileHeld, 20);
+        let start = Instant::now();
+        let x = GamepadButton::X.xinput_mask();
+
+        engine.handle_key_at(LEFT, true, start);
+        assert_eq!(engine.gamepad().buttons, 0);
+        engine.tick_at(at(start, 20));
+        assert_eq!(engine.gamepad().buttons, x);
+        engine.tick_at(at(start, 80));
+        assert_eq!(engine.gamepad().buttons, 0);
+        engine.tick_at(at(start, 100));
+        assert_eq!(engine.gamepad().buttons, x);
+
+        // The current run is finished after the key is released
+        engine.handle_key_at(LEFT, false, at(start, 105));
+        engine.tick_at(at(start, 130));
+        assert_eq!(engine.gamepad().buttons, 0);
+        engine.tick_at(at(start, 200));
+        assert_eq!(engine.gamepad().buttons, 0);
+        assert!(!engine.needs_tick());
+    }
+
+    #[test]
+    fn macro_cancels_on_release() {
+        let mut engine = macro_engine(MacroMode::CancelOnRelease, 0);
+        let start = Instant::now();
+
+        engine.handle_key_at(LEFT, true, start);
+        engine.handle_key_at(LEFT, false, at(start, 10));
+        assert_eq!(engine.gamepad().buttons, 0);
+        assert!(!engine.needs_tick());
+        assert!(engine.tick_at(at(start, 100)).is_empty());
+    }
+
+    fn device_engine(devices: &[&str]) -> Engine {
+        let mut bindings = vec![keyboard_binding(LEFT, LEFT_OUTPUT)];
+        for device in devices {
+            bindings.push(Binding {
+                device: Some(device.to_string()),
+                ..keyboard_binding(LEFT, RIGHT_OUTPUT)
+            });
+        }
+        engine_for(&bindings)
+    }
+
+    #[test]
+    fn device_binding_takes_over_on_its_device() {
+        let mut engine = device_engine(&["pad"]);
+
+        assert_eq!(
+            engine.handle_device_key("pad", LEFT, true).actions,
+            vec![key(RIGHT_OUTPUT, true)]
+        );
+        assert_eq!(
+            engine.handle_device_key("keyboard", LEFT, true).actions,
+            vec![key(LEFT_OUTPUT, true)]
+        );
+        assert_eq!(
+            engine.handle_device_key("pad", LEFT, false).actions,
+            vec![key(RIGHT_OUTPUT, false)]
+        );
+        assert_eq!(
+            engine.handle_device_key("keyboard", LEFT, false).actions,
+            vec![key(LEFT_OUTPUT, false)]
+        );
+    }
+
+    #[test]
+    fn device_key_is_released_when_its_device_is_unbound() {
+        let mut engine = device_engine(&["pad"]);
+
+        engine.handle_device_key("pad", LEFT, true);
+        assert_eq!(engine.replace_bindings(device_engine(&["pad"])), Vec::new());
+        assert_eq!(
+            engine.replace_bindings(device_engine(&[])),
+            vec![key(RIGHT_OUTPUT, false)]
+        );
+        assert!(engine
+            .handle_device_key("pad", LEFT, false)
+            .actions
+            .is_empty());
+    }
+
+    fn pad_stick_binding(keycode: u32, value: i16) -> Binding {
+        Binding {
+            device: Some("pad".to_string()),
+            ..binding(
+                keycode,
+                BindingAction::Axis {
+                    axis: StickAxis::LeftX,
+                    value,
+                    ramp: None,
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn device_socd_pair_honours_its_priority_key() {
+        let mut bindings = socd_bindings(SocdMode::Priority(RIGHT));
+        for binding in &mut bindings {
+            binding.device = Some("pad".to_string());
+        }
+        bindings.push(pad_stick_binding(LEFT, -29000));
+        bindings.push(pad_stick_binding(RIGHT, 29000));
+
+        for order in [[LEFT, RIGHT], [RIGHT, LEFT]] {
+            let mut engine = engine_for(&bindings);
+            engine.handle_device_key("pad", order[0], true);
+            engine.handle_device_key("pad", order[1], true);
+            assert_eq!(engine.gamepad().thumb_lx, 29000, "{:X?}", order);
+        }
+    }
+
+    #[test]
+    fn device_axis_honours_its_priority_key() {
+        let mut config = BindingConfig {
+            bindings: vec![
+                pad_stick_binding(LEFT, -29000),
+                pad_stick_binding(RIGHT, 29000),
+            ],
+            ..Default::default()
+        };
+        config
+            .axis_socd
+            .insert(StickAxis::LeftX, SocdMode::Priority(LEFT));
+
+        for order in [[LEFT, RIGHT], [RIGHT, LEFT]] {
+            let mut engine = Engine::from_config(&config, Some, false);
+            engine.handle_device_key("pad", order[0], true);
+            engine.handle_device_key("pad", order[1], true);
+            assert_eq!(engine.gamepad().thumb_lx, -29000, "{:X?}", order);
+        }
+    }
+
+    #[test]
+    fn replacing_bindings_releases_held_key_whose_binding_changed() {
+        let mut engine = engine_for(&[keyboard_binding(LEFT, LEFT_OUTPUT)]);
+
+        engine.handle_key(LEFT, true);
+        assert_eq!(
+            engine.replace_bindings(engine_for(&[keyboard_binding(LEFT, RIGHT_OUTPUT)])),
+            vec![key(LEFT_OUTPUT, false)]
+        );
+        // The new output isn't released without having been pressed
+        assert!(engine.handle_key(LEFT, false).actions.is_empty());
+    }
+
+    #[test]
+    fn replacing_bindings_releases_held_key_whose_binding_was_removed() {
+        let mut engine = engine_for(&[
+            keyboard_binding(LEFT, LEFT_OUTPUT),
+            button_binding(RIGHT, GamepadButton::A),
+        ]);
+
+        engine.handle_key(LEFT, true);
+        engine.handle_key(RIGHT, true);
+        assert_eq!(
+            engine.replace_bindings(engine_for(&[])),
+            vec![
+                key(LEFT_OUTPUT, false),
+                OutputAction::Button {
+                    button: GamepadButton::A,
+                    is_down: false,
+                },
+            ]
+        );
+        assert_eq!(engine.gamepad().buttons, 0);
+        assert!(engine.handle_key(LEFT, false).actions.is_empty());
+        assert!(engine.handle_key(RIGHT, false).actions.is_empty());
+    }
+
+    #[test]
+    fn replacing_bindings_keeps_held_key_whose_binding_is_unchanged() {
+        let bindings = [
+            keyboard_binding(LEFT, LEFT_OUTPUT),
+            button_binding(RIGHT, GamepadButton::A),
+        ];
+        let mut engine = engine_for(&bindings);
+
+        engine.handle_key(LEFT, true);
+        engine.handle_key(RIGHT, true);
+        let mut next = bindings.to_vec();
+        next.push(keyboard_binding(W, RIGHT_OUTPUT));
+        assert_eq!(engine.replace_bindings(engine_for(&next)), Vec::new());
+        assert_eq!(engine.gamepad().buttons, GamepadButton::A.xinput_mask());
+
+        assert_eq!(
+            engine.handle_key(LEFT, false).actions,
+            vec![key(LEFT_OUTPUT, false)]
+        );
+        assert_eq!(
+            engine.handle_key(RIGHT, false).actions,
+            vec![OutputAction::Button {
+                button: GamepadButton::A,
+                is_down: false,
+            }]
+        );
+    }
+}
@@ -0,0 +1,418 @@
+#![cfg(target_os = "linux")]
+
+use gio::prelude::*;
+use log::{debug, info, warn};
+use once_cell::sync::{Lazy, OnceCell};
+use serde_json::Value;
+use std::env;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, RwLock};
+use x11rb::connection::Connection;
+use x11rb::properties::WmClass;
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::wayland_focus::WlrFocus;
+
+// Follows which program has the focus, the name it gives is what allowed programs and profiles
+// are matched against
+pub(crate) trait FocusProvider: Send {
+    fn name(&self) -> &'static str;
+
+    // The program focused right now, None when it isn't known yet
+    fn current_focus(&mut self) -> Option<String>;
+
+    // Blocks until another program gets the focus, None when the provider stopped working
+    fn next_focus(&mut self) -> Option<String>;
+
+    // False when the focus is only learned from its first change, which may never come
+    fn knows_focus_at_start(&self) -> bool {
+        true
+    }
+}
+
+pub(crate) type ConnectProvider = fn() -> Result<Box<dyn FocusProvider>, String>;
+
+pub(crate) const BRIDGE_PROVIDER: &str = "D-Bus bridge";
+
+// The providers that may work in the session we run in, in the order they are tried. Wayland
+// compositors come first, X11 there would only see XWayland windows.
+pub(crate) fn candidates() -> Vec<(&'static str, ConnectProvider)> {
+    let mut attempts: Vec<(&'static str, ConnectProvider)> = Vec::new();
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            attempts.push(("Hyprland", || Ok(Box::new(HyprlandFocus::connect()?))));
+        }
+        if env::var_os("SWAYSOCK").is_some() {
+            attempts.push(("Sway", || Ok(Box::new(SwayFocus::connect()?))));
+        }
+        let desktop = env::var("XDG_CURRENT_DESKTOP")
+            .unwrap_or_default()
+            .to_uppercase();
+        if desktop.contains("KDE") || desktop.contains("GNOME") {
+            attempts.push((BRIDGE_PROVIDER, || Ok(Box::new(DBusFocus::connect()?))));
+        }
+        attempts.push(("wlroots", || Ok(Box::new(WlrFocus::connect()?))));
+    }
+    if env::var_os("DISPLAY").is_some() {
+        attempts.push(("X11", || Ok(Box::new(X11Focus::connect()?))));
+    }
+    attempts
+}
+
+// Connects the first provider that works, None when nothing can follow the focus
+pub(crate) fn detect() -> Option<Box<dyn FocusProvider>> {
+    for (name, connect) in candidates() {
+        match connect() {
+            Ok(provider) => return Some(provider),
+            Err(e) => info!("Can't follow the focus through {}: {}", name, e),
+        }
+    }
+    None
+}
+
+x11rb::atom_manager! {
+    Atoms:
+    AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_VISIBLE_NAME,
+        _NET_WM_NAME,
+        WM_NAME,
+        UTF8_STRING,
+        STRING,
+    }
+}
+
+// Watches _NET_ACTIVE_WINDOW on the root window
+struct X11Focus {
+    conn: RustConnection,
+    atoms: Atoms,
+    root: Window,
+}
+
+impl X11Focus {
+    fn connect() -> Result<Self, String> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        let root = conn.setup().roots[screen_num].root;
+        let atoms = Atoms::new(&conn)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+
+        let vau = ChangeWindowAttributesAux::default().event_mask(EventMask::PROPERTY_CHANGE);
+        conn.change_window_attributes(root, &vau)
+            .map_err(|e| e.to_string())?;
+
+        // Without this, the change_window_attributes() is not actually sent to the X11 server
+        conn.flush().map_err(|e| e.to_string())?;
+        Ok(X11Focus { conn, atoms, root })
+    }
+}
+
+impl FocusProvider for X11Focus {
+    fn name(&self) -> &'static str {
+        "X11"
+    }
+
+    fn current_focus(&mut self) -> Option<String> {
+        check_focus(&self.conn, &self.atoms, self.root)
+    }
+
+    fn next_focus(&mut self) -> Option<String> {
+        loop {
+            match self.conn.wait_for_event().ok()? {
+                Event::PropertyNotify(event) if event.atom == self.atoms._NET_ACTIVE_WINDOW => {
+                    if let Some(name) = check_focus(&self.conn, &self.atoms, self.root) {
+                        return Some(name);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn check_focus(conn: &impl Connection, atoms: &Atoms, root_window: Window) -> Option<String> {
+    let focus = conn
+        .get_property(
+            false,
+            root_window,
+            atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            0,
+            1,
+        )
+        .ok()?
+        .reply()
+        .ok()?
+        .value32()?
+        .next()?;
+
+    let wm_class = WmClass::get(conn, focus).ok()?;
+    let name = conn
+        .get_property(false, focus, atoms.WM_NAME, atoms.STRING, 0, 0x1000)
+        .ok()?;
+
+    let name = String::from_utf8_lossy(&name.reply().unwrap_or_default().value).to_string();
+    match wm_class.reply().unwrap_or_default() {
+        Some(wm_class) => Some(String::from_utf8_lossy(wm_class.class()).to_string()),
+        None => Some(name),
+    }
+}
+
+// i3 compatible IPC on $SWAYSOCK
+const SWAY_MAGIC: &[u8] = b"i3-ipc";
+const SWAY_SUBSCRIBE: u32 = 2;
+const SWAY_GET_TREE: u32 = 4;
+const SWAY_WINDOW_EVENT: u32 = 0x8000_0003;
+
+struct SwayFocus {
+    events: UnixStream,
+}
+
+impl SwayFocus {
+    fn connect() -> Result<Self, String> {
+        let mut events = sway_connect()?;
+        sway_request(&mut events, SWAY_SUBSCRIBE, br#"["window"]"#)?;
+        let (_, reply) = sway_read(&mut events)?;
+        if reply["success"] != Value::Bool(true) {
+            return Err(format!("Sway refused the subscription: {}", reply));
+        }
+        Ok(SwayFocus { events })
+    }
+}
+
+impl FocusProvider for SwayFocus {
+    fn name(&self) -> &'static str {
+        "Sway"
+    }
+
+    fn current_focus(&mut self) -> Option<String> {
+        let mut stream = sway_connect().ok()?;
+        sway_request(&mut stream, SWAY_GET_TREE, b"").ok()?;
+        let (_, tree) = sway_read(&mut stream).ok()?;
+        find_sway_focus(&tree).and_then(sway_app_name)
+    }
+
+    fn next_focus(&mut self) -> Option<String> {
+        loop {
+            let (kind, event) = sway_read(&mut self.events).ok()?;
+            if kind == SWAY_WINDOW_EVENT && event["change"] == "focus" {
+                if let Some(name) = sway_app_name(&event["container"]) {
+                    return Some(name);
+                }
+            }
+        }
+    }
+}
+
+fn sway_connect() -> Result<UnixStream, String> {
+    let path = env::var("SWAYSOCK").map_err(|_| "SWAYSOCK isn't set".to_string())?;
+    UnixStream::connect(&path).map_err(|e| format!("Can't connect to {}: {}", path, e))
+}
+
+fn sway_request(stream: &mut UnixStream, kind: u32, payload: &[u8]) -> Result<(), String> {
+    let mut message = SWAY_MAGIC.to_vec();
+    message.extend((payload.len() as u32).to_ne_bytes());
+    message.extend(kind.to_ne_bytes());
+    message.extend(payload);
+    stream.write_all(&message).map_err(|e| e.to_string())
+}
+
+fn sway_read(stream: &mut UnixStream) -> Result<(u32, Value), String> {
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header).map_err(|e| e.to_string())?;
+    if &header[..6] != SWAY_MAGIC {
+        return Err("Unexpected message from Sway".to_string());
+    }
+    let length = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+    let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload).map_err(|e| e.to_string())?;
+    let payload = serde_json::from_slice(&payload).map_err(|e| e.to_string())?;
+    Ok((kind, payload))
+}
+
+fn find_sway_focus(node: &Value) -> Option<&Value> {
+    if node["focused"] == Value::Bool(true) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|children| node[children].as_array())
+        .flatten()
+        .find_map(find_sway_focus)
+}
+
+// Native Wayland windows have an app_id, XWayland ones the WM_CLASS class X11 would give
+fn sway_app_name(container: &Value) -> Option<String> {
+    container["app_id"]
+        .as_str()
+        .or_else(|| container["window_properties"]["class"].as_str())
+        .map(str::to_string)
+}
+
+// Hyprland posts events as lines on .socket2.sock and answers requests on .socket.sock
+struct HyprlandFocus {
+    events: BufReader<UnixStream>,
+}
+
+impl HyprlandFocus {
+    fn connect() -> Result<Self, String> {
+        let path = hyprland_socket(".socket2.sock")?;
+        let stream = UnixStream::connect(&path)
+            .map_err(|e| format!("Can't connect to {}: {}", path.display(), e))?;
+        Ok(HyprlandFocus {
+            events: BufReader::new(stream),
+        })
+    }
+}
+
+impl FocusProvider for HyprlandFocus {
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    fn current_focus(&mut self) -> Option<String> {
+        let mut stream = UnixStream::connect(hyprland_socket(".socket.sock").ok()?).ok()?;
+        stream.write_all(b"j/activewindow").ok()?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).ok()?;
+        let window: Value = serde_json::from_str(&reply).ok()?;
+        window["class"].as_str().map(str::to_string)
+    }
+
+    fn next_focus(&mut self) -> Option<String> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.events.read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            // activewindow>>CLASS,TITLE, the class is empty when nothing has the focus
+            if let Some(window) = line.trim_end().strip_prefix("activewindow>>") {
+                let class = window.split(',').next().unwrap_or_default();
+                if !class.is_empty() {
+                    return Some(class.to_string());
+                }
+            }
+        }
+    }
+}
+
+// Newer Hyprland versions keep their sockets in the runtime directory, older ones in /tmp
+fn hyprland_socket(name: &str) -> Result<PathBuf, String> {
+    let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .map_err(|_| "HYPRLAND_INSTANCE_SIGNATURE isn't set".to_string())?;
+    let mut dirs = Vec::new();
+    if let Some(runtime_dir) = env::var_os("XDG_RUNTIME_DIR") {
+        dirs.push(PathBuf::from(runtime_dir).join("hypr"));
+    }
+    dirs.push(PathBuf::from("/tmp/hypr"));
+    dirs.into_iter()
+        .map(|dir| dir.join(&signature).join(name))
+        .find(|path| path.exists())
+        .ok_or_else(|| format!("Can't find Hyprland's {}", name))
+}
+
+// KWin and GNOME Shell don't tell other programs what has the focus, so a small script running
+// inside them (see focus-bridge/) calls FocusChanged on this name
+const BRIDGE_NAME: &str = "io.github.overbind.Focus";
+const BRIDGE_PATH: &str = "/io/github/overbind/Focus";
+const BRIDGE_XML: &str = r#"
+<node>
+  <interface name="io.github.overbind.Focus">
+    <method name="FocusChanged">
+      <arg type="s" name="app" direction="in"/>
+    </method>
+  </interface>
+</node>"#;
+
+// The bridge is exported once for the whole process, every start only takes over where its
+// calls go. Calls are dispatched by the main loop Tauri runs on the main thread.
+static BRIDGE_CONNECTION: OnceCell<Result<gio::DBusConnection, String>> = OnceCell::new();
+static BRIDGE_CHANGES: Lazy<Arc<RwLock<Option<Sender<String>>>>> =
+    Lazy::new(|| Arc::new(RwLock::new(None)));
+static HAS_BRIDGE_CALLED: AtomicBool = AtomicBool::new(false);
+
+// Whether the script in KWin or GNOME Shell has reported the focus since OverBind started
+pub(crate) fn has_bridge_called() -> bool {
+    HAS_BRIDGE_CALLED.load(Ordering::SeqCst)
+}
+
+fn export_bridge() -> Result<gio::DBusConnection, String> {
+    let connection = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)
+        .map_err(|e| format!("Can't connect to the session bus: {}", e))?;
+    let node = gio::DBusNodeInfo::for_xml(BRIDGE_XML).map_err(|e| e.to_string())?;
+    let interface = node
+        .lookup_interface(BRIDGE_NAME)
+        .ok_or("The bridge interface is missing")?;
+
+    connection
+        .register_object(
+            BRIDGE_PATH,
+            &interface,
+            |_, _, _, _, method, parameters, invocation| {
+                if method == "FocusChanged" {
+                    if let Some((app,)) = parameters.get::<(String,)>() {
+                        HAS_BRIDGE_CALLED.store(true, Ordering::SeqCst);
+                        if let Some(sender) = BRIDGE_CHANGES.read().unwrap().as_ref() {
+                            let _ = sender.send(app);
+                        }
+                    }
+                }
+                invocation.return_value(None);
+            },
+            |_, _, _, _, _| ().to_variant(),
+            |_, _, _, _, _, _| false,
+        )
+        .map_err(|e| format!("Can't export the focus bridge: {}", e))?;
+    gio::bus_own_name_on_connection(
+        &connection,
+        BRIDGE_NAME,
+        gio::BusNameOwnerFlags::NONE,
+        |_, name| debug!("Owning {} for the focus bridge", name),
+        |_, name| warn!("Lost {}, the focus bridge can't reach OverBind", name),
+    );
+    Ok(connection)
+}
+
+struct DBusFocus {
+    changes: Receiver<String>,
+}
+
+impl DBusFocus {
+    fn connect() -> Result<Self, String> {
+        BRIDGE_CONNECTION.get_or_init(export_bridge).clone()?;
+        let (sender, changes) = mpsc::channel();
+        *BRIDGE_CHANGES.write().unwrap() = Some(sender);
+        Ok(DBusFocus { changes })
+    }
+}
+
+impl FocusProvider for DBusFocus {
+    fn name(&self) -> &'static str {
+        BRIDGE_PROVIDER
+    }
+
+    // Only known once the bridge reports a change
+    fn current_focus(&mut self) -> Option<String> {
+        None
+    }
+
+    fn next_focus(&mut self) -> Option<String> {
+        self.changes.recv().ok()
+    }
+
+    // Exporting the bridge works without the script installed, then nothing ever calls it
+    fn knows_focus_at_start(&self) -> bool {
+        false
+    }
+}
@@ -1,4 +1,23 @@
+use crate::macro_recorder::RecordedEvent;
 use crate::Settings;
+use serde::Serialize;
+
+// An input device keys can be read from, `id` is the name the settings select it by
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct InputDeviceInfo {
+    pub id: String,
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+    pub phys: Option<String>,
+    pub path: String,
+    pub event_types: Vec<String>,
+    pub has_letter_keys: bool,
+    // Grabbed by another program, whatever it grabbed doesn't reach anyone else
+    pub is_grabbed: bool,
+    // One of the virtual devices OverBind sends its output through
+    pub is_overbind: bool,
+}
 
 pub(crate) trait KeyInterceptorTrait {
     fn new() -> Self
@@ -7,5 +26,22 @@ pub(crate) trait KeyInterceptorTrait {
     fn initialize(&mut self, settings: &Settings) -> Result<(), String>;
     fn start(&mut self, app: &tauri::AppHandle) -> Result<(), String>;
     fn stop(&self, app: &tauri::AppHandle) -> ();
+    // Re-reads the bindings while running, keys held across the swap are released as needed
+    fn reload_bindings(&self) -> Result<(), String>;
     fn is_running(&self) -> bool;
+    // Captures raw key events with their timestamps until stopped, interception has to be running
+    fn start_recording(&self) -> Result<(), String>;
+    fn stop_recording(&self) -> Result<Vec<RecordedEvent>, String>;
+}
+
+// Translates a config keycode (a windows virtual keycode) into the current platform's keycode
+pub(crate) fn native_keycode(code: u32) -> Option<u32> {
+    #[cfg(target_os = "linux")]
+    return crate::linux_key_interceptor::native_keycode(code);
+
+    #[cfg(target_os = "windows")]
+    return crate::windows_key_interceptor::native_keycode(code);
+
+    #[cfg(target_os = "macos")]
+    return crate::mac_key_interceptor::native_keycode(code);
 }
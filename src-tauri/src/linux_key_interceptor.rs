@@ -1,19 +1,21 @@
 #![cfg(target_os = "linux")]
 
-use evdev::{Device, InputEventKind, Key};
+use evdev::{Device, InputEvent, InputEventKind, Key, RelativeAxisType};
 use log::{debug, error, info, trace, warn};
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::fs;
+use std::io::Write;
+use std::os::fd::AsRawFd;
 use std::os::unix::net::UnixStream;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{Emitter, Manager};
 use tauri_plugin_shell::process::{CommandChild, CommandEvent};
 use tauri_plugin_shell::ShellExt;
 use uinput::event::absolute::Hat::{X0, Y0};
@@ -22,33 +24,24 @@ use uinput::event::controller::DPad::{Down, Left, Right, Up};
 use uinput::event::controller::GamePad::{
     East, Mode, North, Select, South, Start, ThumbL, ThumbR, West, TL, TR,
 };
+use uinput::event::controller::Mouse as MouseButton;
+use uinput::event::relative::{Position as RelativePosition, Wheel};
 use uinput::event::Absolute::Hat;
 use uinput::event::Absolute::Position;
 use uinput::event::Controller::{DPad, GamePad};
+use uinput::event::Relative;
 use uinput::event::{Code, Kind, Press, Release};
 use uinput::Device as UInputDevice;
 use uinput::Event::{Absolute, Controller};
-use x11rb::connection::Connection;
-use x11rb::properties::WmClass;
-use x11rb::protocol::xproto::{
-    AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Window,
-};
-use x11rb::protocol::Event;
-
-use crate::key_interceptor::KeyInterceptorTrait;
-use crate::{get_config_path, Settings};
-
-x11rb::atom_manager! {
-    Atoms:
-    AtomsCookie {
-        _NET_ACTIVE_WINDOW,
-        _NET_WM_VISIBLE_NAME,
-        _NET_WM_NAME,
-        WM_NAME,
-        UTF8_STRING,
-        STRING,
-    }
-}
+
+use crate::bindings::{BindingConfig, GamepadButton, WHEEL_DOWN_KEYCODE, WHEEL_UP_KEYCODE};
+use crate::device_watcher::DeviceWatcher;
+use crate::engine::{self, Axis, Engine, OutputAction};
+use crate::focus_providers;
+use crate::key_interceptor::{InputDeviceInfo, KeyInterceptorTrait};
+use crate::macro_recorder::RecordedEvent;
+use crate::profiles::ProfileSet;
+use crate::Settings;
 
 trait Killable {
     fn kill(&mut self) -> std::io::Result<()>;
@@ -75,49 +68,37 @@ impl Killable for Option<CommandChild> {
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct ConfigJsonData {
-    keycode: String,
-    result_type: String,
-    result_value: i32,
-}
+// Wheel ticks get keycodes past the highest evdev key
+const WHEEL_UP_CODE: u32 = 0x300;
+const WHEEL_DOWN_CODE: u32 = 0x301;
 
-struct KeyState {
-    is_pressed: bool,
-    result_type: String,
-    result_value: i32,
-}
+// How often a lost device is looked for when nothing wakes us up sooner
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
 
-#[derive(Clone)]
-struct OppositeKey {
-    is_pressed: bool,
-    is_virtual_pressed: bool,
-    opposite_key_type: String,
-    opposite_key_value: u16,
-    opposite_key_mapping: Option<u16>,
+// Sent to the frontend when an input device is lost or grabbed again
+#[derive(Clone, Serialize)]
+struct DeviceStatus {
+    device: String,
+    connected: bool,
 }
 
-static KEY_STATES: Lazy<Arc<RwLock<HashMap<u16, KeyState>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
-static OPPOSITE_KEY_STATES: Lazy<Arc<RwLock<HashMap<u16, OppositeKey>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
-static OPPOSITE_KEY_MAPPINGS: Lazy<Arc<RwLock<HashMap<u16, u16>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
-static DPAD_BUTTON_STATES: Lazy<Arc<RwLock<HashMap<u32, KeyState>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
+static ENGINE: Lazy<Arc<RwLock<Engine>>> = Lazy::new(|| Arc::new(RwLock::new(Engine::default())));
 
 struct SharedState {
     uinput_controller: Option<UInputDevice>,
     uinput_keyboard: Option<UInputDevice>,
+    uinput_mouse: Option<UInputDevice>,
     allowed_programs: Option<Vec<String>>,
-    device_path: Option<String>,
+    // Name and path of each input device to read keys from
+    device_paths: Vec<(String, String)>,
+    mouse_device: Option<(String, String)>,
     active_app_name: Option<String>,
+    // False when nothing follows the focus in this session
+    is_focus_followed: bool,
     is_cursor_overlay_enabled: bool,
     cursor_overlay_process: Option<Box<dyn Killable>>,
     block_kb_on_controller: bool,
+    profiles: Option<ProfileSet>,
 }
 
 unsafe impl Send for SharedState {}
@@ -127,17 +108,25 @@ static SHARED_STATE: Lazy<Arc<RwLock<SharedState>>> = Lazy::new(|| {
     Arc::new(RwLock::new(SharedState {
         uinput_controller: None,
         uinput_keyboard: None,
+        uinput_mouse: None,
         allowed_programs: None,
-        device_path: None,
+        device_paths: Vec::new(),
+        mouse_device: None,
         active_app_name: None,
+        is_focus_followed: false,
         is_cursor_overlay_enabled: false,
         cursor_overlay_process: None,
         block_kb_on_controller: false,
+        profiles: None,
     }))
 });
 
 static SHOULD_RUN: Lazy<Arc<AtomicBool>> = Lazy::new(|| Arc::new(AtomicBool::new(false)));
 
+// Key events captured while a macro is being recorded
+static RECORDING: Lazy<Arc<RwLock<Option<Vec<RecordedEvent>>>>> =
+    Lazy::new(|| Arc::new(RwLock::new(None)));
+
 pub(crate) struct LinuxKeyInterceptor {}
 
 impl KeyInterceptorTrait for LinuxKeyInterceptor {
@@ -150,127 +139,46 @@ impl KeyInterceptorTrait for LinuxKeyInterceptor {
 
     fn initialize(&mut self, settings: &Settings) -> Result<(), String> {
         // Create the virtual gamepad device
-        let controller = uinput::default()
-            .unwrap()
-            .name("Overbind Virtual Gamepad")
-            .unwrap()
-            .event(Absolute(Position(X)))
-            .unwrap()
-            .min(-32768)
-            .max(32767)
-            .fuzz(0)
-            .flat(0)
-            .event(Absolute(Position(Y)))
-            .unwrap()
-            .min(-32768)
-            .max(32767)
-            .fuzz(0)
-            .flat(0)
-            .event(Absolute(Position(RX)))
-            .unwrap()
-            .min(-32768)
-            .max(32767)
-            .fuzz(0)
-            .flat(0)
-            .event(Absolute(Position(RY)))
-            .unwrap()
-            .min(-32768)
-            .max(32767)
-            .fuzz(0)
-            .flat(0)
-            .event(Absolute(Hat(X0)))
-            .unwrap()
-            .min(-1)
-            .max(1)
-            .fuzz(0)
-            .flat(0)
-            .event(Absolute(Hat(Y0)))
-            .unwrap()
-            .min(-1)
-            .max(1)
-            .fuzz(0)
-            .flat(0)
-            .event(Absolute(Position(Z)))
-            .unwrap()
-            .min(0)
-            .max(1023)
-            .fuzz(0)
-            .flat(0)
-            .event(Absolute(Position(RZ)))
-            .unwrap()
-            .min(0)
-            .max(1023)
-            .fuzz(0)
-            .flat(0)
-            .event(Controller(GamePad(North)))
-            .unwrap()
-            .event(Controller(GamePad(South)))
-            .unwrap()
-            .event(Controller(GamePad(East)))
-            .unwrap()
-            .event(Controller(GamePad(West)))
-            .unwrap()
-            .event(Controller(GamePad(TL)))
-            .unwrap()
-            .event(Controller(GamePad(TR)))
-            .unwrap()
-            .event(Controller(GamePad(ThumbL)))
-            .unwrap()
-            .event(Controller(GamePad(ThumbR)))
-            .unwrap()
-            .event(Controller(GamePad(Select)))
-            .unwrap()
-            .event(Controller(GamePad(Start)))
-            .unwrap()
-            .event(Controller(GamePad(Mode)))
-            .unwrap()
-            .create()
-            .unwrap();
+        let controller = create_virtual_gamepad()
+            .map_err(|e| format!("Failed to create the virtual gamepad: {}", e))?;
 
-        let keyboard = uinput::default()
-            .unwrap()
-            .name("Overbind Virtual Keyboard")
-            .unwrap()
-            .event(uinput::event::Keyboard::All)
-            .unwrap()
-            .create()
-            .unwrap();
+        let keyboard = create_virtual_keyboard()
+            .map_err(|e| format!("Failed to create the virtual keyboard: {}", e))?;
+
+        // Unbound input of the selected mouse passes through this one
+        let mouse = create_virtual_mouse()
+            .map_err(|e| format!("Failed to create the virtual mouse: {}", e))?;
 
         let mut shared_state = SHARED_STATE.write().unwrap();
         shared_state.uinput_controller = Some(controller);
         shared_state.uinput_keyboard = Some(keyboard);
+        shared_state.uinput_mouse = Some(mouse);
         if !settings.allowed_programs.is_empty() {
             info!("Allowed programs: {:?}", settings.allowed_programs);
             shared_state.allowed_programs = Some(settings.allowed_programs.clone());
         }
 
-        // Find the input device
-        let mut device_name = "/dev/input/event0".to_owned();
-        if settings.selected_input.is_some() {
-            let symlink_path =
-                Path::new("/dev/input/by-id").join(settings.selected_input.as_ref().unwrap());
-            if symlink_path.exists() {
-                if let Ok(input_path) = fs::read_link(symlink_path) {
-                    if let Some(input_device) = input_path.file_name() {
-                        info!("Found input device: {:?}", input_device);
-                        device_name = format!("/dev/input/{}", input_device.to_str().unwrap());
-                    }
-                }
-            } else {
-                let backup_path =
-                    Path::new("/dev/input/by-path").join(settings.selected_input.as_ref().unwrap());
-                if backup_path.exists() {
-                    if let Ok(input_path) = fs::read_link(backup_path) {
-                        if let Some(input_device) = input_path.file_name() {
-                            info!("Found input device: {:?}", input_device);
-                            device_name = format!("/dev/input/{}", input_device.to_str().unwrap());
-                        }
-                    }
+        // Find the input devices, keys are read from event0 in case we didn't find any
+        shared_state.device_paths = settings
+            .input_devices()
+            .into_iter()
+            .filter_map(|name| match find_device_path(&name) {
+                Some(path) => Some((name, path)),
+                None => {
+                    warn!("Input device {:?} not found", name);
+                    None
                 }
-            }
+            })
+            .collect();
+        if shared_state.device_paths.is_empty() {
+            shared_state
+                .device_paths
+                .push(("event0".to_owned(), "/dev/input/event0".to_owned()));
         }
-
-        shared_state.device_path = Some(device_name);
+        shared_state.mouse_device = settings
+            .selected_mouse
+            .as_ref()
+            .and_then(|name| Some((name.clone(), find_device_path(name)?)));
         shared_state.is_cursor_overlay_enabled = settings.force_cursor;
         shared_state.block_kb_on_controller = settings.block_kb_on_controller;
 
@@ -278,263 +186,86 @@ impl KeyInterceptorTrait for LinuxKeyInterceptor {
     }
 
     fn start(&mut self, app: &tauri::AppHandle) -> Result<(), String> {
-        // Read keybindings from file
-        let path = get_config_path()?;
-        let mut file = File::open(path).map_err(|e| e.to_string())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|e| e.to_string())?;
-
-        let data: Vec<ConfigJsonData> =
-            serde_json::from_str(&contents).map_err(|e| e.to_string())?;
-        let mut key_states = HashMap::new();
-        let mut opposite_key_states = HashMap::new();
-        let mut opposite_key_mappings = HashMap::new();
-        let mut dpad_button_states = HashMap::new();
-
-        for item in &data {
-            let windows_keycode =
-                u32::from_str_radix(&item.keycode, 16).expect("Invalid hexadecimal string");
-            let keycode = windows_code_to_evdev_enum(windows_keycode).unwrap().code();
-
-            if item.result_type != "socd" {
-                let mut result_value = item.result_value as i32;
-                if item.result_type == "keyboard" {
-                    result_value = windows_code_to_evdev_enum(result_value as u32)
-                        .unwrap()
-                        .code() as i32;
-                }
-                if item.result_type == "thumb_lx"
-                    || item.result_type == "thumb_ly"
-                    || item.result_type == "thumb_rx"
-                    || item.result_type == "thumb_ry"
-                {
-                    // result_value = result_value * 32767 / 30000;
-                    if item.result_type == "thumb_ly" || item.result_type == "thumb_ry" {
-                        result_value = -result_value;
-                    }
-                }
-                if item.result_type == "trigger_l" || item.result_type == "trigger_r" {
-                    result_value = result_value * 1023 / 255;
-                }
-                if item.result_type == "face_button" && item.result_value < 0x0010 {
-                    let opposite_dpad =
-                        dpad_button_opposite_key(item.result_value as u32).expect(&format!(
-                            "Could not find opposite dpad button code: {}",
-                            item.result_value
-                        ));
-                    dpad_button_states
-                        .entry(item.result_value as u32)
-                        .or_insert_with(|| KeyState {
-                            is_pressed: false,
-                            result_type: item.result_type.clone(),
-                            result_value: opposite_dpad as i32,
-                        });
-                    debug!(
-                        "DPad button code: {:?}, Opposite dpad button code: {:?}",
-                        item.result_value, opposite_dpad
-                    )
-                }
-
-                let key_state = key_states.entry(keycode).or_insert_with(|| KeyState {
-                    is_pressed: false,
-                    result_type: item.result_type.clone(),
-                    result_value: result_value,
-                });
-                debug!(
-                    "Keycode: {:?}, ResultType: {:?}, ResultValue {:?}",
-                    keycode, key_state.result_type, key_state.result_value
-                );
-            }
-        }
-
-        for item in &data {
-            let windows_keycode =
-                u32::from_str_radix(&item.keycode, 16).expect("Invalid hexadecimal string");
-            let keycode = windows_code_to_evdev_enum(windows_keycode).unwrap().code();
-
-            if item.result_type == "socd" {
-                let windows_opposite_keycode = item.result_value as u16;
-                let opposite_keycode = windows_code_to_evdev_enum(windows_opposite_keycode.into())
-                    .unwrap()
-                    .code();
-
-                let this_key_state_mapping = key_states.get(&keycode.clone());
-                let opposite_key_state_mapping = key_states.get(&opposite_keycode.clone());
-                let mut key_type = String::from("keyboard");
-                let mut opposite_key_mapping = None;
-
-                if this_key_state_mapping.is_some()
-                    && (this_key_state_mapping.unwrap().result_type == "keyboard")
-                {
-                    opposite_key_mappings.insert(
-                        keycode.clone(),
-                        this_key_state_mapping.unwrap().result_value as u16,
-                    );
-                } else {
-                    opposite_key_mappings.insert(keycode.clone(), keycode.clone());
-                }
-
-                if opposite_key_state_mapping.is_some()
-                    && (opposite_key_state_mapping.unwrap().result_type == "keyboard"
-                        || opposite_key_state_mapping.unwrap().result_type == "face_button")
-                {
-                    key_type = opposite_key_state_mapping.unwrap().result_type.clone();
-                    opposite_key_mapping =
-                        Some(opposite_key_state_mapping.unwrap().result_value as u16);
-                }
-
-                let opposite_key_state = opposite_key_states
-                    .entry(opposite_key_mappings.get(&keycode).unwrap().clone())
-                    .or_insert_with(|| OppositeKey {
-                        is_pressed: false,
-                        is_virtual_pressed: false,
-                        opposite_key_value: opposite_keycode,
-                        opposite_key_type: key_type,
-                        opposite_key_mapping,
-                    });
-
-                debug!(
-                    "Keycode: {:?}, KeycodeMapping: {:?}, OppositeKeycode: {:?}, OppositeKeyMapping: {:?}",
-                    keycode,
-                    opposite_key_mappings.get(&keycode).unwrap(),
-                    opposite_key_state.opposite_key_value,
-                    opposite_key_state.opposite_key_mapping
-                );
-            }
+        if SHARED_STATE.read().unwrap().uinput_keyboard.is_none() {
+            return Err(
+                "The virtual devices couldn't be created, fix the setup and restart OverBind"
+                    .to_string(),
+            );
         }
 
-        *KEY_STATES.write().unwrap() = key_states;
-        *OPPOSITE_KEY_STATES.write().unwrap() = opposite_key_states;
-        *OPPOSITE_KEY_MAPPINGS.write().unwrap() = opposite_key_mappings;
-        *DPAD_BUTTON_STATES.write().unwrap() = dpad_button_states;
+        // Read keybindings from the profiles, the focused program picks which one is used
+        let mut profiles = ProfileSet::load()?;
+        let active_app_name = SHARED_STATE.read().unwrap().active_app_name.clone();
+        profiles.select(active_app_name.as_deref());
+        let block_kb_on_controller = SHARED_STATE.read().unwrap().block_kb_on_controller;
+        *ENGINE.write().unwrap() =
+            Engine::from_config(profiles.current(), native_keycode, block_kb_on_controller);
+        SHARED_STATE.write().unwrap().profiles = Some(profiles);
 
         SHOULD_RUN.store(true, Ordering::SeqCst);
 
-        //Thread to update the active application name asynchronously using X11 events
-        thread::spawn(move || {
-            let (conn, screen_num) = x11rb::connect(None).unwrap();
-            let screen = &conn.setup().roots[screen_num];
-
-            let atoms = Atoms::new(&conn).unwrap().reply().unwrap();
-
-            let vau = ChangeWindowAttributesAux::default().event_mask(EventMask::PROPERTY_CHANGE);
-            conn.change_window_attributes(screen.root, &vau).unwrap();
-
-            // Without this, the change_window_attributes() is not actually sent to the X11 server
-            conn.flush().unwrap();
-
-            check_focus(&conn, &atoms, screen.root);
-            while SHOULD_RUN.load(Ordering::SeqCst) {
-                match conn.wait_for_event().unwrap() {
-                    Event::PropertyNotify(event) if event.atom == atoms._NET_ACTIVE_WINDOW => {
-                        let window_name = check_focus(&conn, &atoms, screen.root);
-                        {
-                            let mut shared_state = SHARED_STATE.write().unwrap();
-                            shared_state.active_app_name = Some(window_name);
-                            info!("Active app name: {:?}", shared_state.active_app_name);
-
-                            if shared_state.is_cursor_overlay_enabled {
-                                match UnixStream::connect("/tmp/cursor_overlay.sock") {
-                                    Ok(mut stream) => {
-                                        let mut command = None;
-                                        if shared_state.allowed_programs.as_ref().is_none()
-                                            || (shared_state
-                                                .allowed_programs
-                                                .as_ref()
-                                                .unwrap()
-                                                .contains(
-                                                    &shared_state.active_app_name.as_ref().unwrap(),
-                                                ))
-                                        {
-                                            debug!("Showing cursor");
-                                            command = Some("show");
-                                        } else {
-                                            debug!("Hiding cursor");
-                                            command = Some("hide");
-                                        }
-
-                                        stream
-                                            .write_all(command.unwrap().as_bytes())
-                                            .expect("Failed to send command");
-                                    }
-                                    Err(e) => {
-                                        debug!("Failed to connect to cursor overlay. You may have to delete the socket file at /tmp/cursor_overlay.sock and restart OverBind. Error: {}", e);
-                                    }
-                                }
+        // Thread to update the active application name, with whatever follows the focus in this
+        // session. Without anything every program is allowed, so the bindings still work. The same
+        // goes for a provider that only learns the focus from a change, until it has seen one.
+        let provider = focus_providers::detect();
+        SHARED_STATE.write().unwrap().is_focus_followed = provider
+            .as_ref()
+            .is_some_and(|provider| provider.knows_focus_at_start());
+        match provider {
+            Some(mut provider) => {
+                info!("Following the focused program through {}", provider.name());
+                thread::spawn(move || {
+                    if let Some(app_name) = provider.current_focus() {
+                        set_active_app(app_name);
+                    }
+                    while SHOULD_RUN.load(Ordering::SeqCst) {
+                        match provider.next_focus() {
+                            Some(app_name) => set_active_app(app_name),
+                            // Stopped while waiting for a change, nothing was lost
+                            None if !SHOULD_RUN.load(Ordering::SeqCst) => break,
+                            None => {
+                                error!("Lost track of the focused program ({})", provider.name());
+                                break;
                             }
                         }
                     }
-                    _ => {}
-                }
+                });
             }
-        });
+            None => warn!("Can't follow the focused program, every program is allowed"),
+        }
 
-        // Start listening for key events
-        info!("Spawning key event listener thread");
+        // Advances everything that changes with time between key events: ramps, chord windows,
+        // taps, turbo pulses and macros
         thread::spawn(move || {
-            let mut allowed_programs = Option::None;
-            let mut device_path = String::from("/dev/input/event0"); // Default in case we didn't find the device
-            {
-                let shared_state = SHARED_STATE.read().unwrap();
-                if let Some(ref path) = shared_state.device_path {
-                    device_path = path.clone();
-                }
-                if let Some(ref programs) = shared_state.allowed_programs {
-                    allowed_programs = Some(programs.clone());
+            while SHOULD_RUN.load(Ordering::SeqCst) {
+                let interval = ENGINE.read().unwrap().next_tick_in();
+                thread::sleep(interval);
+                if !ENGINE.read().unwrap().needs_tick() {
+                    continue;
                 }
-            }
-            let mut device = Device::open(&device_path).unwrap();
-            info!("Opened device: {:?}", device_path);
-
-            device.grab().unwrap();
 
-            loop {
-                for event in device.fetch_events().expect("Failed to fetch events") {
-                    match event.kind() {
-                        InputEventKind::Key(key_event) => {
-                            let handle_start = Instant::now();
-                            let mut active_app_name = None;
-                            {
-                                let shared_state = SHARED_STATE.read().unwrap();
-                                active_app_name = shared_state.active_app_name.clone();
-                            }
-                            if SHOULD_RUN.load(Ordering::SeqCst) {
-                                if allowed_programs.as_ref().is_none()
-                                    || (active_app_name.is_some()
-                                        && allowed_programs
-                                            .as_ref()
-                                            .unwrap()
-                                            .contains(&active_app_name.unwrap()))
-                                {
-                                    handle_key_event(key_event.code(), event.value() != 0);
-                                } else {
-                                    send_keyboard_event(key_event.code(), event.value() != 0);
-                                    sync_keyboard();
-                                }
-                                let handle_duration = handle_start.elapsed();
-                                debug!("Handle duration in us: {:?}", handle_duration.as_micros());
-                            } else {
-                                // Because fetch_events is blocking when overbind is stopped we still will process one more event
-                                // Send it and immediately release it to resent the virtual keyboard back to normal
-                                send_keyboard_event(key_event.code(), event.value() != 0);
-                                send_keyboard_event(key_event.code(), false);
-                                sync_keyboard();
-                                break;
-                            }
-                        }
-                        _ => (),
-                    }
-                }
-                if !SHOULD_RUN.load(Ordering::SeqCst) {
-                    break;
-                }
+                let actions = ENGINE.write().unwrap().tick();
+                send_output_actions(&actions);
+                sync_keyboard();
+                sync_controller();
             }
-
-            info!("Ungrabbing device");
-            device.ungrab().unwrap();
         });
 
+        let mouse_device = SHARED_STATE.read().unwrap().mouse_device.clone();
+        if let Some((mouse_name, mouse_path)) = mouse_device {
+            info!("Spawning mouse event listener thread");
+            let app = app.clone();
+            thread::spawn(move || listen_to_mouse(&app, &mouse_name, &mouse_path));
+        }
+
+        // Start listening for key events, one thread per input device
+        let device_paths = SHARED_STATE.read().unwrap().device_paths.clone();
+        for (device_name, device_path) in device_paths {
+            info!("Spawning key event listener thread for {:?}", device_name);
+            let app = app.clone();
+            thread::spawn(move || listen_to_keyboard(&app, &device_name, &device_path));
+        }
+
         // Cursor overlay
         {
             let mut shared_state = SHARED_STATE.write().unwrap();
@@ -607,198 +338,428 @@ impl KeyInterceptorTrait for LinuxKeyInterceptor {
         }
     }
 
+    fn reload_bindings(&self) -> Result<(), String> {
+        let mut profiles = ProfileSet::load()?;
+        let active_app_name = SHARED_STATE.read().unwrap().active_app_name.clone();
+        profiles.select(active_app_name.as_deref());
+        let config = profiles.current().clone();
+        SHARED_STATE.write().unwrap().profiles = Some(profiles);
+
+        info!("Reloading bindings");
+        replace_bindings(&config);
+        Ok(())
+    }
+
     fn is_running(&self) -> bool {
         SHOULD_RUN.load(Ordering::SeqCst)
     }
-}
 
-fn handle_key_event(key_code: u16, key_is_down: bool) {
-    // Update Key State
-    {
-        let mut key_states = KEY_STATES.write().unwrap();
-        match key_states.get_mut(&key_code) {
-            Some(state) => state.is_pressed = key_is_down,
-            _ => (),
+    fn start_recording(&self) -> Result<(), String> {
+        if !SHOULD_RUN.load(Ordering::SeqCst) {
+            return Err("OverBind has to be running to record a macro".to_string());
         }
-    }
-    debug!("Keycode: {:?}, Key is down: {:?}", key_code, key_is_down);
 
-    // SOCD
-    {
-        let mut opposite_key_states = OPPOSITE_KEY_STATES.write().unwrap();
-        let opposite_key_mappings = OPPOSITE_KEY_MAPPINGS.write().unwrap();
-
-        let cloned_key_state;
-        if opposite_key_mappings.contains_key(&key_code) {
-            {
-                let mapped_key = opposite_key_mappings.get(&key_code).unwrap();
-                let key_state = opposite_key_states.get_mut(&mapped_key).unwrap();
-                key_state.is_pressed = key_is_down;
-                key_state.is_virtual_pressed = key_is_down;
-
-                cloned_key_state = key_state.clone();
-            }
+        // Bindings are off while recording, release what they hold so nothing gets stuck
+        let actions = ENGINE.write().unwrap().release_all();
+        send_output_actions(&actions);
+        sync_keyboard();
+        sync_controller();
 
-            let opposite_key_value = cloned_key_state.opposite_key_value;
-            let mapping_opposite_key_state =
-                cloned_key_state
-                    .opposite_key_mapping
-                    .and_then(|opposite_key_mapping| {
-                        opposite_key_states.get_mut(&opposite_key_mapping)
-                    });
-            let opposite_key_state = match mapping_opposite_key_state {
-                Some(value) => value,
-                None => opposite_key_states.get_mut(&opposite_key_value).unwrap(),
-            };
+        info!("Recording macro");
+        *RECORDING.write().unwrap() = Some(Vec::new());
+        Ok(())
+    }
 
-            if key_is_down && opposite_key_state.is_pressed && opposite_key_state.is_virtual_pressed
-            {
-                opposite_key_state.is_virtual_pressed = false;
-                send_keyboard_event(cloned_key_state.opposite_key_value, false);
-            } else if !key_is_down && opposite_key_state.is_pressed {
-                opposite_key_state.is_virtual_pressed = true;
-                send_keyboard_event(cloned_key_state.opposite_key_value, true);
-            }
-        }
+    fn stop_recording(&self) -> Result<Vec<RecordedEvent>, String> {
+        info!("Stopped recording macro");
+        RECORDING
+            .write()
+            .unwrap()
+            .take()
+            .ok_or_else(|| "No macro is being recorded".to_string())
     }
+}
 
-    // Rebinds
-    {
-        let key_states = KEY_STATES.read().unwrap();
-        if let Some(key_state) = key_states.get(&key_code) {
-            if key_state.result_type == "keyboard" {
-                send_keyboard_event(key_state.result_value as u16, key_is_down);
-                sync_keyboard();
-                return;
-            } else if key_state.result_type == "face_button" {
-                if key_state.result_value as u32 <= 0x0010 {
-                    let event_key = dpad_button_to_abs_key(key_state.result_value as u32).expect(
-                        &format!("Invalid dpad button code: {}", key_state.result_value),
-                    );
-                    let event_value = dpad_button_to_abs_value(key_state.result_value as u32)
-                        .expect(&format!(
-                            "Invalid dpad button code: {}",
-                            key_state.result_value
-                        ));
-
-                    if key_is_down {
-                        debug!(
-                            "Sending hat event (dpad down): {:?}, {:?}",
-                            event_key, event_value
-                        );
-                        send_hat_event(event_key, event_value, true);
-                    } else {
-                        let dpad_states = DPAD_BUTTON_STATES.read().unwrap();
-                        debug!(
-                            "Searching opposite dpad state for {:?}",
-                            key_state.result_value
-                        );
-                        // Print all dpad states
-                        for (key, value) in dpad_states.iter() {
-                            debug!("Key: {:?}, Value: {:?}", key, value.result_value);
-                        }
-                        if let Some(opposite_dpad_state) =
-                            dpad_states.get(&(key_state.result_value as u32))
-                        {
-                            if opposite_dpad_state.is_pressed {
-                                debug!(
-                                    "Sending hat even (opposite dpad down): {:?}, {:?}",
-                                    event_key,
-                                    dpad_button_to_abs_value(
-                                        opposite_dpad_state.result_value as u32
-                                    )
-                                    .expect(&format!(
-                                        "Invalid dpad button code: {}",
-                                        opposite_dpad_state.result_value
-                                    ))
-                                );
-                                send_hat_event(
-                                    event_key,
-                                    dpad_button_to_abs_value(
-                                        opposite_dpad_state.result_value as u32,
-                                    )
-                                    .expect(&format!(
-                                        "Invalid dpad button code: {}",
-                                        opposite_dpad_state.result_value
-                                    )),
-                                    true,
-                                );
+// Captures the event when a macro is being recorded, recorded keys skip the bindings
+fn record_key_event(key_code: u16, value: i32, time: SystemTime) -> bool {
+    let mut recording = RECORDING.write().unwrap();
+    let Some(events) = recording.as_mut() else {
+        return false;
+    };
+
+    // Key repeats aren't part of the macro
+    if value != 2 {
+        events.push(RecordedEvent {
+            keycode: key_code as u32,
+            is_down: value != 0,
+            time,
+        });
+    }
+    true
+}
+
+// Keys of one grabbed input device, every device feeds the same engine
+fn listen_to_keyboard(app: &tauri::AppHandle, device_name: &str, device_path: &str) {
+    listen_to_device(app, device_name, device_path, |device| {
+        loop {
+            for event in device.fetch_events()? {
+                match event.kind() {
+                    InputEventKind::Key(key_event) => {
+                        let handle_start = Instant::now();
+                        if SHOULD_RUN.load(Ordering::SeqCst) {
+                            if record_key_event(key_event.code(), event.value(), event.timestamp())
+                            {
+                                send_keyboard_event(key_event.code(), event.value() != 0);
+                                sync_keyboard();
+                            } else if is_focused_program_allowed() {
+                                handle_key_event(device_name, key_event.code(), event.value() != 0);
                             } else {
-                                debug!(
-                                    "Sending hat event (opposite of {:?} not pressed): {:?}, {:?}",
-                                    key_state.result_value, event_key, event_value
-                                );
-                                send_hat_event(event_key, event_value, false);
+                                send_keyboard_event(key_event.code(), event.value() != 0);
+                                sync_keyboard();
                             }
+                            let handle_duration = handle_start.elapsed();
+                            debug!("Handle duration in us: {:?}", handle_duration.as_micros());
                         } else {
-                            debug!(
-                                "Sending hat event (opposite of {:?} not found): {:?}, {:?}",
-                                key_state.result_value, event_key, event_value
-                            );
-                            send_hat_event(event_key, event_value, false);
+                            // Because fetch_events is blocking when overbind is stopped we still will process one more event
+                            // Send it and immediately release it to resent the virtual keyboard back to normal
+                            send_keyboard_event(key_event.code(), event.value() != 0);
+                            send_keyboard_event(key_event.code(), false);
+                            sync_keyboard();
+                            break;
                         }
                     }
+                    _ => (),
+                }
+            }
+            if !SHOULD_RUN.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+        Ok(())
+    });
 
-                    let mut dpad_states = DPAD_BUTTON_STATES.write().unwrap();
-                    if let Some(dpad_state) = dpad_states
-                        .iter_mut()
-                        .find(|(_, state)| state.result_value == key_state.result_value)
-                    {
-                        dpad_state.1.is_pressed = key_is_down;
-                        debug!(
-                            "Setting dpad state for {:?} to {:?}",
-                            key_state.result_value, key_is_down
-                        );
-                    }
-                } else {
-                    let event = face_button_to_uinput_enum(key_state.result_value as u32).expect(
-                        &format!("Invalid controller code: {}", key_state.result_value),
-                    );
+    // Don't leave anything held on the virtual devices once we stop listening
+    release_outputs();
+}
+
+// Keeps a device grabbed while interception runs. When the device is lost its keys are released
+// and it is grabbed again once it comes back. `read_events` returns when interception
+// stops or with the error that lost the device.
+fn listen_to_device(
+    app: &tauri::AppHandle,
+    device_name: &str,
+    device_path: &str,
+    mut read_events: impl FnMut(&mut Device) -> std::io::Result<()>,
+) {
+    let mut opened = open_device(device_path);
+    loop {
+        let device = opened.take().or_else(|| {
+            emit_device_status(app, device_name, false);
+            wait_for_device(device_name)
+        });
+        let Some(mut device) = device else {
+            break;
+        };
+        emit_device_status(app, device_name, true);
+
+        let result = read_events(&mut device);
+        info!("Ungrabbing device {:?}", device_name);
+        let _ = device.ungrab();
+        match result {
+            Ok(()) => break,
+            Err(e) => {
+                error!("Lost input device {:?}: {}", device_name, e);
+                release_device_keys(device_name, &device);
+            }
+        }
+    }
+}
+
+fn open_device(device_path: &str) -> Option<Device> {
+    let mut device = match Device::open(device_path) {
+        Ok(device) => device,
+        Err(e) => {
+            error!("Failed to open device {:?}: {}", device_path, e);
+            return None;
+        }
+    };
+    if let Err(e) = device.grab() {
+        error!("Failed to grab device {:?}: {}", device_path, e);
+        return None;
+    }
+    info!("Opened device: {:?}", device_path);
+    Some(device)
+}
+
+// Waits for a device to be plugged back in, None when interception stops first
+fn wait_for_device(device_name: &str) -> Option<Device> {
+    info!("Waiting for input device {:?}", device_name);
+    let watcher = DeviceWatcher::new()
+        .map_err(|e| warn!("{}, checking every {:?}", e, RECONNECT_INTERVAL))
+        .ok();
+    while SHOULD_RUN.load(Ordering::SeqCst) {
+        if let Some(device) = find_device_path(device_name).and_then(|path| open_device(&path)) {
+            info!("Input device {:?} is back", device_name);
+            return Some(device);
+        }
+        match &watcher {
+            Some(watcher) => watcher.wait(RECONNECT_INTERVAL),
+            None => thread::sleep(RECONNECT_INTERVAL),
+        }
+    }
+    None
+}
+
+fn emit_device_status(app: &tauri::AppHandle, device_name: &str, is_connected: bool) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.emit(
+            "input_device_status",
+            DeviceStatus {
+                device: device_name.to_owned(),
+                connected: is_connected,
+            },
+        );
+    }
+}
+
+// Lets go of the keys the lost device was last seen holding, going through the engine like a real
+// release so device scoped keys resolve to their keycode and keys held on other devices stay held
+fn release_device_keys(device_name: &str, device: &Device) {
+    let Some(keys) = device.cached_state().key_vals() else {
+        return;
+    };
+    for key in keys.iter() {
+        handle_key_event(device_name, key.code(), false);
+        // Keys pressed while the bindings were off went straight to the virtual keyboard, the
+        // engine blocks the release of a bound key. Releasing a key that isn't held does nothing.
+        send_keyboard_event(key.code(), false);
+    }
+    sync_keyboard();
+}
+
+// Releases everything the engine holds on the virtual devices
+fn release_outputs() {
+    let actions = ENGINE.write().unwrap().release_all();
+    send_output_actions(&actions);
+    sync_keyboard();
+    sync_controller();
+}
+
+fn handle_key_event(device_name: &str, key_code: u16, key_is_down: bool) {
+    debug!(
+        "Device: {:?}, Keycode: {:?}, Key is down: {:?}",
+        device_name, key_code, key_is_down
+    );
+    let output =
+        ENGINE
+            .write()
+            .unwrap()
+            .handle_device_key(device_name, key_code as u32, key_is_down);
 
-                    send_face_button_event(event, key_is_down);
+    send_output_actions(&output.actions);
+
+    if !output.block_input {
+        send_keyboard_event(key_code, key_is_down);
+    }
+
+    sync_keyboard();
+    if output.has_gamepad_actions() {
+        sync_controller();
+    }
+}
+
+// Mouse buttons are bindable like keys, wheel ticks press and release their wheel key and with
+// mouse-look on, movement moves the right stick. Everything else and whatever isn't bound goes on
+// to the virtual mouse.
+fn listen_to_mouse(app: &tauri::AppHandle, device_name: &str, device_path: &str) {
+    listen_to_device(app, device_name, device_path, |device| {
+        while SHOULD_RUN.load(Ordering::SeqCst) {
+            let events: Vec<InputEvent> = device.fetch_events()?.collect();
+
+            let is_remapping = SHOULD_RUN.load(Ordering::SeqCst) && is_focused_program_allowed();
+            let is_looking = is_remapping && ENGINE.read().unwrap().has_mouse_look();
+            let mut motion = (0, 0);
+            for event in events {
+                match event.kind() {
+                    InputEventKind::RelAxis(RelativeAxisType::REL_X) if is_looking => {
+                        motion.0 += event.value()
+                    }
+                    InputEventKind::RelAxis(RelativeAxisType::REL_Y) if is_looking => {
+                        motion.1 += event.value()
+                    }
+                    InputEventKind::Key(key) if is_remapping => {
+                        handle_key_event(device_name, key.code(), event.value() != 0)
+                    }
+                    InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL) if is_remapping => {
+                        handle_wheel_event(event.value())
+                    }
+                    // The virtual mouse only scrolls in whole ticks
+                    InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL_HI_RES)
+                    | InputEventKind::RelAxis(RelativeAxisType::REL_HWHEEL_HI_RES) => (),
+                    _ => forward_mouse_event(&event),
                 }
-            } else if key_state.result_type != "socd" {
-                send_position_event(
-                    position_to_uinput_enum(&key_state.result_type)
-                        .expect(&format!("Invalid thumb code: {}", key_state.result_value)),
-                    key_state.result_value as i32,
-                    key_is_down,
-                );
             }
-        } else {
-            send_keyboard_event(key_code, key_is_down);
-            sync_keyboard();
-            return;
+            if motion != (0, 0) {
+                handle_mouse_motion(motion.0, motion.1);
+            }
+        }
+        Ok(())
+    });
+}
+
+fn handle_wheel_event(value: i32) {
+    let code = if value > 0 {
+        WHEEL_UP_CODE
+    } else {
+        WHEEL_DOWN_CODE
+    };
+    for _ in 0..value.unsigned_abs() {
+        let output = ENGINE.write().unwrap().handle_momentary_key(code);
+        send_output_actions(&output.actions);
+
+        if !output.block_input {
+            send_mouse_event(Relative::Wheel(Wheel::Vertical), value.signum());
+        }
+
+        sync_keyboard();
+        if output.has_gamepad_actions() {
+            sync_controller();
         }
     }
+}
 
-    // Controller SOCD
-    {
-        let opposite_key_states = OPPOSITE_KEY_STATES.read().unwrap();
-        for (_, opposite_key_state) in opposite_key_states
-            .iter()
-            .filter(|&(_, ks)| ks.opposite_key_type == String::from("face_button"))
-        {
-            if let Some(opposite_key_mapping) = opposite_key_state.opposite_key_mapping {
-                let mapped_key = opposite_key_mapping as u16;
-                send_face_button_event(
-                    face_button_to_uinput_enum(mapped_key as u32)
-                        .expect(&format!("Invalid face button code: {}", mapped_key)),
-                    opposite_key_state.is_virtual_pressed,
-                );
+fn handle_mouse_motion(x: i32, y: i32) {
+    let actions = ENGINE.write().unwrap().move_mouse(x, y);
+    send_output_actions(&actions);
+    sync_controller();
+}
+
+// Sends a mouse event on to the virtual mouse as it came in
+fn forward_mouse_event(event: &InputEvent) {
+    match event.kind() {
+        InputEventKind::Synchronization(_) => sync_mouse(),
+        InputEventKind::Key(key) => {
+            if let Some(button) = evdev_enum_to_mouse_button(key) {
+                send_mouse_event(button, event.value());
+            }
+        }
+        InputEventKind::RelAxis(axis) => {
+            if let Some(relative) = evdev_enum_to_relative(axis) {
+                send_mouse_event(relative, event.value());
             }
         }
+        _ => (),
     }
+}
 
-    if !SHARED_STATE.read().unwrap().block_kb_on_controller {
-        send_keyboard_event(key_code, key_is_down);
+// Switches to the profile of the newly focused program and shows the cursor overlay only over
+// allowed programs
+fn set_active_app(app_name: String) {
+    switch_profile(&app_name);
+    let mut shared_state = SHARED_STATE.write().unwrap();
+    shared_state.active_app_name = Some(app_name);
+    shared_state.is_focus_followed = true;
+    info!("Active app name: {:?}", shared_state.active_app_name);
+
+    if shared_state.is_cursor_overlay_enabled {
+        match UnixStream::connect("/tmp/cursor_overlay.sock") {
+            Ok(mut stream) => {
+                let mut command = None;
+                if shared_state.allowed_programs.as_ref().is_none()
+                    || (shared_state
+                        .allowed_programs
+                        .as_ref()
+                        .unwrap()
+                        .contains(&shared_state.active_app_name.as_ref().unwrap()))
+                {
+                    debug!("Showing cursor");
+                    command = Some("show");
+                } else {
+                    debug!("Hiding cursor");
+                    command = Some("hide");
+                }
+
+                stream
+                    .write_all(command.unwrap().as_bytes())
+                    .expect("Failed to send command");
+            }
+            Err(e) => {
+                debug!("Failed to connect to cursor overlay. You may have to delete the socket file at /tmp/cursor_overlay.sock and restart OverBind. Error: {}", e);
+            }
+        }
+    }
+}
+
+// Whether bindings apply to the focused program. Until something follows the focus it is never
+// known whether an allowed program has it, so they apply everywhere.
+fn is_focused_program_allowed() -> bool {
+    let shared_state = SHARED_STATE.read().unwrap();
+    if !shared_state.is_focus_followed {
+        return true;
+    }
+    match (
+        &shared_state.allowed_programs,
+        &shared_state.active_app_name,
+    ) {
+        (None, _) => true,
+        (Some(programs), Some(name)) => programs.contains(name),
+        (Some(_), None) => false,
+    }
+}
+
+fn switch_profile(app_name: &str) {
+    let config = match SHARED_STATE.write().unwrap().profiles.as_mut() {
+        Some(profiles) => profiles.select(Some(app_name)).cloned(),
+        None => None,
+    };
+    if let Some(config) = config {
+        replace_bindings(&config);
     }
+}
 
+// Swaps the engine's bindings, outputs of bindings that changed are released
+fn replace_bindings(config: &BindingConfig) {
+    let block_kb_on_controller = SHARED_STATE.read().unwrap().block_kb_on_controller;
+    let next = Engine::from_config(config, native_keycode, block_kb_on_controller);
+    let actions = ENGINE.write().unwrap().replace_bindings(next);
+    send_output_actions(&actions);
     sync_keyboard();
     sync_controller();
 }
 
+fn send_output_actions(actions: &[OutputAction]) {
+    for action in actions {
+        debug!("Sending output action: {:?}", action);
+        match *action {
+            OutputAction::Keyboard { code, is_down } => send_keyboard_event(code as u16, is_down),
+            OutputAction::Button { button, is_down } => {
+                if let Some(event) = face_button_to_uinput_enum(button) {
+                    send_face_button_event(event, is_down);
+                }
+            }
+            OutputAction::Axis { axis, value } => {
+                let (event, value) = axis_to_uinput_position(axis, value);
+                send_position_event(event, value);
+            }
+            OutputAction::Hat { hat, value } => {
+                let event = match hat {
+                    engine::Hat::X => X0,
+                    engine::Hat::Y => Y0,
+                };
+                send_hat_event(event, value);
+            }
+        }
+    }
+}
+
 fn send_keyboard_event(key_code: u16, key_is_down: bool) {
+    // Mouse buttons bound as keys, or passing through, are clicked on the virtual mouse
+    if let Some(button) = evdev_enum_to_mouse_button(Key::new(key_code)) {
+        send_mouse_event(button, key_is_down as i32);
+        sync_mouse();
+        return;
+    }
+
     let mut shared_state = SHARED_STATE.write().unwrap();
     if let Some(uinput_code) = evdev_enum_to_uinput_enum(key_code) {
         if key_is_down {
@@ -838,42 +799,44 @@ fn send_face_button_event(button: UInputOutput, key_is_down: bool) {
     }
 }
 
-fn send_position_event(event: uinput::event::absolute::Position, value: i32, key_is_down: bool) {
+fn send_position_event(event: uinput::event::absolute::Position, value: i32) {
     let mut shared_state = SHARED_STATE.write().unwrap();
-    if key_is_down {
-        shared_state
-            .uinput_controller
-            .as_mut()
-            .unwrap()
-            .position(&event, value)
-            .unwrap();
-    } else {
-        shared_state
-            .uinput_controller
-            .as_mut()
-            .unwrap()
-            .position(&event, 0)
-            .unwrap();
-    }
+    shared_state
+        .uinput_controller
+        .as_mut()
+        .unwrap()
+        .position(&event, value)
+        .unwrap();
 }
 
-fn send_hat_event(event: uinput::event::absolute::Hat, value: i32, key_is_down: bool) {
+fn send_hat_event(event: uinput::event::absolute::Hat, value: i32) {
     let mut shared_state = SHARED_STATE.write().unwrap();
-    if key_is_down {
-        shared_state
-            .uinput_controller
-            .as_mut()
-            .unwrap()
-            .position(&event, value)
-            .unwrap();
-    } else {
-        shared_state
-            .uinput_controller
-            .as_mut()
-            .unwrap()
-            .position(&event, 0)
-            .unwrap();
-    }
+    shared_state
+        .uinput_controller
+        .as_mut()
+        .unwrap()
+        .position(&event, value)
+        .unwrap();
+}
+
+fn send_mouse_event(event: impl Into<uinput::Event>, value: i32) {
+    let mut shared_state = SHARED_STATE.write().unwrap();
+    shared_state
+        .uinput_mouse
+        .as_mut()
+        .unwrap()
+        .send(event, value)
+        .unwrap();
+}
+
+fn sync_mouse() {
+    let mut shared_state = SHARED_STATE.write().unwrap();
+    shared_state
+        .uinput_mouse
+        .as_mut()
+        .unwrap()
+        .synchronize()
+        .unwrap();
 }
 
 fn sync_keyboard() {
@@ -896,8 +859,347 @@ fn sync_controller() {
         .unwrap();
 }
 
+pub(crate) fn native_keycode(code: u32) -> Option<u32> {
+    match code {
+        WHEEL_UP_KEYCODE => Some(WHEEL_UP_CODE),
+        WHEEL_DOWN_KEYCODE => Some(WHEEL_DOWN_CODE),
+        _ => windows_code_to_evdev_enum(code).map(|key| key.code() as u32),
+    }
+}
+
+const VIRTUAL_DEVICE_PREFIX: &str = "Overbind Virtual ";
+
+const LETTER_KEYS: [Key; 26] = [
+    Key::KEY_A,
+    Key::KEY_B,
+    Key::KEY_C,
+    Key::KEY_D,
+    Key::KEY_E,
+    Key::KEY_F,
+    Key::KEY_G,
+    Key::KEY_H,
+    Key::KEY_I,
+    Key::KEY_J,
+    Key::KEY_K,
+    Key::KEY_L,
+    Key::KEY_M,
+    Key::KEY_N,
+    Key::KEY_O,
+    Key::KEY_P,
+    Key::KEY_Q,
+    Key::KEY_R,
+    Key::KEY_S,
+    Key::KEY_T,
+    Key::KEY_U,
+    Key::KEY_V,
+    Key::KEY_W,
+    Key::KEY_X,
+    Key::KEY_Y,
+    Key::KEY_Z,
+];
+
+fn create_virtual_gamepad() -> Result<UInputDevice, uinput::Error> {
+    uinput::default()?
+        .name("Overbind Virtual Gamepad")?
+        .event(Absolute(Position(X)))?
+        .min(-32768)
+        .max(32767)
+        .fuzz(0)
+        .flat(0)
+        .event(Absolute(Position(Y)))?
+        .min(-32768)
+        .max(32767)
+        .fuzz(0)
+        .flat(0)
+        .event(Absolute(Position(RX)))?
+        .min(-32768)
+        .max(32767)
+        .fuzz(0)
+        .flat(0)
+        .event(Absolute(Position(RY)))?
+        .min(-32768)
+        .max(32767)
+        .fuzz(0)
+        .flat(0)
+        .event(Absolute(Hat(X0)))?
+        .min(-1)
+        .max(1)
+        .fuzz(0)
+        .flat(0)
+        .event(Absolute(Hat(Y0)))?
+        .min(-1)
+        .max(1)
+        .fuzz(0)
+        .flat(0)
+        .event(Absolute(Position(Z)))?
+        .min(0)
+        .max(1023)
+        .fuzz(0)
+        .flat(0)
+        .event(Absolute(Position(RZ)))?
+        .min(0)
+        .max(1023)
+        .fuzz(0)
+        .flat(0)
+        .event(Controller(GamePad(North)))?
+        .event(Controller(GamePad(South)))?
+        .event(Controller(GamePad(East)))?
+        .event(Controller(GamePad(West)))?
+        .event(Controller(GamePad(TL)))?
+        .event(Controller(GamePad(TR)))?
+        .event(Controller(GamePad(ThumbL)))?
+        .event(Controller(GamePad(ThumbR)))?
+        .event(Controller(GamePad(Select)))?
+        .event(Controller(GamePad(Start)))?
+        .event(Controller(GamePad(Mode)))?
+        .create()
+}
+
+fn create_virtual_keyboard() -> Result<UInputDevice, uinput::Error> {
+    uinput::default()?
+        .name("Overbind Virtual Keyboard")?
+        .event(uinput::event::Keyboard::All)?
+        .create()
+}
+
+fn create_virtual_mouse() -> Result<UInputDevice, uinput::Error> {
+    uinput::default()?
+        .name("Overbind Virtual Mouse")?
+        .event(MouseButton::Left)?
+        .event(MouseButton::Right)?
+        .event(MouseButton::Middle)?
+        .event(MouseButton::Side)?
+        .event(MouseButton::Extra)?
+        .event(Relative::Position(RelativePosition::X))?
+        .event(Relative::Position(RelativePosition::Y))?
+        .event(Relative::Wheel(Wheel::Vertical))?
+        .event(Relative::Wheel(Wheel::Horizontal))?
+        .create()
+}
+
+// Every evdev device we are allowed to open
+pub(crate) fn list_input_devices() -> Vec<InputDeviceInfo> {
+    let links = device_links();
+    // The devices we grab ourselves are busy as well, that's not another program
+    let own_paths: Vec<String> = if SHOULD_RUN.load(Ordering::SeqCst) {
+        let shared_state = SHARED_STATE.read().unwrap();
+        shared_state
+            .device_paths
+            .iter()
+            .chain(&shared_state.mouse_device)
+            .map(|(_, path)| path.clone())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut devices: Vec<InputDeviceInfo> = evdev::enumerate()
+        .map(|(path, mut device)| {
+            let path_name = path.to_string_lossy().to_string();
+            let id = device_id(&links, &path);
+            let name = device.name().unwrap_or_default().to_owned();
+            let is_grabbed = !own_paths.contains(&path_name)
+                && match device.grab() {
+                    Ok(()) => {
+                        let _ = device.ungrab();
+                        false
+                    }
+                    Err(e) => e.raw_os_error() == Some(libc::EBUSY),
+                };
+
+            InputDeviceInfo {
+                id,
+                is_overbind: name.starts_with(VIRTUAL_DEVICE_PREFIX),
+                name,
+                vendor: device.input_id().vendor(),
+                product: device.input_id().product(),
+                phys: device.physical_path().map(str::to_owned),
+                path: path_name,
+                event_types: device
+                    .supported_events()
+                    .iter()
+                    .map(|event_type| format!("{:?}", event_type).to_lowercase())
+                    .collect(),
+                has_letter_keys: has_letter_keys(&device),
+                is_grabbed,
+            }
+        })
+        .collect();
+    devices.sort_by(|a, b| a.id.cmp(&b.id));
+    devices
+}
+
+// Devices without a link go by their event node's name
+fn device_id(links: &HashMap<PathBuf, String>, path: &Path) -> String {
+    links.get(path).cloned().unwrap_or_else(|| {
+        path.file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .to_string()
+    })
+}
+
+fn has_letter_keys(device: &Device) -> bool {
+    device
+        .supported_keys()
+        .is_some_and(|keys| LETTER_KEYS.iter().all(|&key| keys.contains(key)))
+}
+
+// Any key below the button range (BTN_MISC, BTN_0 in evdev) counts, so keypads and macro pads
+// without letters can be detected too
+fn has_keyboard_keys(device: &Device) -> bool {
+    device
+        .supported_keys()
+        .is_some_and(|keys| keys.iter().any(|key| key.code() < Key::BTN_0.code()))
+}
+
+// Waits for a key press on any keyboard and returns the id of the device it came from. Keyboards
+// are read without grabbing them, so the key still goes wherever it would have.
+pub(crate) fn detect_input_device(timeout: Duration) -> Result<String, String> {
+    if SHOULD_RUN.load(Ordering::SeqCst) {
+        return Err(
+            "Stop OverBind to detect a device, the devices it reads are grabbed".to_string(),
+        );
+    }
+
+    let links = device_links();
+    let mut keyboards: Vec<(PathBuf, Device)> = evdev::enumerate()
+        .filter(|(_, device)| {
+            has_keyboard_keys(device)
+                && !device
+                    .name()
+                    .is_some_and(|name| name.starts_with(VIRTUAL_DEVICE_PREFIX))
+        })
+        .collect();
+    if keyboards.is_empty() {
+        return Err("No keyboard could be opened, check the permissions of /dev/input".to_string());
+    }
+
+    let mut poll_fds: Vec<libc::pollfd> = keyboards
+        .iter()
+        .map(|(_, device)| {
+            let fd = device.as_raw_fd();
+            unsafe {
+                let flags = libc::fcntl(fd, libc::F_GETFL);
+                libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+            }
+            libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            }
+        })
+        .collect();
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(format!(
+                "No key was pressed within {} seconds",
+                timeout.as_secs()
+            ));
+        }
+        let timeout_ms = remaining.as_millis().min(i32::MAX as u128) as i32;
+        let ready = unsafe {
+            libc::poll(
+                poll_fds.as_mut_ptr(),
+                poll_fds.len() as libc::nfds_t,
+                timeout_ms,
+            )
+        };
+        if ready < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(format!("Failed to wait for a key press: {}", e));
+        }
+
+        for (poll_fd, (path, device)) in poll_fds.iter_mut().zip(&mut keyboards) {
+            if poll_fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+                // Unplugged while waiting, a negative fd is skipped by poll
+                poll_fd.fd = -1;
+                continue;
+            }
+            if poll_fd.revents & libc::POLLIN == 0 {
+                continue;
+            }
+            let Ok(mut events) = device.fetch_events() else {
+                continue;
+            };
+            // Only a press of a keyboard key counts, not releases, repeats or buttons
+            let is_key_press = events.any(|event| match event.kind() {
+                InputEventKind::Key(key) => event.value() == 1 && key.code() < Key::BTN_0.code(),
+                _ => false,
+            });
+            if is_key_press {
+                let id = device_id(&links, path);
+                info!("Detected key press on {:?}", id);
+                return Ok(id);
+            }
+        }
+    }
+}
+
+// The link name of each event node, by-id names win over by-path ones
+fn device_links() -> HashMap<PathBuf, String> {
+    let mut links = HashMap::new();
+    for dir in ["/dev/input/by-path", "/dev/input/by-id"] {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if let Ok(node) = fs::canonicalize(entry.path()) {
+                links.insert(node, entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+    links
+}
+
+// Device node of an input device listed by id, or by path when it has no id
+pub(crate) fn find_device_path(name: &str) -> Option<String> {
+    ["/dev/input/by-id", "/dev/input/by-path"]
+        .iter()
+        .map(|dir| Path::new(dir).join(name))
+        .find(|symlink_path| symlink_path.exists())
+        .and_then(|symlink_path| fs::read_link(symlink_path).ok())
+        .and_then(|input_path| {
+            let input_device = input_path.file_name()?;
+            info!("Found input device: {:?}", input_device);
+            Some(format!("/dev/input/{}", input_device.to_str()?))
+        })
+}
+
+fn evdev_enum_to_mouse_button(key: Key) -> Option<MouseButton> {
+    match key {
+        Key::BTN_LEFT => Some(MouseButton::Left),
+        Key::BTN_RIGHT => Some(MouseButton::Right),
+        Key::BTN_MIDDLE => Some(MouseButton::Middle),
+        Key::BTN_SIDE => Some(MouseButton::Side),
+        Key::BTN_EXTRA => Some(MouseButton::Extra),
+        _ => None,
+    }
+}
+
+fn evdev_enum_to_relative(axis: RelativeAxisType) -> Option<Relative> {
+    match axis {
+        RelativeAxisType::REL_X => Some(Relative::Position(RelativePosition::X)),
+        RelativeAxisType::REL_Y => Some(Relative::Position(RelativePosition::Y)),
+        RelativeAxisType::REL_WHEEL => Some(Relative::Wheel(Wheel::Vertical)),
+        RelativeAxisType::REL_HWHEEL => Some(Relative::Wheel(Wheel::Horizontal)),
+        _ => None,
+    }
+}
+
 fn windows_code_to_evdev_enum(code: u32) -> Option<Key> {
     match code {
+        0x01 => Some(Key::BTN_LEFT),
+        0x02 => Some(Key::BTN_RIGHT),
+        0x04 => Some(Key::BTN_MIDDLE),
+        0x05 => Some(Key::BTN_SIDE),
+        0x06 => Some(Key::BTN_EXTRA),
         0x08 => Some(Key::KEY_BACKSPACE),
         0x09 => Some(Key::KEY_TAB),
         0x0C => Some(Key::KEY_CLEAR),
@@ -1316,111 +1618,46 @@ fn windows_code_to_uinput_enum(code: u32) -> Option<UInputOutput> {
  * Right trigger -> 5 (ABS_RZ, type 3 EV_ABS) [0, 1023]
  */
 
-fn face_button_to_uinput_enum(code: u32) -> Option<UInputOutput> {
-    match code {
-        0x0010 => Some(UInputOutput::GamePad(
+fn face_button_to_uinput_enum(button: GamepadButton) -> Option<UInputOutput> {
+    match button {
+        GamepadButton::Start => Some(UInputOutput::GamePad(
             uinput::event::controller::GamePad::Start,
         )),
-        0x0020 => Some(UInputOutput::GamePad(
+        GamepadButton::Back => Some(UInputOutput::GamePad(
             uinput::event::controller::GamePad::Select,
         )),
-        0x0040 => Some(UInputOutput::GamePad(
+        GamepadButton::LeftThumb => Some(UInputOutput::GamePad(
             uinput::event::controller::GamePad::ThumbL,
         )),
-        0x0080 => Some(UInputOutput::GamePad(
+        GamepadButton::RightThumb => Some(UInputOutput::GamePad(
             uinput::event::controller::GamePad::ThumbR,
         )),
-        0x0100 => Some(UInputOutput::GamePad(
+        GamepadButton::LeftShoulder => Some(UInputOutput::GamePad(
             uinput::event::controller::GamePad::TL,
         )),
-        0x0200 => Some(UInputOutput::GamePad(
+        GamepadButton::RightShoulder => Some(UInputOutput::GamePad(
             uinput::event::controller::GamePad::TR,
         )),
-        0x0400 => Some(UInputOutput::GamePad(
+        GamepadButton::Guide => Some(UInputOutput::GamePad(
             uinput::event::controller::GamePad::Mode,
         )),
-        0x1000 => Some(UInputOutput::GamePad(uinput::event::controller::GamePad::A)),
-        0x2000 => Some(UInputOutput::GamePad(uinput::event::controller::GamePad::B)),
-        0x4000 => Some(UInputOutput::GamePad(uinput::event::controller::GamePad::X)),
-        0x8000 => Some(UInputOutput::GamePad(uinput::event::controller::GamePad::Y)),
-        _ => None,
-    }
-}
-
-fn dpad_button_to_abs_key(code: u32) -> Option<uinput::event::absolute::Hat> {
-    match code {
-        0x0001 => Some(uinput::event::absolute::Hat::Y0),
-        0x0002 => Some(uinput::event::absolute::Hat::Y0),
-        0x0004 => Some(uinput::event::absolute::Hat::X0),
-        0x0008 => Some(uinput::event::absolute::Hat::X0),
-        _ => None,
-    }
-}
-
-fn dpad_button_to_abs_value(code: u32) -> Option<i32> {
-    match code {
-        0x0001 => Some(-1),
-        0x0002 => Some(1),
-        0x0004 => Some(-1),
-        0x0008 => Some(1),
+        GamepadButton::A => Some(UInputOutput::GamePad(uinput::event::controller::GamePad::A)),
+        GamepadButton::B => Some(UInputOutput::GamePad(uinput::event::controller::GamePad::B)),
+        GamepadButton::X => Some(UInputOutput::GamePad(uinput::event::controller::GamePad::X)),
+        GamepadButton::Y => Some(UInputOutput::GamePad(uinput::event::controller::GamePad::Y)),
+        // The dpad is sent as hat events
         _ => None,
     }
 }
 
-fn dpad_button_opposite_key(code: u32) -> Option<u32> {
-    match code {
-        0x0001 => Some(0x0002),
-        0x0002 => Some(0x0001),
-        0x0004 => Some(0x0008),
-        0x0008 => Some(0x0004),
-        _ => None,
-    }
-}
-
-fn position_to_uinput_enum(result_type: &str) -> Option<uinput::event::absolute::Position> {
-    match result_type {
-        "thumb_lx" => Some(uinput::event::absolute::Position::X),
-        "thumb_ly" => Some(uinput::event::absolute::Position::Y),
-        "thumb_rx" => Some(uinput::event::absolute::Position::RX),
-        "thumb_ry" => Some(uinput::event::absolute::Position::RY),
-        "trigger_l" => Some(uinput::event::absolute::Position::Z),
-        "trigger_r" => Some(uinput::event::absolute::Position::RZ),
-        _ => None,
-    }
-}
-
-fn check_focus(conn: &impl Connection, atoms: &Atoms, root_window: Window) -> String {
-    let focus = conn
-        .get_property(
-            false,
-            root_window,
-            atoms._NET_ACTIVE_WINDOW,
-            AtomEnum::WINDOW,
-            0,
-            1,
-        )
-        .unwrap()
-        .reply()
-        .unwrap()
-        .value32()
-        .ok_or("_NET_ACTIVE_WINDOW has incorrect format")
-        .unwrap()
-        .next()
-        .ok_or("_NET_ACTIVE_WINDOW is empty")
-        .unwrap();
-
-    let wm_class = WmClass::get(conn, focus).unwrap();
-    let name = conn
-        .get_property(false, focus, atoms.WM_NAME, atoms.STRING, 0, 0x1000)
-        .unwrap();
-
-    let name: String = String::from_utf8(name.reply().unwrap_or_default().value).unwrap();
-    let wm_class = wm_class.reply().unwrap_or_default();
-    if wm_class.is_some() {
-        let wm_class = wm_class.unwrap();
-        let class = std::str::from_utf8(wm_class.class()).unwrap();
-        return class.to_string();
-    } else {
-        return name;
+// Converts engine values (XInput ranges) to the ranges the virtual gamepad was created with
+fn axis_to_uinput_position(axis: Axis, value: i32) -> (uinput::event::absolute::Position, i32) {
+    match axis {
+        Axis::LeftX => (uinput::event::absolute::Position::X, value),
+        Axis::LeftY => (uinput::event::absolute::Position::Y, -value),
+        Axis::RightX => (uinput::event::absolute::Position::RX, value),
+        Axis::RightY => (uinput::event::absolute::Position::RY, -value),
+        Axis::LeftTrigger => (uinput::event::absolute::Position::Z, value * 1023 / 255),
+        Axis::RightTrigger => (uinput::event::absolute::Position::RZ, value * 1023 / 255),
     }
 }
@@ -19,19 +19,16 @@ use core_graphics::event_source::CGEventSourceStateID;
 use io_kit_sys::ret::IOReturn;
 use io_kit_sys::types::IOOptionBits;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
-use std::cmp;
-use std::collections::HashMap;
 use std::ffi::c_void;
-use std::fs::File;
-use std::io::Read;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::Duration;
 
+use crate::engine::{Engine, OutputAction};
 use crate::key_interceptor::KeyInterceptorTrait;
-use crate::{get_config_path, Settings};
+use crate::macro_recorder::RecordedEvent;
+use crate::{load_config, Settings};
 
 pub type IOHIDUserDeviceRef = *mut c_void;
 extern "C" {
@@ -49,39 +46,7 @@ extern "C" {
     ) -> IOReturn;
 }
 
-#[derive(Debug, Deserialize)]
-struct ConfigJsonData {
-    keycode: String,
-    result_type: String,
-    result_value: i32,
-}
-
-struct KeyState {
-    is_pressed: bool,
-    result_type: String,
-    result_value: i64,
-}
-
-#[derive(Clone)]
-struct OppositeKey {
-    is_pressed: bool,
-    is_virtual_pressed: bool,
-    opposite_key_type: String,
-    opposite_key_value: i64,
-    opposite_key_mapping: Option<i64>,
-}
-
-static KEY_STATES: Lazy<Arc<RwLock<HashMap<i64, KeyState>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
-static OPPOSITE_KEY_STATES: Lazy<Arc<RwLock<HashMap<i64, OppositeKey>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
-static OPPOSITE_KEY_MAPPINGS: Lazy<Arc<RwLock<HashMap<i64, i64>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
-static DPAD_BUTTON_STATES: Lazy<Arc<RwLock<HashMap<u32, KeyState>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
+static ENGINE: Lazy<Arc<RwLock<Engine>>> = Lazy::new(|| Arc::new(RwLock::new(Engine::default())));
 
 struct SharedState {
     device: Option<IOHIDUserDeviceRef>,
@@ -130,11 +95,7 @@ impl KeyInterceptorTrait for MacKeyInterceptor {
 
     fn start(&mut self, app: &tauri::AppHandle) -> Result<(), String> {
         // Read keybindings from file
-        let path = get_config_path()?;
-        let mut file = File::open(path).map_err(|e| e.to_string())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|e| e.to_string())?;
+        let config = load_config()?;
 
         // const GAMEPAD_HID_DESCRIPTOR: &[u8] = &[
         //     0x05, 0x01, // Usage Page (Generic Desktop Controls)
@@ -213,127 +174,30 @@ impl KeyInterceptorTrait for MacKeyInterceptor {
         //     shared_state.device = Some(device);
         // }
 
-        let data: Vec<ConfigJsonData> =
-            serde_json::from_str(&contents).map_err(|e| e.to_string())?;
-        let mut key_states = HashMap::new();
-        let mut opposite_key_states = HashMap::new();
-        let mut opposite_key_mappings = HashMap::new();
-        let mut dpad_button_states = HashMap::new();
-
-        for item in &data {
-            let windows_keycode =
-                u32::from_str_radix(&item.keycode, 16).expect("Invalid hexadecimal string");
-            let keycode = windows_code_to_mac_keycode(windows_keycode).unwrap();
-
-            if item.result_type != "socd" {
-                let mut result_value = item.result_value as i64;
-                if item.result_type == "keyboard" {
-                    result_value = windows_code_to_mac_keycode(result_value as u32).unwrap();
-                }
-                if item.result_type == "thumb_lx"
-                    || item.result_type == "thumb_ly"
-                    || item.result_type == "thumb_rx"
-                    || item.result_type == "thumb_ry"
-                {
-                    // result_value = result_value * 32767 / 30000;
-                    if item.result_type == "thumb_ly" || item.result_type == "thumb_ry" {
-                        result_value = -result_value;
-                    }
-                }
-                if item.result_type == "trigger_l" || item.result_type == "trigger_r" {
-                    result_value = result_value * 1023 / 255;
-                }
-                if item.result_type == "face_button" && item.result_value < 0x0010 {
-                    let opposite_dpad =
-                        dpad_button_opposite_key(item.result_value as u32).expect(&format!(
-                            "Could not find opposite dpad button code: {}",
-                            item.result_value
-                        ));
-                    dpad_button_states
-                        .entry(item.result_value as u32)
-                        .or_insert_with(|| KeyState {
-                            is_pressed: false,
-                            result_type: item.result_type.clone(),
-                            result_value: opposite_dpad as i64,
-                        });
-                    println!(
-                        "DPad button code: {:?}, Opposite dpad button code: {:?}",
-                        item.result_value, opposite_dpad
-                    )
-                }
+        let block_kb_on_controller = SHARED_STATE.read().unwrap().block_kb_on_controller;
+        *ENGINE.write().unwrap() =
+            Engine::from_config(&config, native_keycode, block_kb_on_controller);
 
-                let key_state = key_states.entry(keycode).or_insert_with(|| KeyState {
-                    is_pressed: false,
-                    result_type: item.result_type.clone(),
-                    result_value: result_value,
-                });
-                println!(
-                    "Keycode: {:?}, ResultType: {:?}, ResultValue {:?}",
-                    keycode, key_state.result_type, key_state.result_value
-                );
-            }
-        }
+        SHOULD_RUN.store(true, Ordering::SeqCst);
 
-        for item in &data {
-            let windows_keycode =
-                u32::from_str_radix(&item.keycode, 16).expect("Invalid hexadecimal string");
-            let keycode = windows_code_to_mac_keycode(windows_keycode).unwrap();
-
-            if item.result_type == "socd" {
-                let windows_opposite_keycode = item.result_value as u16;
-                let opposite_keycode =
-                    windows_code_to_mac_keycode(windows_opposite_keycode.into()).unwrap();
-
-                let this_key_state_mapping = key_states.get(&keycode.clone());
-                let opposite_key_state_mapping = key_states.get(&opposite_keycode.clone());
-                let mut key_type = String::from("keyboard");
-                let mut opposite_key_mapping = None;
-
-                if this_key_state_mapping.is_some()
-                    && (this_key_state_mapping.unwrap().result_type == "keyboard")
-                {
-                    opposite_key_mappings.insert(
-                        keycode.clone(),
-                        this_key_state_mapping.unwrap().result_value as i64,
-                    );
-                } else {
-                    opposite_key_mappings.insert(keycode.clone(), keycode.clone());
+        // Sends the keyboard outputs that change with time between key events: chord windows,
+        // taps, turbo pulses and macros
+        thread::spawn(move || {
+            while SHOULD_RUN.load(Ordering::SeqCst) {
+                let interval = ENGINE.read().unwrap().next_tick_in();
+                thread::sleep(interval);
+                if !ENGINE.read().unwrap().needs_tick() {
+                    continue;
                 }
 
-                if opposite_key_state_mapping.is_some()
-                    && (opposite_key_state_mapping.unwrap().result_type == "keyboard"
-                        || opposite_key_state_mapping.unwrap().result_type == "face_button")
-                {
-                    key_type = opposite_key_state_mapping.unwrap().result_type.clone();
-                    opposite_key_mapping = Some(opposite_key_state_mapping.unwrap().result_value);
+                let actions = ENGINE.write().unwrap().tick();
+                for action in actions {
+                    if let OutputAction::Keyboard { code, is_down } = action {
+                        send_new_keyboard_event(code as i64, is_down);
+                    }
                 }
-
-                let opposite_key_state = opposite_key_states
-                    .entry(opposite_key_mappings.get(&keycode).unwrap().clone())
-                    .or_insert_with(|| OppositeKey {
-                        is_pressed: false,
-                        is_virtual_pressed: false,
-                        opposite_key_value: opposite_keycode,
-                        opposite_key_type: key_type,
-                        opposite_key_mapping,
-                    });
-
-                println!(
-                    "Keycode: {:?}, KeycodeMapping: {:?}, OppositeKeycode: {:?}, OppositeKeyMapping: {:?}",
-                    keycode,
-                    opposite_key_mappings.get(&keycode).unwrap(),
-                    opposite_key_state.opposite_key_value,
-                    opposite_key_state.opposite_key_mapping
-                );
             }
-        }
-
-        *KEY_STATES.write().unwrap() = key_states;
-        *OPPOSITE_KEY_STATES.write().unwrap() = opposite_key_states;
-        *OPPOSITE_KEY_MAPPINGS.write().unwrap() = opposite_key_mappings;
-        *DPAD_BUTTON_STATES.write().unwrap() = dpad_button_states;
-
-        SHOULD_RUN.store(true, Ordering::SeqCst);
+        });
 
         // Start listening for key events
         println!("Spawning key event listener thread");
@@ -390,11 +254,41 @@ impl KeyInterceptorTrait for MacKeyInterceptor {
 
     fn stop(&self, _app: &tauri::AppHandle) {
         SHOULD_RUN.store(false, Ordering::SeqCst);
+
+        let actions = ENGINE.write().unwrap().release_all();
+        for action in actions {
+            if let OutputAction::Keyboard { code, is_down } = action {
+                send_new_keyboard_event(code as i64, is_down);
+            }
+        }
+    }
+
+    fn reload_bindings(&self) -> Result<(), String> {
+        let config = load_config()?;
+        let block_kb_on_controller = SHARED_STATE.read().unwrap().block_kb_on_controller;
+        let next = Engine::from_config(&config, native_keycode, block_kb_on_controller);
+
+        println!("Reloading bindings");
+        let actions = ENGINE.write().unwrap().replace_bindings(next);
+        for action in actions {
+            if let OutputAction::Keyboard { code, is_down } = action {
+                send_new_keyboard_event(code as i64, is_down);
+            }
+        }
+        Ok(())
     }
 
     fn is_running(&self) -> bool {
         SHOULD_RUN.load(Ordering::SeqCst)
     }
+
+    fn start_recording(&self) -> Result<(), String> {
+        Err("Macro recording is only supported on Linux".to_string())
+    }
+
+    fn stop_recording(&self) -> Result<Vec<RecordedEvent>, String> {
+        Err("Macro recording is only supported on Linux".to_string())
+    }
 }
 
 fn handle_key_event(
@@ -422,141 +316,31 @@ fn handle_key_event(
         return Some(event.to_owned()); // Return the event without the help flag
     }
 
-    // Update Key State
-    {
-        let mut key_states = KEY_STATES.write().unwrap();
-        match key_states.get_mut(&key_code) {
-            Some(state) => state.is_pressed = key_is_down,
-            _ => (),
-        }
-    }
-    println!("Keycode: {:?}, Key is down: {:?}", key_code, key_is_down);
+    let output = ENGINE
+        .write()
+        .unwrap()
+        .handle_key(key_code as u32, key_is_down);
+    println!("Keycode: {:?}, Output: {:?}", key_code, output);
 
-    // SOCD
-    {
-        let mut opposite_key_states = OPPOSITE_KEY_STATES.write().unwrap();
-        let opposite_key_mappings = OPPOSITE_KEY_MAPPINGS.write().unwrap();
-
-        let cloned_key_state;
-        if opposite_key_mappings.contains_key(&key_code) {
-            {
-                let mapped_key = opposite_key_mappings.get(&key_code).unwrap();
-                let key_state = opposite_key_states.get_mut(&mapped_key).unwrap();
-                key_state.is_pressed = key_is_down;
-                key_state.is_virtual_pressed = key_is_down;
-
-                cloned_key_state = key_state.clone();
-            }
-
-            let opposite_key_value = cloned_key_state.opposite_key_value;
-            let mapping_opposite_key_state =
-                cloned_key_state
-                    .opposite_key_mapping
-                    .and_then(|opposite_key_mapping| {
-                        opposite_key_states.get_mut(&opposite_key_mapping)
-                    });
-            let opposite_key_state = match mapping_opposite_key_state {
-                Some(value) => value,
-                None => opposite_key_states.get_mut(&opposite_key_value).unwrap(),
-            };
-
-            if key_is_down && opposite_key_state.is_pressed && opposite_key_state.is_virtual_pressed
-            {
-                println!("Releasing opposite key");
-                opposite_key_state.is_virtual_pressed = false;
-                send_new_keyboard_event(cloned_key_state.opposite_key_value, false);
-            } else if !key_is_down && opposite_key_state.is_pressed {
-                println!("Repressing opposite key");
-                opposite_key_state.is_virtual_pressed = true;
-                send_new_keyboard_event(cloned_key_state.opposite_key_value, true);
-            }
-        }
-    }
-
-    // Rebinds
-    {
-        let key_states = KEY_STATES.read().unwrap();
-        if let Some(key_state) = key_states.get(&key_code) {
-            println!("Key state: {:?}", key_state.result_value);
-            if key_state.result_type == "keyboard" {
-                send_keyboard_event(event, key_state.result_value, key_is_down);
-                println!(
-                    "Sending keyboard event {:?}",
-                    event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE)
-                );
-                return Some(event.to_owned());
-            }
+    for action in &output.actions {
+        if let OutputAction::Keyboard { code, is_down } = *action {
+            send_new_keyboard_event(code as i64, is_down);
         }
     }
 
     // Controller
-
-    // Controller Rebinds
-    let mut face_buttons: u16 = 0;
-    let mut left_trigger: u8 = 0;
-    let mut right_trigger: u8 = 0;
-    let mut thumb_lx: i16 = 0;
-    let mut thumb_ly: i16 = 0;
-    let mut thumb_rx: i16 = 0;
-    let mut thumb_ry: i16 = 0;
-
-    {
-        let key_states = KEY_STATES.read().unwrap();
-        for (_, key_state) in key_states.iter().filter(|&(_, ks)| ks.is_pressed) {
-            match key_state.result_type.as_str() {
-                "face_button" => face_buttons = face_buttons | key_state.result_value as u16,
-                "trigger_l" => left_trigger = cmp::max(left_trigger, key_state.result_value as u8),
-                "trigger_r" => {
-                    right_trigger = cmp::max(right_trigger, key_state.result_value as u8)
-                }
-                "thumb_lx" => {
-                    thumb_lx = find_higher_priority(thumb_lx, key_state.result_value as i16)
-                }
-                "thumb_ly" => {
-                    thumb_ly = find_higher_priority(thumb_ly, key_state.result_value as i16)
-                }
-                "thumb_rx" => {
-                    thumb_rx = find_higher_priority(thumb_rx, key_state.result_value as i16)
-                }
-                "thumb_ry" => {
-                    thumb_ry = find_higher_priority(thumb_ry, key_state.result_value as i16)
-                }
-                _ => (),
-            }
-        }
-    }
-
-    {
-        let opposite_key_states = OPPOSITE_KEY_STATES.read().unwrap();
-        for (_, opposite_key_state) in opposite_key_states
-            .iter()
-            .filter(|&(_, ks)| ks.opposite_key_type == String::from("face_button"))
-        {
-            if let Some(opposite_key_mapping) = opposite_key_state.opposite_key_mapping {
-                let mask = opposite_key_mapping as u16;
-
-                if opposite_key_state.is_virtual_pressed {
-                    // If it's virtually pressed, set the specific bit
-                    face_buttons |= mask;
-                } else {
-                    // If it's virtually unpressed, clear the specific bit
-                    let clear_mask = !mask;
-                    face_buttons &= clear_mask;
-                }
-            }
-        }
-    }
-
+    let gamepad = *ENGINE.read().unwrap().gamepad();
+    let face_buttons = gamepad.xinput_buttons();
     let report = vec![
         1,
         (face_buttons & 0xFF) as u8,
         (face_buttons >> 8) as u8,
-        thumb_lx as u8,
-        thumb_ly as u8,
-        thumb_rx as u8,
-        thumb_ry as u8,
-        left_trigger,
-        right_trigger,
+        gamepad.thumb_lx as u8,
+        gamepad.thumb_ly as u8,
+        gamepad.thumb_rx as u8,
+        gamepad.thumb_ry as u8,
+        gamepad.left_trigger,
+        gamepad.right_trigger,
     ];
     {
         // let device = SHARED_STATE.read().unwrap().device.clone().unwrap();
@@ -573,7 +357,7 @@ fn handle_key_event(
         // }
     }
 
-    if SHARED_STATE.read().unwrap().block_kb_on_controller {
+    if output.block_input {
         event.set_type(CGEventType::Null);
         return Some(event.to_owned());
     }
@@ -581,16 +365,6 @@ fn handle_key_event(
     Some(event.to_owned())
 }
 
-fn send_keyboard_event(event: &CGEvent, key_code: i64, key_is_down: bool) {
-    let event_type = if key_is_down {
-        CGEventType::KeyDown
-    } else {
-        CGEventType::KeyUp
-    };
-    event.set_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE, key_code);
-    event.set_type(event_type);
-}
-
 fn send_new_keyboard_event(key_code: i64, key_is_down: bool) {
     let event_source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
         .expect("Failed to create event source");
@@ -600,42 +374,8 @@ fn send_new_keyboard_event(key_code: i64, key_is_down: bool) {
     new_event.post(CGEventTapLocation::HID);
 }
 
-fn dpad_button_opposite_key(code: u32) -> Option<u32> {
-    match code {
-        0x0001 => Some(0x0002),
-        0x0002 => Some(0x0001),
-        0x0004 => Some(0x0008),
-        0x0008 => Some(0x0004),
-        _ => None,
-    }
-}
-
-// Used for when multiple stick rebinds are set, first prioritizes right/up(positive) over left/down(negative), then larger values within those bands
-// neutral(zero) is the absolute lowest priority
-fn analog_priority_transform(n: i32) -> i32 {
-    if n < 0 {
-        return -1 * n;
-    }
-    if n > 0 {
-        return n + i16::MAX as i32;
-    }
-
-    0
-}
-
-fn find_higher_priority(num1: i16, num2: i16) -> i16 {
-    if num1 == 0 {
-        return num2;
-    }
-    if num2 == 0 {
-        return num1;
-    }
-
-    if analog_priority_transform(num1 as i32) >= analog_priority_transform(num2 as i32) {
-        return num1;
-    } else {
-        return num2;
-    }
+pub(crate) fn native_keycode(code: u32) -> Option<u32> {
+    windows_code_to_mac_keycode(code).map(|key| key as u32)
 }
 
 fn windows_code_to_mac_keycode(code: u32) -> Option<i64> {
@@ -0,0 +1,196 @@
+use crate::bindings::{BindingAction, MacroStep};
+use std::time::{Duration, SystemTime};
+
+// Highest windows virtual keycode, recorded keys are looked up among these
+const MAX_CONFIG_KEYCODE: u32 = 0xFE;
+
+// A key press or release captured from the input device, `keycode` is the platform's keycode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RecordedEvent {
+    pub keycode: u32,
+    pub is_down: bool,
+    // When the device reported the event, not when we got to read it
+    pub time: SystemTime,
+}
+
+// The config keycode that translates to a native keycode, the first one when several do
+pub(crate) fn config_keycode(native: u32, to_native: impl Fn(u32) -> Option<u32>) -> Option<u32> {
+    (1..=MAX_CONFIG_KEYCODE).find(|&code| to_native(code) == Some(native))
+}
+
+// Turns a recording into macro steps, delays are rounded to multiples of `quantize_ms` when set.
+// Releases of keys that were already held when recording started are dropped and keys still held
+// at the end are released, so playing the macro never leaves anything pressed
+pub(crate) fn macro_steps(
+    events: &[RecordedEvent],
+    quantize_ms: Option<u32>,
+    to_native: impl Fn(u32) -> Option<u32>,
+) -> Vec<MacroStep> {
+    let quantize_ms = quantize_ms.filter(|&ms| ms > 0).map(u64::from);
+    let mut steps = Vec::new();
+    let mut held = Vec::new();
+    let mut start = None;
+    let mut previous_offset = 0;
+
+    // Every device is read on its own thread, so events of different devices can be recorded out
+    // of order
+    let mut events = events.to_vec();
+    events.sort_by_key(|event| event.time);
+
+    for event in &events {
+        let Some(key) = config_keycode(event.keycode, &to_native) else {
+            continue;
+        };
+        if event.is_down {
+            if held.contains(&key) {
+                continue;
+            }
+            held.push(key);
+        } else {
+            match held.iter().position(|&held_key| held_key == key) {
+                Some(index) => held.remove(index),
+                None => continue,
+            };
+        }
+
+        let start = *start.get_or_insert(event.time);
+        let elapsed = event
+            .time
+            .duration_since(start)
+            .unwrap_or(Duration::ZERO)
+            .as_millis() as u64;
+        // Rounding the time since the start rather than each delay keeps errors from adding up
+        let offset = match quantize_ms {
+            Some(ms) => (elapsed + ms / 2) / ms * ms,
+            None => elapsed,
+        };
+        steps.push(MacroStep {
+            delay_ms: u32::try_from(offset - previous_offset).unwrap_or(u32::MAX),
+            action: BindingAction::Keyboard { key },
+            is_down: event.is_down,
+        });
+        previous_offset = offset;
+    }
+
+    for key in held {
+        steps.push(MacroStep {
+            delay_ms: 0,
+            action: BindingAction::Keyboard { key },
+            is_down: false,
+        });
+    }
+
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: u32 = 0x41;
+    const D: u32 = 0x44;
+    const NATIVE_A: u32 = 30;
+    const NATIVE_D: u32 = 32;
+
+    fn to_native(code: u32) -> Option<u32> {
+        match code {
+            A => Some(NATIVE_A),
+            D => Some(NATIVE_D),
+            _ => None,
+        }
+    }
+
+    fn event(keycode: u32, is_down: bool, ms: u64) -> RecordedEvent {
+        RecordedEvent {
+            keycode,
+            is_down,
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(ms),
+        }
+    }
+
+    fn step(key: u32, is_down: bool, delay_ms: u32) -> MacroStep {
+        MacroStep {
+            delay_ms,
+            action: BindingAction::Keyboard { key },
+            is_down,
+        }
+    }
+
+    #[test]
+    fn recording_keeps_timings() {
+        let events = [
+            event(NATIVE_A, true, 500),
+            event(NATIVE_D, true, 517),
+            event(NATIVE_A, false, 603),
+            event(NATIVE_D, false, 640),
+        ];
+
+        assert_eq!(
+            macro_steps(&events, None, to_native),
+            vec![
+                step(A, true, 0),
+                step(D, true, 17),
+                step(A, false, 86),
+                step(D, false, 37),
+            ]
+        );
+    }
+
+    #[test]
+    fn recording_quantizes_from_the_start() {
+        let events = [
+            event(NATIVE_A, true, 0),
+            event(NATIVE_A, false, 14),
+            event(NATIVE_D, true, 26),
+            event(NATIVE_D, false, 37),
+        ];
+
+        assert_eq!(
+            macro_steps(&events, Some(10), to_native),
+            vec![
+                step(A, true, 0),
+                step(A, false, 10),
+                step(D, true, 20),
+                step(D, false, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn recording_balances_presses_and_releases() {
+        let events = [
+            // Held before recording started
+            event(NATIVE_D, false, 0),
+            event(NATIVE_A, true, 10),
+            // Key repeat and an unknown key
+            event(NATIVE_A, true, 40),
+            event(99, true, 50),
+        ];
+
+        assert_eq!(
+            macro_steps(&events, None, to_native),
+            vec![step(A, true, 0), step(A, false, 0)]
+        );
+    }
+
+    #[test]
+    fn recording_orders_events_of_different_devices() {
+        let events = [
+            event(NATIVE_A, true, 500),
+            event(NATIVE_A, false, 600),
+            // Recorded late by another device's thread
+            event(NATIVE_D, true, 550),
+            event(NATIVE_D, false, 650),
+        ];
+
+        assert_eq!(
+            macro_steps(&events, None, to_native),
+            vec![
+                step(A, true, 0),
+                step(D, true, 50),
+                step(A, false, 50),
+                step(D, false, 50),
+            ]
+        );
+    }
+}
@@ -16,31 +16,38 @@ use tauri::menu::{MenuBuilder, MenuItemBuilder};
 #[cfg(target_os = "windows")]
 use windows_key_interceptor::WindowsKeyInterceptor;
 
-use log::info;
+use log::{error, info};
 use serde_json::Value;
 use std::fs::{self, File};
 use std::io::{BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::{env, panic};
+use std::time::Duration;
+use std::{env, panic, thread};
+mod bindings;
+mod device_watcher;
+mod engine;
+mod focus_providers;
 mod key_interceptor;
 mod linux_key_interceptor;
 mod mac_key_interceptor;
+mod macro_recorder;
+mod profiles;
+mod setup_diagnostics;
+mod validation;
+mod wayland_focus;
 mod windows_key_interceptor;
 
-use crate::key_interceptor::KeyInterceptorTrait;
-use serde::{Deserialize, Serialize};
+use crate::bindings::{BindingConfig, RecordedMacro, CONFIG_VERSION};
+use crate::key_interceptor::{native_keycode, InputDeviceInfo, KeyInterceptorTrait};
+use crate::profiles::ProfileList;
+use crate::setup_diagnostics::SetupCheck;
+use crate::validation::ConfigDiagnostic;
+use serde::Deserialize;
 use tauri::{Emitter, Manager, State, WebviewWindow};
 
 static WINDOW: Lazy<Arc<Mutex<Option<WebviewWindow>>>> = Lazy::new(|| Arc::new(Mutex::new(None)));
 
-#[derive(Serialize, Deserialize)]
-struct KeyConfig {
-    keycode: String,
-    result_type: String,
-    result_value: i32,
-}
-
 #[derive(Clone)]
 struct KeyInterceptorState(Arc<Mutex<Box<dyn KeyInterceptorTrait + Send>>>);
 
@@ -59,7 +66,10 @@ impl KeyInterceptorState {
         let interceptor_arc = Arc::new(Mutex::new(interceptor));
         {
             let mut interceptor = interceptor_arc.lock().unwrap();
-            interceptor.initialize(&settings).unwrap();
+            // A broken setup shouldn't keep the app from starting, starting interception reports it
+            if let Err(e) = interceptor.initialize(&settings) {
+                error!("Failed to initialize key interception: {}", e);
+            }
         }
         Self(interceptor_arc.clone())
     }
@@ -75,14 +85,38 @@ struct Settings {
     #[serde(skip_serializing_if = "Option::is_none")]
     selected_input: Option<String>,
     #[cfg(target_os = "linux")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    selected_inputs: Vec<String>,
+    #[cfg(target_os = "linux")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    selected_mouse: Option<String>,
+    #[cfg(target_os = "linux")]
     #[serde(default)]
     force_cursor: bool,
 }
 
+impl Settings {
+    // Devices to read keys from, settings saved before several could be selected have just one
+    #[cfg(target_os = "linux")]
+    fn input_devices(&self) -> Vec<String> {
+        if self.selected_inputs.is_empty() {
+            self.selected_input.iter().cloned().collect()
+        } else {
+            self.selected_inputs.clone()
+        }
+    }
+}
+
 #[derive(Clone)]
 struct AppSettingsState(Arc<Mutex<Settings>>);
 
-fn start_key_interception(app: &tauri::AppHandle, state: &State<KeyInterceptorState>) {
+fn start_key_interception(
+    app: &tauri::AppHandle,
+    state: &State<KeyInterceptorState>,
+) -> Result<(), String> {
+    let mut interceptor = state.0.lock().unwrap();
+    interceptor.start(app)?;
+
     let icon = Image::from_path(
         app.path()
             .resource_dir()
@@ -94,9 +128,7 @@ fn start_key_interception(app: &tauri::AppHandle, state: &State<KeyInterceptorSt
         .unwrap()
         .set_icon(Some(icon))
         .unwrap();
-
-    let mut interceptor = state.0.lock().unwrap();
-    let _ = interceptor.start(app).map_err(|e| e.to_string());
+    Ok(())
 }
 
 fn stop_key_interception(app: &tauri::AppHandle, state: &State<KeyInterceptorState>) {
@@ -116,6 +148,15 @@ fn stop_key_interception(app: &tauri::AppHandle, state: &State<KeyInterceptorSta
     interceptor.stop(app);
 }
 
+// Applies changed bindings to a running interceptor without stopping it
+fn reload_key_interception(state: &State<KeyInterceptorState>) -> Result<(), String> {
+    let interceptor = state.0.lock().unwrap();
+    if interceptor.is_running() {
+        interceptor.reload_bindings()?;
+    }
+    Ok(())
+}
+
 fn is_key_interception_running(state: &State<KeyInterceptorState>) -> bool {
     let interceptor = state.0.lock().unwrap();
     interceptor.is_running()
@@ -131,15 +172,23 @@ fn start_interception(
     {
         let settings = settings_state.0.lock().unwrap();
         let window = app.get_webview_window("main").unwrap();
-        if settings.selected_input == None {
+        if settings.input_devices().is_empty() {
             window.emit("settings_incomplete", true).unwrap();
         } else {
             window.emit("settings_incomplete", false).unwrap();
         }
+        if let Some(e) = setup_diagnostics::setup_error(&setup_diagnostics::run_checks(&settings)) {
+            return Err(e);
+        }
     }
-    start_key_interception(&app, &key_interceptor_state);
+    start_key_interception(&app, &key_interceptor_state)
+}
 
-    Ok(())
+// What the system is missing for OverBind to run
+#[tauri::command]
+fn check_setup(state: State<AppSettingsState>) -> Result<Vec<SetupCheck>, String> {
+    let settings = state.0.lock().unwrap();
+    Ok(setup_diagnostics::run_checks(&settings))
 }
 
 #[tauri::command]
@@ -152,6 +201,40 @@ fn is_interceptor_running(state: State<KeyInterceptorState>) -> bool {
     is_key_interception_running(&state)
 }
 
+#[tauri::command]
+fn start_recording(state: State<KeyInterceptorState>) -> Result<(), String> {
+    let interceptor = state.0.lock().unwrap();
+    interceptor.start_recording()
+}
+
+// Keeps the recording in the active profile, replacing a recorded macro with the same name
+#[tauri::command]
+fn stop_recording(
+    name: String,
+    quantize_ms: Option<u32>,
+    state: State<KeyInterceptorState>,
+) -> Result<RecordedMacro, String> {
+    // Checked first so the recording keeps going and can still be saved under a name
+    if name.trim().is_empty() {
+        return Err("The recorded macro needs a name".to_string());
+    }
+    let events = state.0.lock().unwrap().stop_recording()?;
+
+    let recorded = RecordedMacro {
+        name,
+        steps: macro_recorder::macro_steps(&events, quantize_ms, native_keycode),
+    };
+    let profile = profiles::active_profile()?;
+    let mut config = profiles::load_profile(&profile)?;
+    config
+        .recorded_macros
+        .retain(|existing| existing.name != recorded.name);
+    config.recorded_macros.push(recorded.clone());
+    profiles::save_profile(&profile, &config)?;
+
+    Ok(recorded)
+}
+
 fn get_config_path() -> Result<PathBuf, String> {
     match dirs::data_dir() {
         Some(mut path) => {
@@ -164,30 +247,125 @@ fn get_config_path() -> Result<PathBuf, String> {
     }
 }
 
+// Bindings of the active profile
+fn load_config() -> Result<BindingConfig, String> {
+    profiles::load_active_profile()
+}
+
 #[tauri::command]
-fn read_config() -> Result<Vec<KeyConfig>, String> {
-    let config_path = get_config_path()?;
-    let file = File::open(config_path).map_err(|e| e.to_string())?;
-    let reader = BufReader::new(file);
+fn read_config() -> Result<BindingConfig, String> {
+    load_config()
+}
 
-    let configs: Vec<KeyConfig> = serde_json::from_reader(reader).map_err(|e| e.to_string())?;
+#[tauri::command]
+fn validate_config(config: BindingConfig) -> Vec<ConfigDiagnostic> {
+    validation::validate_config(&config, native_keycode)
+}
 
-    Ok(configs)
+#[tauri::command]
+fn save_config(mut config: BindingConfig, state: State<KeyInterceptorState>) -> Result<(), String> {
+    let diagnostics = validation::validate_config(&config, native_keycode);
+    if validation::has_errors(&diagnostics) {
+        return Err(validation::error_summary(&diagnostics));
+    }
+
+    // Always written in the current format
+    config.version = CONFIG_VERSION;
+
+    profiles::save_profile(&profiles::active_profile()?, &config)?;
+    reload_key_interception(&state)
 }
 
 #[tauri::command]
-fn save_config(configs: Vec<KeyConfig>) -> Result<(), String> {
-    let config_path = get_config_path()?;
+fn list_profiles() -> Result<ProfileList, String> {
+    profiles::list_profiles()
+}
 
-    let mut file = File::create(config_path).map_err(|e| e.to_string())?;
+#[tauri::command]
+fn create_profile(name: String, copy_from: Option<String>) -> Result<(), String> {
+    profiles::create_profile(&name, copy_from.as_deref())
+}
 
-    let json = serde_json::to_string_pretty(&configs).map_err(|e| e.to_string())?;
+#[tauri::command]
+fn rename_profile(
+    name: String,
+    new_name: String,
+    state: State<KeyInterceptorState>,
+) -> Result<(), String> {
+    profiles::rename_profile(&name, &new_name)?;
+    reload_key_interception(&state)
+}
 
-    file.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+#[tauri::command]
+fn delete_profile(name: String, state: State<KeyInterceptorState>) -> Result<(), String> {
+    profiles::delete_profile(&name)?;
+    reload_key_interception(&state)
+}
 
+#[tauri::command]
+fn activate_profile(name: String, state: State<KeyInterceptorState>) -> Result<(), String> {
+    profiles::activate_profile(&name)?;
+    reload_key_interception(&state)
+}
+
+#[tauri::command]
+fn set_profile_programs(
+    name: String,
+    programs: Vec<String>,
+    state: State<KeyInterceptorState>,
+) -> Result<(), String> {
+    profiles::set_profile_programs(&name, programs)?;
+    reload_key_interception(&state)
+}
+
+// Profiles edited outside of OverBind haven't been through save_config's validation, checks the
+// ones the bindings are loaded from
+fn check_profiles() -> Result<(), String> {
+    let list = profiles::list_profiles()?;
+    let mut configs = vec![(list.active.clone(), profiles::load_profile(&list.active)?)];
+    for name in list.programs.into_values() {
+        // Program profiles that don't load are skipped when the bindings are loaded as well
+        if let Ok(config) = profiles::load_profile(&name) {
+            configs.push((name, config));
+        }
+    }
+
+    for (name, config) in configs {
+        let diagnostics = validation::validate_config(&config, native_keycode);
+        if validation::has_errors(&diagnostics) {
+            return Err(format!(
+                "Profile {:?}: {}",
+                name,
+                validation::error_summary(&diagnostics)
+            ));
+        }
+    }
     Ok(())
 }
 
+// Picks up profiles edited outside of OverBind, saves made through the UI reload directly
+fn watch_profiles(app: tauri::AppHandle) {
+    // Only changes from here on need a reload
+    profiles::changed_on_disk();
+    loop {
+        thread::sleep(Duration::from_secs(1));
+
+        if !profiles::changed_on_disk() {
+            continue;
+        }
+
+        info!("Profiles changed on disk");
+        let state = app.state::<KeyInterceptorState>();
+        // Invalid bindings are never swapped in, the current ones stay until the profile is fixed
+        if let Err(e) = check_profiles().and_then(|_| reload_key_interception(&state)) {
+            error!("Failed to reload bindings: {}", e);
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.emit("bindings_reload_failed", e);
+            }
+        }
+    }
+}
+
 fn ensure_config_file_exists() -> Result<(), String> {
     let config_path = get_config_path()?;
     if !config_path.exists() {
@@ -259,6 +437,32 @@ fn save_app_settings(settings: Value, state: State<AppSettingsState>) -> Result<
     Ok(())
 }
 
+#[tauri::command]
+fn list_input_devices() -> Result<Vec<InputDeviceInfo>, String> {
+    #[cfg(target_os = "linux")]
+    return Ok(linux_key_interceptor::list_input_devices());
+
+    #[cfg(not(target_os = "linux"))]
+    Ok(Vec::new())
+}
+
+// Waits for a key press and returns the id of the keyboard it came from
+#[tauri::command]
+async fn detect_input_device(timeout_ms: u64) -> Result<String, String> {
+    #[cfg(target_os = "linux")]
+    return tauri::async_runtime::spawn_blocking(move || {
+        linux_key_interceptor::detect_input_device(Duration::from_millis(timeout_ms))
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = timeout_ms;
+        Err("Detecting the input device is only supported on Linux".to_string())
+    }
+}
+
 #[tauri::command]
 fn list_inputs() -> Result<Vec<String>, String> {
     let mut inputs = Vec::new();
@@ -314,6 +518,7 @@ fn main() {
         .plugin(tauri_plugin_shell::init());
 
     let _ = ensure_config_file_exists();
+    let _ = profiles::ensure_profiles_exist();
     let _ = ensure_settings_file_exists();
 
     let settings_json = read_settings().unwrap();
@@ -401,7 +606,10 @@ fn main() {
                     }
                     "enable" => {
                         let state = app.state::<KeyInterceptorState>();
-                        start_key_interception(app, &state);
+                        if let Err(e) = start_key_interception(app, &state) {
+                            error!("Failed to start key interception: {}", e);
+                            return;
+                        }
 
                         let window = app.get_webview_window("main").unwrap();
                         window.emit("tray_intercept_enable", "").unwrap();
@@ -424,6 +632,9 @@ fn main() {
                 .expect("Failed to get resource dir");
             std::env::set_var("OVERBIND_RESOURCE_DIR", resource_dir);
 
+            let app_handle = app.handle().clone();
+            thread::spawn(move || watch_profiles(app_handle));
+
             Ok(())
         })
         .plugin(tauri_plugin_window_state::Builder::default().build());
@@ -433,13 +644,25 @@ fn main() {
         .manage(settings_state)
         .invoke_handler(tauri::generate_handler![
             read_config,
+            validate_config,
             save_config,
+            list_profiles,
+            create_profile,
+            rename_profile,
+            delete_profile,
+            activate_profile,
+            set_profile_programs,
             read_app_settings,
             save_app_settings,
             start_interception,
             stop_interception,
             is_interceptor_running,
+            start_recording,
+            stop_recording,
             list_inputs,
+            list_input_devices,
+            detect_input_device,
+            check_setup,
         ]);
 
     builder = builder.on_window_event(|window, event| match event {
@@ -0,0 +1,622 @@
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+#[cfg(test)]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use crate::bindings::{self, BindingConfig};
+
+const DEFAULT_PROFILE: &str = "Default";
+const LEGACY_CONFIG_FILE: &str = "OverBind_conf.json";
+
+type Snapshot = Vec<(PathBuf, SystemTime)>;
+
+// Profiles as last seen by the watcher or as left by OverBind's own last write
+static KNOWN_SNAPSHOT: Lazy<Arc<RwLock<Option<Snapshot>>>> =
+    Lazy::new(|| Arc::new(RwLock::new(None)));
+
+#[cfg(test)]
+thread_local! {
+    // Tests keep their profiles in a temporary directory instead of the user data directory
+    static OVERBIND_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProfileIndex {
+    active: String,
+    // Program name, as written in allowed_programs, to the profile used while it is focused
+    #[serde(default)]
+    programs: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ProfileList {
+    pub profiles: Vec<String>,
+    pub active: String,
+    pub programs: HashMap<String, String>,
+}
+
+fn get_overbind_dir() -> Result<PathBuf, String> {
+    #[cfg(test)]
+    if let Some(path) = OVERBIND_DIR.with(|dir| dir.borrow().clone()) {
+        fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+        return Ok(path);
+    }
+
+    match dirs::data_dir() {
+        Some(mut path) => {
+            path.push("OverBind");
+            fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+            Ok(path)
+        }
+        None => Err("Failed to get user data directory".into()),
+    }
+}
+
+fn get_profiles_dir() -> Result<PathBuf, String> {
+    let path = get_overbind_dir()?.join("profiles");
+    fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+fn get_index_path() -> Result<PathBuf, String> {
+    Ok(get_overbind_dir()?.join("OverBind_profiles.json"))
+}
+
+// Profile names become file names, so only allow characters that are safe on every platform
+fn check_profile_name(name: &str) -> Result<(), String> {
+    let is_valid = !name.trim().is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.' | '(' | ')'));
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid profile name {:?}, use letters, numbers, spaces and - _ . ( )",
+            name
+        ))
+    }
+}
+
+fn profile_path(name: &str) -> Result<PathBuf, String> {
+    check_profile_name(name)?;
+    Ok(get_profiles_dir()?.join(format!("{}.json", name)))
+}
+
+fn read_index() -> Result<ProfileIndex, String> {
+    let contents = fs::read_to_string(get_index_path()?).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+// Written to a temporary file first so the config watcher never reads a half written file
+fn write_atomic(path: &Path, contents: &str) -> Result<(), String> {
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, contents).map_err(|e| e.to_string())?;
+    fs::rename(&temp_path, path).map_err(|e| e.to_string())
+}
+
+fn write_index(index: &ProfileIndex) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(index).map_err(|e| e.to_string())?;
+    write_atomic(&get_index_path()?, &json)?;
+    remember_snapshot();
+    Ok(())
+}
+
+fn profile_exists(name: &str) -> Result<bool, String> {
+    Ok(profile_path(name)?.exists())
+}
+
+pub(crate) fn load_profile(name: &str) -> Result<BindingConfig, String> {
+    let contents = fs::read_to_string(profile_path(name)?)
+        .map_err(|e| format!("Failed to read profile {:?}: {}", name, e))?;
+    bindings::parse_config(&contents).map_err(|e| format!("Profile {:?}: {}", name, e))
+}
+
+pub(crate) fn save_profile(name: &str, config: &BindingConfig) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    write_atomic(&profile_path(name)?, &json)?;
+    remember_snapshot();
+    Ok(())
+}
+
+// The single config file used before profiles existed becomes the "Default" profile
+pub(crate) fn ensure_profiles_exist() -> Result<(), String> {
+    if get_index_path()?.exists() {
+        return Ok(());
+    }
+
+    let config = match fs::read_to_string(get_overbind_dir()?.join(LEGACY_CONFIG_FILE)) {
+        Ok(contents) => bindings::parse_config(&contents).unwrap_or_else(|e| {
+            warn!("Existing config could not be migrated to a profile: {}", e);
+            BindingConfig::default()
+        }),
+        Err(_) => BindingConfig::default(),
+    };
+
+    if !profile_exists(DEFAULT_PROFILE)? {
+        save_profile(DEFAULT_PROFILE, &config)?;
+    }
+    info!("Created the {:?} profile", DEFAULT_PROFILE);
+
+    write_index(&ProfileIndex {
+        active: DEFAULT_PROFILE.to_string(),
+        programs: HashMap::new(),
+    })
+}
+
+pub(crate) fn list_profiles() -> Result<ProfileList, String> {
+    let index = read_index()?;
+
+    let mut profiles = Vec::new();
+    for entry in fs::read_dir(get_profiles_dir()?).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            if let Some(name) = path.file_stem() {
+                profiles.push(name.to_string_lossy().to_string());
+            }
+        }
+    }
+    profiles.sort();
+
+    Ok(ProfileList {
+        profiles,
+        active: index.active,
+        programs: index.programs,
+    })
+}
+
+// Modification times of the index and every profile, compared to notice changes made outside OverBind
+fn snapshot() -> Result<Snapshot, String> {
+    let mut paths = vec![get_index_path()?];
+    for entry in fs::read_dir(get_profiles_dir()?).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            paths.push(path);
+        }
+    }
+
+    let mut snapshot = Vec::new();
+    for path in paths {
+        if let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+            snapshot.push((path, modified));
+        }
+    }
+    snapshot.sort();
+    Ok(snapshot)
+}
+
+// Our own writes are reloaded by whoever made them, the watcher shouldn't reload them again
+fn remember_snapshot() {
+    *KNOWN_SNAPSHOT.write().unwrap() = snapshot().ok();
+}
+
+// Whether the profiles changed on disk since the last call or OverBind's own last write
+pub(crate) fn changed_on_disk() -> bool {
+    let snapshot = snapshot().ok();
+    let mut known = KNOWN_SNAPSHOT.write().unwrap();
+    if *known == snapshot {
+        return false;
+    }
+    *known = snapshot;
+    true
+}
+
+pub(crate) fn active_profile() -> Result<String, String> {
+    Ok(read_index()?.active)
+}
+
+pub(crate) fn load_active_profile() -> Result<BindingConfig, String> {
+    load_profile(&active_profile()?)
+}
+
+pub(crate) fn create_profile(name: &str, copy_from: Option<&str>) -> Result<(), String> {
+    if profile_exists(name)? {
+        return Err(format!("Profile {:?} already exists", name));
+    }
+
+    let config = match copy_from {
+        Some(source) => load_profile(source)?,
+        None => BindingConfig::default(),
+    };
+    save_profile(name, &config)
+}
+
+pub(crate) fn rename_profile(name: &str, new_name: &str) -> Result<(), String> {
+    if !profile_exists(name)? {
+        return Err(format!("Profile {:?} does not exist", name));
+    }
+    if profile_exists(new_name)? {
+        return Err(format!("Profile {:?} already exists", new_name));
+    }
+
+    fs::rename(profile_path(name)?, profile_path(new_name)?).map_err(|e| e.to_string())?;
+
+    let mut index = read_index()?;
+    if index.active == name {
+        index.active = new_name.to_string();
+    }
+    for profile in index.programs.values_mut() {
+        if profile == name {
+            *profile = new_name.to_string();
+        }
+    }
+    write_index(&index)
+}
+
+pub(crate) fn delete_profile(name: &str) -> Result<(), String> {
+    let list = list_profiles()?;
+    if !list.profiles.iter().any(|profile| profile == name) {
+        return Err(format!("Profile {:?} does not exist", name));
+    }
+    if list.profiles.len() == 1 {
+        return Err("The last profile can't be deleted".into());
+    }
+
+    fs::remove_file(profile_path(name)?).map_err(|e| e.to_string())?;
+
+    let mut index = read_index()?;
+    index.programs.retain(|_, profile| profile != name);
+    if index.active == name {
+        index.active = list
+            .profiles
+            .into_iter()
+            .find(|profile| profile != name)
+            .unwrap();
+    }
+    write_index(&index)
+}
+
+pub(crate) fn activate_profile(name: &str) -> Result<(), String> {
+    if !profile_exists(name)? {
+        return Err(format!("Profile {:?} does not exist", name));
+    }
+
+    let mut index = read_index()?;
+    index.active = name.to_string();
+    write_index(&index)
+}
+
+// Replaces the programs that select this profile, a program can only select one profile
+pub(crate) fn set_profile_programs(name: &str, programs: Vec<String>) -> Result<(), String> {
+    if !profile_exists(name)? {
+        return Err(format!("Profile {:?} does not exist", name));
+    }
+
+    let mut index = read_index()?;
+    index.programs.retain(|_, profile| profile != name);
+    for program in programs {
+        index.programs.insert(program, name.to_string());
+    }
+    write_index(&index)
+}
+
+// Profiles loaded when interception starts, the focused program decides which one is applied
+pub(crate) struct ProfileSet {
+    active: String,
+    programs: HashMap<String, String>,
+    configs: HashMap<String, BindingConfig>,
+    current: String,
+}
+
+impl ProfileSet {
+    pub(crate) fn load() -> Result<Self, String> {
+        let index = read_index()?;
+
+        let mut configs = HashMap::new();
+        configs.insert(index.active.clone(), load_profile(&index.active)?);
+
+        let mut programs = HashMap::new();
+        for (program, profile) in index.programs {
+            if !configs.contains_key(&profile) {
+                match load_profile(&profile) {
+                    Ok(config) => {
+                        configs.insert(profile.clone(), config);
+                    }
+                    Err(e) => {
+                        warn!("Ignoring profile for {:?}: {}", program, e);
+                        continue;
+                    }
+                }
+            }
+            programs.insert(program, profile);
+        }
+
+        Ok(Self {
+            current: index.active.clone(),
+            active: index.active,
+            programs,
+            configs,
+        })
+    }
+
+    pub(crate) fn current(&self) -> &BindingConfig {
+        &self.configs[&self.current]
+    }
+
+    // Program names are matched like allowed_programs, an exact match wins over a partial one
+    fn profile_for(&self, app_name: Option<&str>) -> &str {
+        let Some(app_name) = app_name else {
+            return &self.active;
+        };
+
+        self.programs
+            .get(app_name)
+            .or_else(|| {
+                self.programs
+                    .iter()
+                    .filter(|(program, _)| app_name.contains(program.as_str()))
+                    .max_by_key(|(program, _)| program.len())
+                    .map(|(_, profile)| profile)
+            })
+            .unwrap_or(&self.active)
+    }
+
+    // Switches to the profile of the focused program, returns its bindings if the profile changed
+    pub(crate) fn select(&mut self, app_name: Option<&str>) -> Option<&BindingConfig> {
+        let profile = self.profile_for(app_name).to_string();
+        if profile == self.current {
+            return None;
+        }
+
+        info!("Switching to profile {:?}", profile);
+        self.current = profile;
+        Some(self.current())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::{Binding, BindingAction, GamepadButton};
+
+    // Keeps this test's profiles in a fresh temporary directory until dropped
+    struct DataDir(PathBuf);
+
+    impl DataDir {
+        fn new(test: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("overbind-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            OVERBIND_DIR.with(|dir| *dir.borrow_mut() = Some(path.clone()));
+            DataDir(path)
+        }
+    }
+
+    impl Drop for DataDir {
+        fn drop(&mut self) {
+            OVERBIND_DIR.with(|dir| *dir.borrow_mut() = None);
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn config_with_binding(keycode: u32) -> BindingConfig {
+        BindingConfig {
+            bindings: vec![Binding {
+                keycode,
+                chord: Vec::new(),
+                device: None,
+                action: BindingAction::GamepadButton {
+                    button: GamepadButton::A,
+                },
+            }],
+            ..Default::default()
+        }
+    }
+
+    // A Default profile plus the given ones, each with a binding on its own key
+    fn create_profiles(names: &[&str]) {
+        ensure_profiles_exist().unwrap();
+        for (i, name) in names.iter().enumerate() {
+            save_profile(name, &config_with_binding(0x41 + i as u32)).unwrap();
+        }
+    }
+
+    fn profile_names() -> Vec<String> {
+        list_profiles().unwrap().profiles
+    }
+
+    #[test]
+    fn legacy_config_becomes_the_default_profile() {
+        let data_dir = DataDir::new("legacy");
+        let legacy = serde_json::to_string(&config_with_binding(0x41)).unwrap();
+        fs::write(data_dir.0.join(LEGACY_CONFIG_FILE), legacy).unwrap();
+
+        ensure_profiles_exist().unwrap();
+        assert_eq!(profile_names(), vec!["Default"]);
+        assert_eq!(active_profile().unwrap(), "Default");
+        assert_eq!(load_active_profile().unwrap().bindings[0].keycode, 0x41);
+
+        // Only the first start migrates, later changes to the profile are kept
+        save_profile("Default", &BindingConfig::default()).unwrap();
+        ensure_profiles_exist().unwrap();
+        assert!(load_active_profile().unwrap().bindings.is_empty());
+    }
+
+    #[test]
+    fn missing_or_broken_legacy_config_gives_an_empty_default_profile() {
+        let _data_dir = DataDir::new("no-legacy");
+        ensure_profiles_exist().unwrap();
+        assert!(load_active_profile().unwrap().bindings.is_empty());
+
+        let data_dir = DataDir::new("broken-legacy");
+        fs::write(data_dir.0.join(LEGACY_CONFIG_FILE), "not json").unwrap();
+        ensure_profiles_exist().unwrap();
+        assert!(load_active_profile().unwrap().bindings.is_empty());
+    }
+
+    #[test]
+    fn create_profile_copies_or_starts_empty() {
+        let _data_dir = DataDir::new("create");
+        create_profiles(&[]);
+        save_profile("Default", &config_with_binding(0x41)).unwrap();
+
+        create_profile("Racing", Some("Default")).unwrap();
+        create_profile("Empty", None).unwrap();
+        assert_eq!(profile_names(), vec!["Default", "Empty", "Racing"]);
+        assert_eq!(load_profile("Racing").unwrap().bindings[0].keycode, 0x41);
+        assert!(load_profile("Empty").unwrap().bindings.is_empty());
+        assert_eq!(active_profile().unwrap(), "Default");
+
+        assert!(create_profile("Racing", None).is_err());
+        assert!(create_profile("Copy", Some("Missing")).is_err());
+        assert_eq!(profile_names(), vec!["Default", "Empty", "Racing"]);
+    }
+
+    #[test]
+    fn profile_names_have_to_be_safe_file_names() {
+        let _data_dir = DataDir::new("names");
+        create_profiles(&[]);
+        for name in ["", " ", "../Default", "a/b", ".hidden", &"a".repeat(65)] {
+            assert!(create_profile(name, None).is_err(), "{:?}", name);
+        }
+        create_profile("Game (2) - v1.5_b", None).unwrap();
+    }
+
+    #[test]
+    fn rename_profile_follows_the_active_profile_and_programs() {
+        let _data_dir = DataDir::new("rename");
+        create_profiles(&["Racing"]);
+        set_profile_programs("Racing", vec!["game.exe".to_string()]).unwrap();
+
+        rename_profile("Racing", "Driving").unwrap();
+        let list = list_profiles().unwrap();
+        assert_eq!(list.profiles, vec!["Default", "Driving"]);
+        assert_eq!(list.active, "Default");
+        assert_eq!(list.programs["game.exe"], "Driving");
+        assert_eq!(load_profile("Driving").unwrap().bindings[0].keycode, 0x41);
+
+        rename_profile("Default", "Desktop").unwrap();
+        assert_eq!(active_profile().unwrap(), "Desktop");
+    }
+
+    #[test]
+    fn rename_profile_rejects_missing_and_duplicate_names() {
+        let _data_dir = DataDir::new("rename-duplicate");
+        create_profiles(&["Racing"]);
+
+        assert!(rename_profile("Missing", "Other").is_err());
+        assert!(rename_profile("Racing", "Default").is_err());
+        assert!(rename_profile("Racing", "../Racing").is_err());
+        assert_eq!(profile_names(), vec!["Default", "Racing"]);
+        assert_eq!(load_profile("Racing").unwrap().bindings[0].keycode, 0x41);
+    }
+
+    #[test]
+    fn delete_profile_drops_its_programs() {
+        let _data_dir = DataDir::new("delete");
+        create_profiles(&["Racing"]);
+        set_profile_programs("Racing", vec!["game.exe".to_string()]).unwrap();
+
+        delete_profile("Racing").unwrap();
+        let list = list_profiles().unwrap();
+        assert_eq!(list.profiles, vec!["Default"]);
+        assert_eq!(list.active, "Default");
+        assert!(list.programs.is_empty());
+
+        assert!(delete_profile("Racing").is_err());
+    }
+
+    #[test]
+    fn deleting_the_active_profile_activates_another() {
+        let _data_dir = DataDir::new("delete-active");
+        create_profiles(&["Racing"]);
+
+        delete_profile("Default").unwrap();
+        assert_eq!(active_profile().unwrap(), "Racing");
+        assert_eq!(load_active_profile().unwrap().bindings[0].keycode, 0x41);
+
+        assert!(delete_profile("Racing").is_err());
+        assert_eq!(profile_names(), vec!["Racing"]);
+    }
+
+    #[test]
+    fn activate_profile_only_accepts_existing_profiles() {
+        let _data_dir = DataDir::new("activate");
+        create_profiles(&["Racing"]);
+
+        activate_profile("Racing").unwrap();
+        assert_eq!(active_profile().unwrap(), "Racing");
+        assert!(activate_profile("Missing").is_err());
+        assert_eq!(active_profile().unwrap(), "Racing");
+    }
+
+    #[test]
+    fn a_program_selects_only_one_profile() {
+        let _data_dir = DataDir::new("programs");
+        create_profiles(&["Racing", "Flying"]);
+        set_profile_programs("Racing", vec!["game".to_string(), "other".to_string()]).unwrap();
+        set_profile_programs("Flying", vec!["game".to_string()]).unwrap();
+
+        let programs = list_profiles().unwrap().programs;
+        assert_eq!(programs["game"], "Flying");
+        assert_eq!(programs["other"], "Racing");
+
+        set_profile_programs("Racing", Vec::new()).unwrap();
+        assert!(!list_profiles().unwrap().programs.contains_key("other"));
+        assert!(set_profile_programs("Missing", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn profile_for_matches_programs_like_allowed_programs() {
+        let _data_dir = DataDir::new("profile-for");
+        create_profiles(&["Racing", "Flying", "Launcher"]);
+        set_profile_programs("Racing", vec!["game".to_string()]).unwrap();
+        set_profile_programs("Flying", vec!["game_flight".to_string()]).unwrap();
+        set_profile_programs("Launcher", vec!["game_flight.exe".to_string()]).unwrap();
+
+        let profiles = ProfileSet::load().unwrap();
+        assert_eq!(profiles.profile_for(None), "Default");
+        assert_eq!(profiles.profile_for(Some("browser")), "Default");
+        assert_eq!(profiles.profile_for(Some("game.exe")), "Racing");
+        // The longest partial match wins
+        assert_eq!(profiles.profile_for(Some("game_flight_x64")), "Flying");
+        // An exact match wins over everything else
+        assert_eq!(profiles.profile_for(Some("game_flight.exe")), "Launcher");
+    }
+
+    #[test]
+    fn select_only_returns_bindings_when_the_profile_changes() {
+        let _data_dir = DataDir::new("select");
+        create_profiles(&["Racing"]);
+        set_profile_programs("Racing", vec!["game.exe".to_string()]).unwrap();
+
+        let mut profiles = ProfileSet::load().unwrap();
+        assert!(profiles.current().bindings.is_empty());
+        assert!(profiles.select(Some("browser")).is_none());
+
+        let config = profiles.select(Some("game.exe")).unwrap();
+        assert_eq!(config.bindings[0].keycode, 0x41);
+        assert!(profiles.select(Some("game.exe")).is_none());
+        assert_eq!(profiles.current().bindings[0].keycode, 0x41);
+
+        assert!(profiles.select(None).unwrap().bindings.is_empty());
+    }
+
+    #[test]
+    fn profile_set_ignores_programs_with_broken_profiles() {
+        let data_dir = DataDir::new("broken-profile");
+        create_profiles(&["Racing"]);
+        set_profile_programs("Racing", vec!["game.exe".to_string()]).unwrap();
+        fs::write(data_dir.0.join("profiles").join("Racing.json"), "not json").unwrap();
+
+        let mut profiles = ProfileSet::load().unwrap();
+        assert_eq!(profiles.profile_for(Some("game.exe")), "Default");
+        assert!(profiles.select(Some("game.exe")).is_none());
+    }
+}
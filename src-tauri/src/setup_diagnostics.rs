@@ -0,0 +1,246 @@
+use crate::validation::Severity;
+use crate::Settings;
+use serde::Serialize;
+
+// One thing OverBind needs from the system, `fix` says what to do when it is missing
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SetupCheck {
+    pub name: String,
+    pub passed: bool,
+    // How bad a failed check is, errors keep OverBind from running
+    pub severity: Severity,
+    pub message: String,
+    pub fix: Option<String>,
+}
+
+impl SetupCheck {
+    fn passed(name: &str, message: String) -> Self {
+        SetupCheck {
+            name: name.to_string(),
+            passed: true,
+            severity: Severity::Warning,
+            message,
+            fix: None,
+        }
+    }
+
+    fn failed(name: &str, severity: Severity, message: String, fix: &str) -> Self {
+        SetupCheck {
+            name: name.to_string(),
+            passed: false,
+            severity,
+            message,
+            fix: Some(fix.to_string()),
+        }
+    }
+}
+
+// Checks everything the interceptor needs before it is started, there is nothing to check on
+// platforms other than Linux
+pub(crate) fn run_checks(settings: &Settings) -> Vec<SetupCheck> {
+    #[cfg(target_os = "linux")]
+    return linux::run_checks(settings);
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = settings;
+        Vec::new()
+    }
+}
+
+// Explains why the setup keeps OverBind from running, None when nothing does
+pub(crate) fn setup_error(checks: &[SetupCheck]) -> Option<String> {
+    let failures: Vec<&str> = checks
+        .iter()
+        .filter(|check| !check.passed && check.severity == Severity::Error)
+        .map(|check| check.message.as_str())
+        .collect();
+    if failures.is_empty() {
+        None
+    } else {
+        Some(failures.join("\n"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::SetupCheck;
+    use crate::focus_providers::{self, BRIDGE_PROVIDER};
+    use crate::linux_key_interceptor::find_device_path;
+    use crate::validation::Severity;
+    use crate::Settings;
+    use std::ffi::CString;
+    use std::fs::{File, OpenOptions};
+    use std::path::Path;
+
+    const UINPUT_PATH: &str = "/dev/uinput";
+    const UINPUT_RULE: &str = "Add a udev rule like KERNEL==\"uinput\", GROUP=\"input\", \
+        MODE=\"0660\" in /etc/udev/rules.d/99-overbind.rules, then reload the rules or reboot";
+    const INPUT_GROUP: &str = "input";
+
+    pub(super) fn run_checks(settings: &Settings) -> Vec<SetupCheck> {
+        let mut checks = vec![uinput_module(), uinput_device(), uinput_access()];
+        checks.push(input_group());
+        for device in settings.input_devices() {
+            checks.push(device_access("input_device", &device));
+        }
+        if let Some(mouse) = &settings.selected_mouse {
+            checks.push(device_access("mouse_device", mouse));
+        }
+        checks.push(focus_provider());
+        checks
+    }
+
+    fn uinput_module() -> SetupCheck {
+        // Built into the kernel it has no module, but it still registers its misc device
+        let is_loaded = Path::new("/sys/module/uinput").exists()
+            || Path::new("/sys/devices/virtual/misc/uinput").exists();
+        if is_loaded {
+            SetupCheck::passed(
+                "uinput_module",
+                "The uinput kernel module is loaded".to_string(),
+            )
+        } else {
+            SetupCheck::failed(
+                "uinput_module",
+                Severity::Error,
+                "The uinput kernel module isn't loaded".to_string(),
+                "Run `sudo modprobe uinput`, and add uinput to /etc/modules-load.d/ to load it \
+                on boot",
+            )
+        }
+    }
+
+    fn uinput_device() -> SetupCheck {
+        if Path::new(UINPUT_PATH).exists() {
+            SetupCheck::passed("uinput_device", format!("{} exists", UINPUT_PATH))
+        } else {
+            SetupCheck::failed(
+                "uinput_device",
+                Severity::Error,
+                format!("{} doesn't exist", UINPUT_PATH),
+                "Load the uinput kernel module with `sudo modprobe uinput`",
+            )
+        }
+    }
+
+    fn uinput_access() -> SetupCheck {
+        match OpenOptions::new().write(true).open(UINPUT_PATH) {
+            Ok(_) => SetupCheck::passed(
+                "uinput_access",
+                format!("Virtual devices can be created through {}", UINPUT_PATH),
+            ),
+            Err(e) => SetupCheck::failed(
+                "uinput_access",
+                Severity::Error,
+                format!(
+                    "Can't open {} to create virtual devices: {}",
+                    UINPUT_PATH, e
+                ),
+                UINPUT_RULE,
+            ),
+        }
+    }
+
+    fn input_group() -> SetupCheck {
+        let name = CString::new(INPUT_GROUP).unwrap();
+        let group = unsafe { libc::getgrnam(name.as_ptr()) };
+        if group.is_null() {
+            return SetupCheck::passed(
+                "input_group",
+                format!(
+                    "There is no {} group, device access is up to udev",
+                    INPUT_GROUP
+                ),
+            );
+        }
+        let gid = unsafe { (*group).gr_gid };
+
+        let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+        let mut groups = vec![0; count.max(0) as usize];
+        let count = unsafe { libc::getgroups(groups.len() as i32, groups.as_mut_ptr()) };
+        groups.truncate(count.max(0) as usize);
+
+        if groups.contains(&gid) || unsafe { libc::getegid() } == gid {
+            SetupCheck::passed(
+                "input_group",
+                format!("The user is in the {} group", INPUT_GROUP),
+            )
+        } else {
+            // Devices can still be readable through udev ACLs, the device checks tell for sure
+            SetupCheck::failed(
+                "input_group",
+                Severity::Warning,
+                format!("The user isn't in the {} group", INPUT_GROUP),
+                "Run `sudo usermod -aG input $USER`, then log out and back in",
+            )
+        }
+    }
+
+    fn device_access(name: &str, device: &str) -> SetupCheck {
+        let Some(path) = find_device_path(device) else {
+            // It is picked up once it is plugged in, like a device that got unplugged
+            return SetupCheck::failed(
+                name,
+                Severity::Warning,
+                format!("{} isn't connected", device),
+                "Plug the device in or pick another one in Settings",
+            );
+        };
+        match File::open(&path) {
+            Ok(_) => SetupCheck::passed(name, format!("{} ({}) can be read", device, path)),
+            Err(e) => SetupCheck::failed(
+                name,
+                Severity::Error,
+                format!("Can't read {} ({}): {}", device, path, e),
+                "Add the user to the input group with `sudo usermod -aG input $USER`, then log \
+                out and back in",
+            ),
+        }
+    }
+
+    fn focus_provider() -> SetupCheck {
+        let mut failures = Vec::new();
+        for (name, connect) in focus_providers::candidates() {
+            if name == BRIDGE_PROVIDER {
+                // Connecting would take the bridge's calls away from a running interceptor
+                if focus_providers::has_bridge_called() {
+                    return SetupCheck::passed(
+                        "focus_provider",
+                        format!("The focused program is followed through the {}", name),
+                    );
+                }
+                return SetupCheck::failed(
+                    "focus_provider",
+                    Severity::Warning,
+                    "The focus bridge hasn't reported the focused program yet, bindings apply in \
+                    every program until it does"
+                        .to_string(),
+                    "Install the KWin script or GNOME Shell extension from focus-bridge/, start \
+                    OverBind and switch to another window once",
+                );
+            }
+            match connect() {
+                Ok(_) => {
+                    return SetupCheck::passed(
+                        "focus_provider",
+                        format!("The focused program is followed through {}", name),
+                    )
+                }
+                Err(e) => failures.push(format!("{}: {}", name, e)),
+            }
+        }
+        let mut message =
+            "Nothing can follow the focused program, bindings apply in every program".to_string();
+        if !failures.is_empty() {
+            message = format!("{} ({})", message, failures.join(", "));
+        }
+        SetupCheck::failed(
+            "focus_provider",
+            Severity::Warning,
+            message,
+            "Allowed programs and per-program profiles need an X11, Sway, Hyprland, wlroots, KDE \
+            or GNOME session",
+        )
+    }
+}
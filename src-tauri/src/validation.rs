@@ -0,0 +1,1124 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::bindings::{
+    Binding, BindingAction, BindingConfig, MacroMode, RampCurve, SocdMode, StickNormalization,
+    CONFIG_VERSION, WHEEL_DOWN_KEYCODE, WHEEL_UP_KEYCODE,
+};
+use crate::engine::TICK_INTERVAL;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Severity {
+    // The binding can't be applied, saving is refused
+    Error,
+    // The binding is applied but probably doesn't do what was intended
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct ConfigDiagnostic {
+    // Position in `layers` for bindings of a layer, None for the base bindings
+    pub layer: Option<usize>,
+    // Position in the layer's bindings, None for problems with the config as a whole
+    pub index: Option<usize>,
+    pub field: String,
+    pub reason: String,
+    pub severity: Severity,
+}
+
+impl ConfigDiagnostic {
+    fn new(index: Option<usize>, field: &str, reason: String, severity: Severity) -> Self {
+        Self {
+            layer: None,
+            index,
+            field: field.to_string(),
+            reason,
+            severity,
+        }
+    }
+
+    fn in_layer(mut self, layer: Option<usize>) -> Self {
+        self.layer = layer;
+        self
+    }
+}
+
+pub(crate) fn has_errors(diagnostics: &[ConfigDiagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+// One line per error, used when a command has to refuse a config
+pub(crate) fn error_summary(diagnostics: &[ConfigDiagnostic]) -> String {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(|diagnostic| {
+            let layer = match diagnostic.layer {
+                Some(layer) => format!("Layer {} ", layer + 1),
+                None => String::new(),
+            };
+            match diagnostic.index {
+                Some(index) => format!(
+                    "{}Binding {} ({}): {}",
+                    layer,
+                    index + 1,
+                    diagnostic.field,
+                    diagnostic.reason
+                ),
+                None => format!("{}{}: {}", layer, diagnostic.field, diagnostic.reason),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Mode of the SOCD binding from `keycode` back to `opposite`, if there is one
+// The mode of the SOCD binding pairing `keycode` back to `opposite`, pairs are made per device
+fn socd_partner_mode(
+    config: &BindingConfig,
+    keycode: u32,
+    opposite: u32,
+    device: Option<&str>,
+) -> Option<SocdMode> {
+    config
+        .bindings
+        .iter()
+        .find_map(|binding| match binding.action {
+            BindingAction::Socd {
+                opposite: partner,
+                mode,
+            } if binding.keycode == keycode
+                && partner == opposite
+                && binding.device.as_deref() == device =>
+            {
+                Some(mode)
+            }
+            _ => None,
+        })
+}
+
+fn key_error(index: usize, field: &str, code: u32) -> ConfigDiagnostic {
+    let reason = if code == 0 {
+        "No key is set".to_string()
+    } else {
+        format!("Key 0x{:02X} is not supported on this platform", code)
+    };
+    ConfigDiagnostic::new(Some(index), field, reason, Severity::Error)
+}
+
+// Problem with a key that is sent as an output
+fn output_key_error(
+    index: usize,
+    field: &str,
+    key: u32,
+    to_native: impl Fn(u32) -> Option<u32>,
+) -> Option<ConfigDiagnostic> {
+    if to_native(key).is_none() {
+        return Some(key_error(index, field, key));
+    }
+    if key == WHEEL_UP_KEYCODE || key == WHEEL_DOWN_KEYCODE {
+        return Some(ConfigDiagnostic::new(
+            Some(index),
+            field,
+            "The mouse wheel can only be pressed, it can't be sent".to_string(),
+            Severity::Error,
+        ));
+    }
+    None
+}
+
+// Problem with an action inside a tap/hold, turbo or macro binding, these can only hold plain
+// actions
+fn nested_action_diagnostic(
+    config: &BindingConfig,
+    index: usize,
+    field: &str,
+    action: &BindingAction,
+    to_native: impl Fn(u32) -> Option<u32>,
+) -> Option<ConfigDiagnostic> {
+    let reason = match *action {
+        BindingAction::TapHold { .. }
+        | BindingAction::Turbo { .. }
+        | BindingAction::Macro { .. }
+        | BindingAction::Socd { .. } => {
+            "Can't be used inside a tap/hold, turbo or macro binding".to_string()
+        }
+        BindingAction::Keyboard { key } => {
+            return output_key_error(index, &format!("{}.key", field), key, to_native);
+        }
+        BindingAction::LayerHold { layer: ref name }
+        | BindingAction::LayerToggle { layer: ref name }
+            if !config.layers.iter().any(|layer| layer.name == *name) =>
+        {
+            format!("There is no layer named {:?}", name)
+        }
+        _ => return None,
+    };
+    Some(ConfigDiagnostic::new(
+        Some(index),
+        field,
+        reason,
+        Severity::Error,
+    ))
+}
+
+// Checks one layer's bindings, `layer` is None for the base bindings
+fn validate_bindings(
+    config: &BindingConfig,
+    bindings: &[Binding],
+    layer: Option<usize>,
+    to_native: impl Fn(u32) -> Option<u32>,
+    diagnostics: &mut Vec<ConfigDiagnostic>,
+) {
+    let first_new = diagnostics.len();
+
+    // First binding for each key or chord of each device, later ones are ignored by the engine
+    let mut output_owners: HashMap<(Option<&str>, Vec<u32>), usize> = HashMap::new();
+    let mut socd_owners: HashMap<(Option<&str>, Vec<u32>), usize> = HashMap::new();
+
+    for (index, binding) in bindings.iter().enumerate() {
+        if to_native(binding.keycode).is_none() {
+            diagnostics.push(key_error(index, "keycode", binding.keycode));
+        }
+        for &key in &binding.chord {
+            if to_native(key).is_none() {
+                diagnostics.push(key_error(index, "chord", key));
+            } else if key == binding.keycode {
+                diagnostics.push(ConfigDiagnostic::new(
+                    Some(index),
+                    "chord",
+                    "A chord can't contain its own key twice".to_string(),
+                    Severity::Warning,
+                ));
+            }
+        }
+
+        match binding.device.as_deref() {
+            Some("") => diagnostics.push(ConfigDiagnostic::new(
+                Some(index),
+                "device",
+                "The device name is empty".to_string(),
+                Severity::Error,
+            )),
+            Some(_) if !binding.chord.is_empty() => diagnostics.push(ConfigDiagnostic::new(
+                Some(index),
+                "device",
+                "Chords can't be limited to a device".to_string(),
+                Severity::Error,
+            )),
+            _ => (),
+        }
+
+        match binding.action {
+            BindingAction::Socd { .. } if !binding.chord.is_empty() => {
+                diagnostics.push(ConfigDiagnostic::new(
+                    Some(index),
+                    "chord",
+                    "SOCD bindings can't be chords".to_string(),
+                    Severity::Error,
+                ));
+            }
+            BindingAction::Socd { .. } if layer.is_some() => {
+                diagnostics.push(ConfigDiagnostic::new(
+                    Some(index),
+                    "action",
+                    "SOCD bindings only apply on the base layer, this binding is ignored"
+                        .to_string(),
+                    Severity::Warning,
+                ));
+            }
+            BindingAction::LayerHold { layer: ref name }
+            | BindingAction::LayerToggle { layer: ref name }
+                if !config.layers.iter().any(|layer| layer.name == *name) =>
+            {
+                diagnostics.push(ConfigDiagnostic::new(
+                    Some(index),
+                    "action.layer",
+                    format!("There is no layer named {:?}", name),
+                    Severity::Error,
+                ));
+            }
+            BindingAction::Keyboard { key } => {
+                diagnostics.extend(output_key_error(index, "action.key", key, &to_native));
+            }
+            BindingAction::Socd { opposite, mode } => {
+                if to_native(opposite).is_none() {
+                    diagnostics.push(key_error(index, "action.opposite", opposite));
+                } else if opposite == binding.keycode {
+                    diagnostics.push(ConfigDiagnostic::new(
+                        Some(index),
+                        "action.opposite",
+                        "A key can't be its own SOCD opposite".to_string(),
+                        Severity::Error,
+                    ));
+                } else {
+                    match socd_partner_mode(
+                        config,
+                        opposite,
+                        binding.keycode,
+                        binding.device.as_deref(),
+                    ) {
+                        None => diagnostics.push(ConfigDiagnostic::new(
+                            Some(index),
+                            "action.opposite",
+                            format!(
+                                "SOCD partner 0x{:02X} has no SOCD binding back to 0x{:02X}{}",
+                                opposite,
+                                binding.keycode,
+                                if binding.device.is_some() {
+                                    " on the same device"
+                                } else {
+                                    ""
+                                }
+                            ),
+                            Severity::Warning,
+                        )),
+                        Some(partner_mode) if partner_mode != mode => {
+                            diagnostics.push(ConfigDiagnostic::new(
+                                Some(index),
+                                "action.mode",
+                                format!(
+                                    "SOCD mode differs from the binding of partner 0x{:02X}",
+                                    opposite
+                                ),
+                                Severity::Warning,
+                            ))
+                        }
+                        Some(_) => (),
+                    }
+                }
+
+                if let SocdMode::Priority(key) = mode {
+                    if key != binding.keycode && key != opposite {
+                        diagnostics.push(ConfigDiagnostic::new(
+                            Some(index),
+                            "action.mode",
+                            format!("Priority key 0x{:02X} is not part of this SOCD pair", key),
+                            Severity::Error,
+                        ));
+                    }
+                }
+            }
+            BindingAction::Axis {
+                ramp: Some(ramp), ..
+            } if ramp.curve == RampCurve::Stepped(0) => {
+                diagnostics.push(ConfigDiagnostic::new(
+                    Some(index),
+                    "action.ramp.curve",
+                    "A stepped ramp needs at least one step".to_string(),
+                    Severity::Error,
+                ));
+            }
+            BindingAction::TapHold {
+                ref tap, ref hold, ..
+            } => {
+                for (field, action) in [("action.tap", tap), ("action.hold", hold)] {
+                    diagnostics.extend(nested_action_diagnostic(
+                        config, index, field, action, &to_native,
+                    ));
+                }
+            }
+            BindingAction::Macro { ref steps, mode } => {
+                for (step, macro_step) in steps.iter().enumerate() {
+                    diagnostics.extend(nested_action_diagnostic(
+                        config,
+                        index,
+                        &format!("action.steps.{}.action", step),
+                        &macro_step.action,
+                        &to_native,
+                    ));
+                }
+                if steps.is_empty() {
+                    diagnostics.push(ConfigDiagnostic::new(
+                        Some(index),
+                        "action.steps",
+                        "A macro without steps does nothing".to_string(),
+                        Severity::Warning,
+                    ));
+                } else if mode == MacroMode::RepeatWhileHeld
+                    && steps.iter().all(|step| step.delay_ms == 0)
+                {
+                    diagnostics.push(ConfigDiagnostic::new(
+                        Some(index),
+                        "action.steps",
+                        "A repeating macro without delays repeats as fast as it can".to_string(),
+                        Severity::Warning,
+                    ));
+                }
+            }
+            BindingAction::Turbo {
+                ref action,
+                rate_hz,
+                duty_percent,
+            } => {
+                diagnostics.extend(nested_action_diagnostic(
+                    config,
+                    index,
+                    "action.action",
+                    action,
+                    &to_native,
+                ));
+                if rate_hz == 0 {
+                    diagnostics.push(ConfigDiagnostic::new(
+                        Some(index),
+                        "action.rate_hz",
+                        "A turbo rate of 0 never pulses".to_string(),
+                        Severity::Error,
+                    ));
+                } else if u128::from(rate_hz) * TICK_INTERVAL.as_millis() * 2 > 1000 {
+                    diagnostics.push(ConfigDiagnostic::new(
+                        Some(index),
+                        "action.rate_hz",
+                        format!(
+                            "Turbo rates above {} Hz can't be kept",
+                            500 / TICK_INTERVAL.as_millis()
+                        ),
+                        Severity::Warning,
+                    ));
+                }
+                if duty_percent == 0 || duty_percent >= 100 {
+                    diagnostics.push(ConfigDiagnostic::new(
+                        Some(index),
+                        "action.duty_percent",
+                        "A duty cycle outside 1-99% doesn't pulse".to_string(),
+                        Severity::Warning,
+                    ));
+                }
+            }
+            BindingAction::StickScale { percent: 100 } => {
+                diagnostics.push(ConfigDiagnostic::new(
+                    Some(index),
+                    "action.percent",
+                    "A scale of 100% has no effect".to_string(),
+                    Severity::Warning,
+                ));
+            }
+            BindingAction::Axis { value: 0, .. } | BindingAction::Trigger { value: 0, .. } => {
+                diagnostics.push(ConfigDiagnostic::new(
+                    Some(index),
+                    "action.value",
+                    "A value of 0 has no effect".to_string(),
+                    Severity::Warning,
+                ));
+            }
+            _ => (),
+        }
+
+        let owners = match binding.action {
+            BindingAction::Socd { .. } => &mut socd_owners,
+            _ => &mut output_owners,
+        };
+        let mut keys: Vec<u32> = std::iter::once(binding.keycode)
+            .chain(binding.chord.iter().copied())
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let keys = (binding.device.as_deref(), keys);
+        if let Some(&owner) = owners.get(&keys) {
+            diagnostics.push(ConfigDiagnostic::new(
+                Some(index),
+                "keycode",
+                format!(
+                    "Key is already bound by binding {}, this binding is ignored",
+                    owner + 1
+                ),
+                Severity::Warning,
+            ));
+        } else {
+            owners.insert(keys, index);
+        }
+    }
+
+    for diagnostic in &mut diagnostics[first_new..] {
+        diagnostic.layer = layer;
+    }
+}
+
+// Checks everything the interceptors would otherwise only notice when they start. `to_native` is
+// the same keycode translation the backend uses to build its engine.
+pub(crate) fn validate_config(
+    config: &BindingConfig,
+    to_native: impl Fn(u32) -> Option<u32>,
+) -> Vec<ConfigDiagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if config.version > CONFIG_VERSION {
+        diagnostics.push(ConfigDiagnostic::new(
+            None,
+            "version",
+            format!(
+                "Config version {} is newer than the supported version {}",
+                config.version, CONFIG_VERSION
+            ),
+            Severity::Error,
+        ));
+    }
+
+    validate_bindings(config, &config.bindings, None, &to_native, &mut diagnostics);
+
+    let mut layer_names: HashMap<&str, usize> = HashMap::new();
+    for (index, layer) in config.layers.iter().enumerate() {
+        let name_error = if layer.name.trim().is_empty() {
+            Some("Layer has no name".to_string())
+        } else if let Some(&owner) = layer_names.get(layer.name.as_str()) {
+            Some(format!("Name is already used by layer {}", owner + 1))
+        } else {
+            layer_names.insert(&layer.name, index);
+            None
+        };
+        if let Some(reason) = name_error {
+            diagnostics.push(
+                ConfigDiagnostic::new(None, "name", reason, Severity::Error).in_layer(Some(index)),
+            );
+        }
+
+        validate_bindings(
+            config,
+            &layer.bindings,
+            Some(index),
+            &to_native,
+            &mut diagnostics,
+        );
+    }
+
+    let has_chords = config
+        .bindings
+        .iter()
+        .chain(config.layers.iter().flat_map(|layer| &layer.bindings))
+        .any(|binding| !binding.chord.is_empty());
+    if has_chords && config.chord_window_ms == 0 {
+        diagnostics.push(ConfigDiagnostic::new(
+            None,
+            "chord_window_ms",
+            "A chord window of 0 ms leaves no time to press a chord".to_string(),
+            Severity::Warning,
+        ));
+    }
+
+    if let Some(mouse_look) = config.mouse_look {
+        if mouse_look.sensitivity == 0 {
+            diagnostics.push(ConfigDiagnostic::new(
+                None,
+                "mouse_look.sensitivity",
+                "A sensitivity of 0 never moves the stick".to_string(),
+                Severity::Warning,
+            ));
+        }
+        if mouse_look.deadzone_percent >= 100 {
+            diagnostics.push(ConfigDiagnostic::new(
+                None,
+                "mouse_look.deadzone_percent",
+                "The deadzone has to be below 100%".to_string(),
+                Severity::Error,
+            ));
+        }
+    }
+
+    for (axis, mode) in &config.axis_socd {
+        if let SocdMode::Priority(key) = *mode {
+            let mut bindings = config
+                .bindings
+                .iter()
+                .chain(config.layers.iter().flat_map(|layer| &layer.bindings));
+            let is_bound = bindings.any(|binding| match binding.action {
+                BindingAction::Axis { axis: bound, .. } => binding.keycode == key && bound == *axis,
+                _ => false,
+            });
+            if !is_bound {
+                diagnostics.push(ConfigDiagnostic::new(
+                    None,
+                    "axis_socd",
+                    format!("Priority key 0x{:02X} is not bound to {:?}", key, axis),
+                    Severity::Error,
+                ));
+            }
+        }
+    }
+
+    for (stick, normalization) in &config.stick_normalization {
+        if let StickNormalization::Diagonal(percent) = *normalization {
+            if percent == 0 || percent > 142 {
+                diagnostics.push(ConfigDiagnostic::new(
+                    None,
+                    "stick_normalization",
+                    format!(
+                        "Diagonal of {}% on the {:?} stick is outside 1-142%",
+                        percent, stick
+                    ),
+                    Severity::Warning,
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::{
+        GamepadButton, Layer, MacroStep, Ramp, Stick, StickAxis, TriggerSide,
+        DEFAULT_TAP_TIMEOUT_MS,
+    };
+
+    const A: u32 = 0x41;
+    const D: u32 = 0x44;
+    const W: u32 = 0x57;
+    // Has no native keycode in these tests
+    const UNSUPPORTED: u32 = 0xFF;
+
+    fn to_native(code: u32) -> Option<u32> {
+        (code != 0 && code != UNSUPPORTED).then_some(code)
+    }
+
+    fn binding(keycode: u32, action: BindingAction) -> Binding {
+        Binding {
+            keycode,
+            chord: Vec::new(),
+            device: None,
+            action,
+        }
+    }
+
+    fn config(bindings: Vec<Binding>) -> BindingConfig {
+        BindingConfig {
+            bindings,
+            ..Default::default()
+        }
+    }
+
+    fn button() -> BindingAction {
+        BindingAction::GamepadButton {
+            button: GamepadButton::A,
+        }
+    }
+
+    fn socd(opposite: u32, mode: SocdMode) -> BindingAction {
+        BindingAction::Socd { opposite, mode }
+    }
+
+    fn turbo(action: BindingAction, rate_hz: u16, duty_percent: u8) -> BindingAction {
+        BindingAction::Turbo {
+            action: Box::new(action),
+            rate_hz,
+            duty_percent,
+        }
+    }
+
+    // Where each diagnostic points and how bad it is, the reasons are left out
+    fn problems(config: &BindingConfig) -> Vec<(Option<usize>, Option<usize>, String, Severity)> {
+        validate_config(config, to_native)
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.layer,
+                    diagnostic.index,
+                    diagnostic.field,
+                    diagnostic.severity,
+                )
+            })
+            .collect()
+    }
+
+    fn problem(
+        layer: Option<usize>,
+        index: Option<usize>,
+        field: &str,
+        severity: Severity,
+    ) -> (Option<usize>, Option<usize>, String, Severity) {
+        (layer, index, field.to_string(), severity)
+    }
+
+    #[test]
+    fn valid_config_has_no_diagnostics() {
+        let config = config(vec![
+            binding(A, socd(D, SocdMode::Priority(D))),
+            binding(D, socd(A, SocdMode::Priority(D))),
+            binding(A, BindingAction::Keyboard { key: W }),
+            binding(W, button()),
+        ]);
+        assert_eq!(problems(&config), vec![]);
+    }
+
+    #[test]
+    fn newer_version_is_an_error() {
+        let config = BindingConfig {
+            version: CONFIG_VERSION + 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            problems(&config),
+            vec![problem(None, None, "version", Severity::Error)]
+        );
+    }
+
+    #[test]
+    fn unsupported_keys_are_errors() {
+        let config = config(vec![
+            binding(UNSUPPORTED, button()),
+            binding(A, BindingAction::Keyboard { key: 0 }),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "keycode", Severity::Error),
+                problem(None, Some(1), "action.key", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_socd_partner_is_a_warning() {
+        let config = config(vec![binding(A, socd(D, SocdMode::LastInput))]);
+        assert_eq!(
+            problems(&config),
+            vec![problem(None, Some(0), "action.opposite", Severity::Warning)]
+        );
+    }
+
+    #[test]
+    fn socd_partner_has_to_be_on_the_same_device() {
+        let on_pad = |binding: Binding| Binding {
+            device: Some("pad".to_string()),
+            ..binding
+        };
+        let mut config = config(vec![
+            on_pad(binding(A, socd(D, SocdMode::Priority(D)))),
+            binding(D, socd(A, SocdMode::Priority(D))),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.opposite", Severity::Warning),
+                problem(None, Some(1), "action.opposite", Severity::Warning),
+            ]
+        );
+
+        config.bindings[1] = on_pad(config.bindings[1].clone());
+        assert_eq!(problems(&config), vec![]);
+    }
+
+    #[test]
+    fn socd_mode_mismatch_is_a_warning_on_both_keys() {
+        let config = config(vec![
+            binding(A, socd(D, SocdMode::LastInput)),
+            binding(D, socd(A, SocdMode::FirstInput)),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.mode", Severity::Warning),
+                problem(None, Some(1), "action.mode", Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn priority_key_outside_the_pair_is_an_error() {
+        let config = config(vec![
+            binding(A, socd(D, SocdMode::Priority(W))),
+            binding(D, socd(A, SocdMode::Priority(W))),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.mode", Severity::Error),
+                problem(None, Some(1), "action.mode", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn own_socd_opposite_is_an_error() {
+        let config = config(vec![binding(A, socd(A, SocdMode::LastInput))]);
+        assert_eq!(
+            problems(&config),
+            vec![problem(None, Some(0), "action.opposite", Severity::Error)]
+        );
+    }
+
+    #[test]
+    fn duplicate_key_is_a_warning_per_device() {
+        let on_device = |device: &str| Binding {
+            device: Some(device.to_string()),
+            ..binding(A, button())
+        };
+        let config = config(vec![
+            on_device("pad"),
+            binding(A, button()),
+            on_device("keyboard"),
+            on_device("pad"),
+            // SOCD bindings don't take the key's output
+            binding(A, socd(D, SocdMode::LastInput)),
+            binding(D, socd(A, SocdMode::LastInput)),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![problem(None, Some(3), "keycode", Severity::Warning)]
+        );
+    }
+
+    #[test]
+    fn duplicate_chord_is_a_warning_in_any_key_order() {
+        let config = config(vec![
+            Binding {
+                chord: vec![D],
+                ..binding(A, button())
+            },
+            Binding {
+                chord: vec![A],
+                ..binding(D, button())
+            },
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![problem(None, Some(1), "keycode", Severity::Warning)]
+        );
+    }
+
+    #[test]
+    fn chord_problems() {
+        let config = config(vec![
+            Binding {
+                chord: vec![A],
+                ..binding(A, button())
+            },
+            Binding {
+                chord: vec![UNSUPPORTED],
+                ..binding(D, button())
+            },
+            Binding {
+                chord: vec![A],
+                ..binding(W, socd(D, SocdMode::LastInput))
+            },
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "chord", Severity::Warning),
+                problem(None, Some(1), "chord", Severity::Error),
+                problem(None, Some(2), "chord", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_window_of_zero_is_a_warning_with_chords() {
+        let mut config = config(vec![Binding {
+            chord: vec![D],
+            ..binding(A, button())
+        }]);
+        config.chord_window_ms = 0;
+        assert_eq!(
+            problems(&config),
+            vec![problem(None, None, "chord_window_ms", Severity::Warning)]
+        );
+    }
+
+    #[test]
+    fn device_problems_are_errors() {
+        let config = config(vec![
+            Binding {
+                device: Some(String::new()),
+                ..binding(A, button())
+            },
+            Binding {
+                device: Some("pad".to_string()),
+                chord: vec![A],
+                ..binding(D, button())
+            },
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "device", Severity::Error),
+                problem(None, Some(1), "device", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_layer_is_an_error() {
+        let config = config(vec![
+            binding(
+                A,
+                BindingAction::LayerHold {
+                    layer: "Missing".to_string(),
+                },
+            ),
+            binding(
+                D,
+                turbo(
+                    BindingAction::LayerToggle {
+                        layer: "Missing".to_string(),
+                    },
+                    10,
+                    50,
+                ),
+            ),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.layer", Severity::Error),
+                problem(None, Some(1), "action.action", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn wheel_as_output_is_an_error() {
+        let config = config(vec![
+            binding(
+                A,
+                BindingAction::Keyboard {
+                    key: WHEEL_UP_KEYCODE,
+                },
+            ),
+            binding(
+                D,
+                turbo(
+                    BindingAction::Keyboard {
+                        key: WHEEL_DOWN_KEYCODE,
+                    },
+                    10,
+                    50,
+                ),
+            ),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.key", Severity::Error),
+                problem(None, Some(1), "action.action.key", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_tap_hold_turbo_and_macro_are_errors() {
+        let tap_hold = BindingAction::TapHold {
+            tap: Box::new(turbo(button(), 10, 50)),
+            hold: Box::new(button()),
+            timeout_ms: DEFAULT_TAP_TIMEOUT_MS,
+        };
+        let nested_macro = BindingAction::Macro {
+            steps: vec![MacroStep {
+                delay_ms: 10,
+                action: BindingAction::Macro {
+                    steps: Vec::new(),
+                    mode: MacroMode::Once,
+                },
+                is_down: true,
+            }],
+            mode: MacroMode::Once,
+        };
+        let config = config(vec![
+            binding(A, tap_hold.clone()),
+            binding(D, turbo(tap_hold, 10, 50)),
+            binding(W, nested_macro),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.tap", Severity::Error),
+                problem(None, Some(1), "action.action", Severity::Error),
+                problem(None, Some(2), "action.steps.0.action", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_problems_are_warnings() {
+        let config = config(vec![
+            binding(
+                A,
+                BindingAction::Macro {
+                    steps: Vec::new(),
+                    mode: MacroMode::Once,
+                },
+            ),
+            binding(
+                D,
+                BindingAction::Macro {
+                    steps: vec![MacroStep {
+                        delay_ms: 0,
+                        action: button(),
+                        is_down: true,
+                    }],
+                    mode: MacroMode::RepeatWhileHeld,
+                },
+            ),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.steps", Severity::Warning),
+                problem(None, Some(1), "action.steps", Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn turbo_rate_and_duty_problems() {
+        let config = config(vec![
+            binding(A, turbo(button(), 0, 50)),
+            binding(D, turbo(button(), 1000, 50)),
+            binding(W, turbo(button(), 10, 100)),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.rate_hz", Severity::Error),
+                problem(None, Some(1), "action.rate_hz", Severity::Warning),
+                problem(None, Some(2), "action.duty_percent", Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn stick_value_problems() {
+        let config = config(vec![
+            binding(
+                A,
+                BindingAction::Axis {
+                    axis: StickAxis::LeftX,
+                    value: 1000,
+                    ramp: Some(Ramp {
+                        up_ms: 100,
+                        down_ms: 100,
+                        curve: RampCurve::Stepped(0),
+                    }),
+                },
+            ),
+            binding(
+                D,
+                BindingAction::Trigger {
+                    side: TriggerSide::Left,
+                    value: 0,
+                },
+            ),
+            binding(W, BindingAction::StickScale { percent: 100 }),
+        ]);
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, Some(0), "action.ramp.curve", Severity::Error),
+                problem(None, Some(1), "action.value", Severity::Warning),
+                problem(None, Some(2), "action.percent", Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn layer_problems_point_at_the_layer() {
+        let layer = |name: &str, bindings: Vec<Binding>| Layer {
+            name: name.to_string(),
+            bindings,
+        };
+        let mut config = config(Vec::new());
+        config.layers = vec![
+            layer(
+                "Aim",
+                vec![
+                    binding(A, button()),
+                    binding(A, button()),
+                    binding(D, socd(A, SocdMode::LastInput)),
+                ],
+            ),
+            layer("Aim", Vec::new()),
+            layer(" ", Vec::new()),
+        ];
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(Some(0), Some(1), "keycode", Severity::Warning),
+                problem(Some(0), Some(2), "action", Severity::Warning),
+                problem(Some(1), None, "name", Severity::Error),
+                problem(Some(2), None, "name", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn axis_socd_priority_key_has_to_be_bound_to_the_axis() {
+        let mut config = config(vec![
+            binding(
+                A,
+                BindingAction::Axis {
+                    axis: StickAxis::LeftX,
+                    value: -32768,
+                    ramp: None,
+                },
+            ),
+            binding(
+                D,
+                BindingAction::Axis {
+                    axis: StickAxis::LeftY,
+                    value: 32767,
+                    ramp: None,
+                },
+            ),
+        ]);
+        config
+            .axis_socd
+            .insert(StickAxis::LeftX, SocdMode::Priority(A));
+        assert_eq!(problems(&config), vec![]);
+
+        // The priority key counts on whichever device it is bound
+        config.bindings[0].device = Some("pad".to_string());
+        assert_eq!(problems(&config), vec![]);
+
+        config
+            .axis_socd
+            .insert(StickAxis::LeftX, SocdMode::Priority(D));
+        assert_eq!(
+            problems(&config),
+            vec![problem(None, None, "axis_socd", Severity::Error)]
+        );
+    }
+
+    #[test]
+    fn stick_and_mouse_look_settings() {
+        let mut config = config(Vec::new());
+        config
+            .stick_normalization
+            .insert(Stick::Left, StickNormalization::Diagonal(150));
+        config.mouse_look = Some(crate::bindings::MouseLook {
+            sensitivity: 0,
+            deadzone_percent: 100,
+            ..Default::default()
+        });
+        assert_eq!(
+            problems(&config),
+            vec![
+                problem(None, None, "mouse_look.sensitivity", Severity::Warning),
+                problem(None, None, "mouse_look.deadzone_percent", Severity::Error),
+                problem(None, None, "stick_normalization", Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_summary_lists_only_errors() {
+        let mut config = config(vec![
+            binding(A, socd(D, SocdMode::LastInput)),
+            binding(UNSUPPORTED, button()),
+        ]);
+        config.layers = vec![Layer {
+            name: String::new(),
+            bindings: Vec::new(),
+        }];
+        let diagnostics = validate_config(&config, to_native);
+        assert!(has_errors(&diagnostics));
+        assert_eq!(
+            error_summary(&diagnostics),
+            "Binding 2 (keycode): Key 0xFF is not supported on this platform\n\
+            Layer 1 name: Layer has no name"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use crate::focus_providers::FocusProvider;
+
+// Just enough of the Wayland wire protocol to follow the wlr foreign toplevel protocol, the ids
+// of the objects we create are fixed since nothing else is ever created
+const DISPLAY_ID: u32 = 1;
+const REGISTRY_ID: u32 = 2;
+const GLOBALS_CALLBACK_ID: u32 = 3;
+const MANAGER_ID: u32 = 4;
+const TOPLEVELS_CALLBACK_ID: u32 = 5;
+
+const MANAGER_INTERFACE: &str = "zwlr_foreign_toplevel_manager_v1";
+const MANAGER_VERSION: u32 = 3;
+
+// wl_display
+const SYNC: u16 = 0;
+const GET_REGISTRY: u16 = 1;
+const ERROR: u16 = 0;
+// wl_registry
+const BIND: u16 = 0;
+const GLOBAL: u16 = 0;
+// wl_callback
+const CALLBACK_DONE: u16 = 0;
+// zwlr_foreign_toplevel_manager_v1
+const TOPLEVEL: u16 = 0;
+const FINISHED: u16 = 1;
+// zwlr_foreign_toplevel_handle_v1
+const APP_ID: u16 = 1;
+const STATE: u16 = 4;
+const DONE: u16 = 5;
+const CLOSED: u16 = 6;
+const STATE_ACTIVATED: u32 = 2;
+
+// Handle events only take effect on done, so they are kept aside until then
+#[derive(Default)]
+struct Toplevel {
+    app_id: String,
+    is_activated: bool,
+    pending_app_id: Option<String>,
+    pending_activated: Option<bool>,
+}
+
+pub(crate) struct WlrFocus {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    toplevels: HashMap<u32, Toplevel>,
+    focused: Option<String>,
+}
+
+impl WlrFocus {
+    pub(crate) fn connect() -> Result<Self, String> {
+        let path = socket_path()?;
+        let stream = UnixStream::connect(&path)
+            .map_err(|e| format!("Can't connect to {}: {}", path.display(), e))?;
+        let mut focus = WlrFocus {
+            stream,
+            buffer: Vec::new(),
+            toplevels: HashMap::new(),
+            focused: None,
+        };
+
+        focus.request(DISPLAY_ID, GET_REGISTRY, &uint_arg(REGISTRY_ID))?;
+        focus.request(DISPLAY_ID, SYNC, &uint_arg(GLOBALS_CALLBACK_ID))?;
+        let mut manager = None;
+        loop {
+            let (object, opcode, args) = focus.read_event()?;
+            match (object, opcode) {
+                (REGISTRY_ID, GLOBAL) => {
+                    let mut args = Args::new(&args);
+                    let (Some(name), Some(interface), Some(version)) =
+                        (args.uint(), args.string(), args.uint())
+                    else {
+                        continue;
+                    };
+                    if interface == MANAGER_INTERFACE {
+                        manager = Some((name, version));
+                    }
+                }
+                (GLOBALS_CALLBACK_ID, CALLBACK_DONE) => break,
+                _ => {
+                    focus.dispatch(object, opcode, &args)?;
+                }
+            }
+        }
+        let (name, version) =
+            manager.ok_or(format!("The compositor has no {}", MANAGER_INTERFACE))?;
+
+        let mut args = uint_arg(name);
+        args.extend(string_arg(MANAGER_INTERFACE));
+        args.extend(uint_arg(version.min(MANAGER_VERSION)));
+        args.extend(uint_arg(MANAGER_ID));
+        focus.request(REGISTRY_ID, BIND, &args)?;
+
+        // The manager announces every toplevel with its state right after it is bound
+        focus.request(DISPLAY_ID, SYNC, &uint_arg(TOPLEVELS_CALLBACK_ID))?;
+        loop {
+            let (object, opcode, args) = focus.read_event()?;
+            if (object, opcode) == (TOPLEVELS_CALLBACK_ID, CALLBACK_DONE) {
+                break;
+            }
+            focus.dispatch(object, opcode, &args)?;
+        }
+        Ok(focus)
+    }
+
+    fn request(&mut self, object: u32, opcode: u16, args: &[u8]) -> Result<(), String> {
+        let size = (8 + args.len()) as u32;
+        let mut message = object.to_ne_bytes().to_vec();
+        message.extend((size << 16 | opcode as u32).to_ne_bytes());
+        message.extend(args);
+        self.stream.write_all(&message).map_err(|e| e.to_string())
+    }
+
+    fn read_event(&mut self) -> Result<(u32, u16, Vec<u8>), String> {
+        loop {
+            if self.buffer.len() >= 8 {
+                let object = u32::from_ne_bytes(self.buffer[0..4].try_into().unwrap());
+                let header = u32::from_ne_bytes(self.buffer[4..8].try_into().unwrap());
+                let size = (header >> 16) as usize;
+                if size < 8 {
+                    return Err("Malformed Wayland message".to_string());
+                }
+                if self.buffer.len() >= size {
+                    let args = self.buffer[8..size].to_vec();
+                    self.buffer.drain(..size);
+                    return Ok((object, header as u16, args));
+                }
+            }
+
+            let mut chunk = [0u8; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err("The compositor closed the connection".to_string()),
+                Ok(read) => self.buffer.extend(&chunk[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    // Returns the app that just got the focus, if any
+    fn dispatch(
+        &mut self,
+        object: u32,
+        opcode: u16,
+        args: &[u8],
+    ) -> Result<Option<String>, String> {
+        let mut args = Args::new(args);
+        match (object, opcode) {
+            (DISPLAY_ID, ERROR) => {
+                let (_, _, message) = (args.uint(), args.uint(), args.string());
+                Err(format!("Wayland error: {}", message.unwrap_or_default()))
+            }
+            (MANAGER_ID, TOPLEVEL) => {
+                if let Some(id) = args.uint() {
+                    self.toplevels.insert(id, Toplevel::default());
+                }
+                Ok(None)
+            }
+            (MANAGER_ID, FINISHED) => Err("The compositor stopped sending toplevels".to_string()),
+            _ => {
+                let Some(toplevel) = self.toplevels.get_mut(&object) else {
+                    return Ok(None);
+                };
+                match opcode {
+                    APP_ID => toplevel.pending_app_id = args.string(),
+                    STATE => {
+                        let states = args.array().unwrap_or_default();
+                        toplevel.pending_activated = Some(
+                            states
+                                .chunks_exact(4)
+                                .any(|state| state == STATE_ACTIVATED.to_ne_bytes()),
+                        );
+                    }
+                    DONE => {
+                        let was_activated = toplevel.is_activated;
+                        if let Some(app_id) = toplevel.pending_app_id.take() {
+                            toplevel.app_id = app_id;
+                        }
+                        if let Some(is_activated) = toplevel.pending_activated.take() {
+                            toplevel.is_activated = is_activated;
+                        }
+                        if toplevel.is_activated
+                            && !toplevel.app_id.is_empty()
+                            && (!was_activated || self.focused.as_ref() != Some(&toplevel.app_id))
+                        {
+                            self.focused = Some(toplevel.app_id.clone());
+                            return Ok(self.focused.clone());
+                        }
+                    }
+                    CLOSED => {
+                        self.toplevels.remove(&object);
+                    }
+                    _ => {}
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl FocusProvider for WlrFocus {
+    fn name(&self) -> &'static str {
+        "wlroots"
+    }
+
+    fn current_focus(&mut self) -> Option<String> {
+        self.focused.clone()
+    }
+
+    fn next_focus(&mut self) -> Option<String> {
+        loop {
+            let (object, opcode, args) = self.read_event().ok()?;
+            if let Some(app_id) = self.dispatch(object, opcode, &args).ok()? {
+                return Some(app_id);
+            }
+        }
+    }
+}
+
+// WAYLAND_DISPLAY is a socket name in the runtime directory, or already a full path
+fn socket_path() -> Result<PathBuf, String> {
+    let display = env::var_os("WAYLAND_DISPLAY").ok_or("WAYLAND_DISPLAY isn't set")?;
+    let display = PathBuf::from(display);
+    if display.is_absolute() {
+        return Ok(display);
+    }
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR").ok_or("XDG_RUNTIME_DIR isn't set")?;
+    Ok(PathBuf::from(runtime_dir).join(display))
+}
+
+fn uint_arg(value: u32) -> Vec<u8> {
+    value.to_ne_bytes().to_vec()
+}
+
+// Strings are sent with their length, the length and the contents count the terminating nul
+fn string_arg(value: &str) -> Vec<u8> {
+    let mut arg = uint_arg(value.len() as u32 + 1);
+    arg.extend(value.as_bytes());
+    arg.push(0);
+    arg.resize(arg.len().next_multiple_of(4), 0);
+    arg
+}
+
+struct Args<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Args<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Args { data, offset: 0 }
+    }
+
+    fn uint(&mut self) -> Option<u32> {
+        let bytes = self.data.get(self.offset..self.offset + 4)?;
+        self.offset += 4;
+        Some(u32::from_ne_bytes(bytes.try_into().unwrap()))
+    }
+
+    // Arrays and strings are padded to 32 bits
+    fn array(&mut self) -> Option<Vec<u8>> {
+        let length = self.uint()? as usize;
+        let bytes = self.data.get(self.offset..self.offset + length)?.to_vec();
+        self.offset += length.next_multiple_of(4);
+        Some(bytes)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let mut bytes = self.array()?;
+        // A null string has no length at all
+        bytes.pop()?;
+        Some(String::from_utf8_lossy(&bytes).to_string())
+    }
+}
@@ -2,13 +2,9 @@
 
 use log::{debug, error, info};
 use once_cell::sync::Lazy;
-use serde::Deserialize;
-use std::cmp;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
+use std::thread;
 use vigem_client::Client;
 use windows::core::PWSTR;
 use windows::Win32::Foundation::{CloseHandle, HWND};
@@ -33,43 +29,23 @@ use windows::Win32::{
     },
 };
 
+use crate::bindings::BindingConfig;
+use crate::engine::{Engine, GamepadState, OutputAction};
 use crate::key_interceptor::KeyInterceptorTrait;
-use crate::{get_config_path, Settings};
+use crate::macro_recorder::RecordedEvent;
+use crate::profiles::ProfileSet;
+use crate::Settings;
 
-#[derive(Debug, Deserialize)]
-struct ConfigJsonData {
-    keycode: String,
-    result_type: String,
-    result_value: i32,
-}
-
-struct KeyState {
-    is_pressed: bool,
-    result_type: String,
-    result_value: i32,
-}
-
-#[derive(Clone)]
-struct OppositeKey {
-    is_pressed: bool,
-    is_virtual_pressed: bool,
-    opposite_key_type: String,
-    opposite_key_value: u32,
-    opposite_key_mapping: Option<u16>,
-}
-
-static KEY_STATES: Lazy<Arc<RwLock<HashMap<u32, KeyState>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
-static OPPOSITE_KEY_STATES: Lazy<Arc<RwLock<HashMap<u32, OppositeKey>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
+static ENGINE: Lazy<Arc<RwLock<Engine>>> = Lazy::new(|| Arc::new(RwLock::new(Engine::default())));
 
 struct SharedState {
     target: Option<vigem_client::Xbox360Wired<Client>>,
     hook_handle: Option<HHOOK>,
     window_hook_handle: Option<HWINEVENTHOOK>,
     allowed_programs: Option<Vec<String>>,
+    active_app_name: Option<String>,
     block_kb_on_controller: bool,
+    profiles: Option<ProfileSet>,
 }
 
 static SHARED_STATE: Lazy<Arc<RwLock<SharedState>>> = Lazy::new(|| {
@@ -78,7 +54,9 @@ static SHARED_STATE: Lazy<Arc<RwLock<SharedState>>> = Lazy::new(|| {
         hook_handle: None,
         window_hook_handle: None,
         allowed_programs: None,
+        active_app_name: None,
         block_kb_on_controller: false,
+        profiles: None,
     }))
 });
 
@@ -118,77 +96,34 @@ impl KeyInterceptorTrait for WindowsKeyInterceptor {
     }
 
     fn start(&mut self, _: &tauri::AppHandle) -> Result<(), String> {
-        // Read keybindings from file
-        let path = get_config_path()?;
-        let mut file = File::open(path).map_err(|e| e.to_string())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|e| e.to_string())?;
-
-        let data: Vec<ConfigJsonData> =
-            serde_json::from_str(&contents).map_err(|e| e.to_string())?;
-        let mut key_states = HashMap::new();
-        let mut opposite_key_states = HashMap::new();
-
-        for item in &data {
-            let keycode =
-                u32::from_str_radix(&item.keycode, 16).expect("Invalid hexadecimal string");
-
-            if item.result_type != "socd" {
-                let key_state = key_states.entry(keycode).or_insert_with(|| KeyState {
-                    is_pressed: false,
-                    result_type: item.result_type.clone(),
-                    result_value: item.result_value,
-                });
-                debug!(
-                    "Keycode: {:?}, ResultType: {:?}, ResultValue {:?}",
-                    keycode, key_state.result_type, key_state.result_value
-                );
-            }
-        }
-
-        for item in &data {
-            let keycode = u32::from_str_radix(&item.keycode, 16);
-
-            if item.result_type == "socd" {
-                let opposite_keycode = item.result_value as u32;
+        // Read keybindings from the profiles, the focused program picks which one is used
+        let mut profiles = ProfileSet::load()?;
+        let active_app_name = SHARED_STATE.read().unwrap().active_app_name.clone();
+        profiles.select(active_app_name.as_deref());
+        let block_kb_on_controller = SHARED_STATE.read().unwrap().block_kb_on_controller;
+        *ENGINE.write().unwrap() =
+            Engine::from_config(profiles.current(), native_keycode, block_kb_on_controller);
+        SHARED_STATE.write().unwrap().profiles = Some(profiles);
 
-                // Check if key_state has a value for the opposite keycode and if so then use that value instead
-                let key_state = key_states.get(&keycode.clone().unwrap());
-                let mut key_type = String::from("keyboard");
-                let mut key_mapping = None;
+        self.should_run.store(true, Ordering::SeqCst);
 
-                if key_state.is_some()
-                    && (key_state.unwrap().result_type == "keyboard"
-                        || key_state.unwrap().result_type == "face_button")
-                {
-                    key_type = key_state.unwrap().result_type.clone();
-                    key_mapping = Some(key_state.unwrap().result_value as u16);
+        // Advances everything that changes with time between key events: ramps, chord windows,
+        // taps, turbo pulses and macros
+        let should_run = self.should_run.clone();
+        thread::spawn(move || {
+            while should_run.load(Ordering::SeqCst) {
+                let interval = ENGINE.read().unwrap().next_tick_in();
+                thread::sleep(interval);
+                if !ENGINE.read().unwrap().needs_tick() {
+                    continue;
                 }
 
-                let opposite_key_state = opposite_key_states
-                    .entry(keycode.clone().unwrap())
-                    .or_insert_with(|| OppositeKey {
-                        is_pressed: false,
-                        is_virtual_pressed: false,
-                        opposite_key_value: opposite_keycode,
-                        opposite_key_type: key_type,
-                        opposite_key_mapping: key_mapping,
-                    });
-
-                debug!(
-                    "Keycode: {:?}, OppositeKeycode: {:?}, OppositeKeyMapping: {:?}",
-                    keycode,
-                    opposite_key_state.opposite_key_value,
-                    opposite_key_state.opposite_key_mapping
-                );
+                let actions = ENGINE.write().unwrap().tick();
+                send_keyboard_actions(&actions);
+                update_controller(ENGINE.read().unwrap().gamepad());
             }
-        }
-
-        *KEY_STATES.write().unwrap() = key_states;
-        *OPPOSITE_KEY_STATES.write().unwrap() = opposite_key_states;
+        });
 
-        self.should_run.store(true, Ordering::SeqCst);
         unsafe extern "system" fn win_event_proc(
             _hwineventhook: HWINEVENTHOOK,
             _event: u32,
@@ -228,6 +163,8 @@ impl KeyInterceptorTrait for WindowsKeyInterceptor {
                 let _ = CloseHandle(handle);
 
                 debug!("Active process: {:?}", process_name);
+                SHARED_STATE.write().unwrap().active_app_name = Some(process_name.to_string());
+                switch_profile(process_name);
 
                 let allowed_programs = SHARED_STATE.read().unwrap().allowed_programs.clone();
                 for program in allowed_programs.unwrap() {
@@ -248,6 +185,7 @@ impl KeyInterceptorTrait for WindowsKeyInterceptor {
                     }
                 }
             }
+            let mut deactivated = false;
             {
                 let mut shared_state = SHARED_STATE.write().unwrap();
                 if let Some(hook_handle) = shared_state.hook_handle.take() {
@@ -256,41 +194,13 @@ impl KeyInterceptorTrait for WindowsKeyInterceptor {
                         let _ = UnhookWindowsHookEx(hook_handle);
                     }
                     shared_state.hook_handle = None;
-
-                    {
-                        let mut key_states = KEY_STATES.write().unwrap();
-
-                        for (_, key_state) in key_states.iter_mut() {
-                            key_state.is_pressed = false;
-                        }
-                    }
-
-                    {
-                        let mut opposite_key_states = OPPOSITE_KEY_STATES.write().unwrap();
-
-                        for (_, key_state) in opposite_key_states.iter_mut() {
-                            key_state.is_pressed = false;
-                        }
-                    }
-
-                    let gamepad = vigem_client::XGamepad {
-                        buttons: vigem_client::XButtons(0),
-                        left_trigger: 0,
-                        right_trigger: 0,
-                        thumb_lx: 0,
-                        thumb_ly: 0,
-                        thumb_rx: 0,
-                        thumb_ry: 0,
-                    };
-
-                    let mut temp_target = shared_state.target.take();
-                    if let Some(ref mut target) = &mut temp_target {
-                        let _ = target.update(&gamepad);
-
-                        shared_state.target = temp_target;
-                    }
+                    deactivated = true;
                 }
             }
+
+            if deactivated {
+                release_all_outputs();
+            }
         }
 
         let allowed_programs = SHARED_STATE.read().unwrap().allowed_programs.clone();
@@ -328,27 +238,57 @@ impl KeyInterceptorTrait for WindowsKeyInterceptor {
     }
 
     fn stop(&self, _: &tauri::AppHandle) {
-        let mut shared_state = SHARED_STATE.write().unwrap();
-        if let Some(hook_handle) = shared_state.hook_handle.take() {
-            info!("Stopping hook");
-            unsafe {
-                let _ = UnhookWindowsHookEx(hook_handle);
+        self.should_run.store(false, Ordering::SeqCst);
+        {
+            let mut shared_state = SHARED_STATE.write().unwrap();
+            if let Some(hook_handle) = shared_state.hook_handle.take() {
+                info!("Stopping hook");
+                unsafe {
+                    let _ = UnhookWindowsHookEx(hook_handle);
+                }
+                shared_state.hook_handle = None;
             }
-            shared_state.hook_handle = None;
-        }
-        if let Some(window_hook_handle) = shared_state.window_hook_handle.take() {
-            info!("Stopping window hook");
-            unsafe {
-                let _ = UnhookWinEvent(window_hook_handle);
+            if let Some(window_hook_handle) = shared_state.window_hook_handle.take() {
+                info!("Stopping window hook");
+                unsafe {
+                    let _ = UnhookWinEvent(window_hook_handle);
+                }
+                shared_state.window_hook_handle = None;
             }
-            shared_state.window_hook_handle = None;
         }
+
+        release_all_outputs();
+    }
+
+    fn reload_bindings(&self) -> Result<(), String> {
+        let mut profiles = ProfileSet::load()?;
+        let active_app_name = SHARED_STATE.read().unwrap().active_app_name.clone();
+        profiles.select(active_app_name.as_deref());
+        let config = profiles.current().clone();
+        SHARED_STATE.write().unwrap().profiles = Some(profiles);
+
+        info!("Reloading bindings");
+        replace_bindings(&config);
+        Ok(())
     }
 
     fn is_running(&self) -> bool {
         let shared_state = SHARED_STATE.read().unwrap();
         shared_state.hook_handle.is_some() || shared_state.window_hook_handle.is_some()
     }
+
+    fn start_recording(&self) -> Result<(), String> {
+        Err("Macro recording is only supported on Linux".to_string())
+    }
+
+    fn stop_recording(&self) -> Result<Vec<RecordedEvent>, String> {
+        Err("Macro recording is only supported on Linux".to_string())
+    }
+}
+
+// Config keycodes already are virtual keycodes, only the range is checked
+pub(crate) fn native_keycode(code: u32) -> Option<u32> {
+    (0x01..=0xFE).contains(&code).then_some(code)
 }
 
 fn is_extended_key(virtual_keycode: u32) -> bool {
@@ -371,34 +311,88 @@ fn is_extended_key(virtual_keycode: u32) -> bool {
     extended_keys.contains(&virtual_keycode)
 }
 
-// Used for when multiple stick rebinds are set, first prioritizes right/up(positive) over left/down(negative), then larger values within those bands
-// neutral(zero) is the absolute lowest priority
-fn analog_priority_transform(n: i32) -> i32 {
-    if n < 0 {
-        return -1 * n;
-    }
-    if n > 0 {
-        return n + i16::MAX as i32;
-    }
+fn send_keyboard_input(virtual_keycode: u32, key_is_down: bool) {
+    let extended_flag = if is_extended_key(virtual_keycode) {
+        KEYEVENTF_EXTENDEDKEY
+    } else {
+        KEYBD_EVENT_FLAGS(0)
+    };
+    let up_flag = if key_is_down {
+        KEYBD_EVENT_FLAGS(0)
+    } else {
+        KEYEVENTF_KEYUP
+    };
+    let scan_code = unsafe { MapVirtualKeyW(virtual_keycode, MAPVK_VK_TO_VSC_EX) } as u16;
+
+    let ki = KEYBDINPUT {
+        wVk: VIRTUAL_KEY(0),
+        wScan: scan_code,
+        dwFlags: up_flag | KEYEVENTF_SCANCODE | extended_flag,
+        time: 0,
+        dwExtraInfo: 0,
+    };
 
-    0
+    let input = INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 { ki },
+    };
+
+    unsafe {
+        SendInput(&[input], std::mem::size_of::<INPUT>() as i32);
+    }
 }
 
-fn find_higher_priority(num1: i16, num2: i16) -> i16 {
-    if num1 == 0 {
-        return num2;
+fn send_keyboard_actions(actions: &[OutputAction]) {
+    for action in actions {
+        if let OutputAction::Keyboard { code, is_down } = *action {
+            send_keyboard_input(code, is_down);
+        }
     }
-    if num2 == 0 {
-        return num1;
+}
+
+fn update_controller(state: &GamepadState) {
+    let gamepad = vigem_client::XGamepad {
+        buttons: vigem_client::XButtons(state.xinput_buttons()),
+        left_trigger: state.left_trigger,
+        right_trigger: state.right_trigger,
+        thumb_lx: state.thumb_lx,
+        thumb_ly: state.thumb_ly,
+        thumb_rx: state.thumb_rx,
+        thumb_ry: state.thumb_ry,
+    };
+
+    let mut shared_state = SHARED_STATE.write().unwrap();
+    if let Some(target) = shared_state.target.as_mut() {
+        let _ = target.update(&gamepad);
     }
+}
 
-    if analog_priority_transform(num1 as i32) >= analog_priority_transform(num2 as i32) {
-        return num1;
-    } else {
-        return num2;
+// Applies the profile of the newly focused program
+fn switch_profile(app_name: &str) {
+    let config = match SHARED_STATE.write().unwrap().profiles.as_mut() {
+        Some(profiles) => profiles.select(Some(app_name)).cloned(),
+        None => None,
+    };
+    if let Some(config) = config {
+        replace_bindings(&config);
     }
 }
 
+// Swaps the engine's bindings, outputs of bindings that changed are released
+fn replace_bindings(config: &BindingConfig) {
+    let block_kb_on_controller = SHARED_STATE.read().unwrap().block_kb_on_controller;
+    let next = Engine::from_config(config, native_keycode, block_kb_on_controller);
+    let actions = ENGINE.write().unwrap().replace_bindings(next);
+    send_keyboard_actions(&actions);
+    update_controller(ENGINE.read().unwrap().gamepad());
+}
+
+fn release_all_outputs() {
+    let actions = ENGINE.write().unwrap().release_all();
+    send_keyboard_actions(&actions);
+    update_controller(ENGINE.read().unwrap().gamepad());
+}
+
 unsafe extern "system" fn low_level_keyboard_proc_callback(
     n_code: i32,
     w_param: WPARAM,
@@ -417,257 +411,16 @@ unsafe extern "system" fn low_level_keyboard_proc_callback(
         _ => return CallNextHookEx(None, n_code, w_param, l_param),
     };
 
-    let mut disable_keyboard = false;
-    {
-        let shared_state = SHARED_STATE.write().unwrap();
-        disable_keyboard = shared_state.block_kb_on_controller
-    }
-
-    // Update Key State
-    let mut key_event_flag = None;
-    {
-        let mut key_states = KEY_STATES.write().unwrap();
-        match key_states.get_mut(&key) {
-            Some(state) => state.is_pressed = key_is_down,
-            _ => (),
-        }
-        key_event_flag = Some(if key_is_down {
-            KEYBD_EVENT_FLAGS(0)
-        } else {
-            KEYEVENTF_KEYUP
-        });
-    }
-
-    // Keyboard Rebinds
-    'rebind: {
-        if disable_keyboard {
-            break 'rebind;
-        }
-        let key_states = KEY_STATES.read().unwrap();
-        if let Some(flag) = key_event_flag {
-            if let Some(key_state) = key_states.get(&key) {
-                if key_state.result_type == "keyboard" {
-                    let vk_code = key_state.result_value as u16;
-
-                    let ki = KEYBDINPUT {
-                        wVk: VIRTUAL_KEY(vk_code),
-                        wScan: 0,
-                        dwFlags: flag,
-                        time: 0,
-                        dwExtraInfo: 0,
-                    };
-
-                    let input = INPUT {
-                        r#type: INPUT_KEYBOARD,
-                        Anonymous: INPUT_0 { ki },
-                    };
+    let output = ENGINE.write().unwrap().handle_key(key, key_is_down);
+    debug!("Keycode: {:?}, Output: {:?}", key, output);
 
-                    unsafe {
-                        SendInput(&[input], std::mem::size_of::<INPUT>() as i32);
-                    }
+    send_keyboard_actions(&output.actions);
 
-                    return LRESULT(1);
-                }
-            }
-        }
-    }
-
-    // SOCD
-    'socd: {
-        if disable_keyboard {
-            break 'socd;
-        }
-
-        let mut opposite_key_states = OPPOSITE_KEY_STATES.write().unwrap();
-
-        let cloned_key_state;
-        if opposite_key_states.contains_key(&key) {
-            {
-                let key_state = opposite_key_states.get_mut(&key).unwrap();
-                key_state.is_pressed = key_is_down;
-                key_state.is_virtual_pressed = key_is_down;
-
-                cloned_key_state = key_state.clone();
-            }
-
-            let opposite_key_state = opposite_key_states
-                .get_mut(&cloned_key_state.opposite_key_value)
-                .unwrap();
-
-            if key_is_down && opposite_key_state.is_pressed && opposite_key_state.is_virtual_pressed
-            {
-                opposite_key_state.is_virtual_pressed = false;
-
-                // if opposite_key_state.opposite_key_type.clone() != String::from("face_button") {
-                {
-                    let extended_flag;
-                    let scan_code = {
-                        let key_value = if cloned_key_state.opposite_key_type.clone()
-                            == String::from("face_button")
-                            || cloned_key_state.opposite_key_mapping.is_none()
-                        {
-                            cloned_key_state.opposite_key_value as u32
-                        } else {
-                            cloned_key_state.opposite_key_mapping.unwrap() as u32
-                        };
-
-                        extended_flag = if is_extended_key(key_value) {
-                            KEYEVENTF_EXTENDEDKEY
-                        } else {
-                            KEYBD_EVENT_FLAGS(0)
-                        };
-
-                        let code = MapVirtualKeyW(key_value, MAPVK_VK_TO_VSC_EX);
-
-                        code as u16
-                    };
-
-                    let ki = KEYBDINPUT {
-                        wVk: VIRTUAL_KEY(0),
-                        wScan: scan_code,
-                        dwFlags: KEYEVENTF_KEYUP | KEYEVENTF_SCANCODE | extended_flag,
-                        time: 0,
-                        dwExtraInfo: 0,
-                    };
-
-                    let input = INPUT {
-                        r#type: INPUT_KEYBOARD,
-                        Anonymous: INPUT_0 { ki },
-                    };
-
-                    unsafe {
-                        let inputs = &[input];
-                        let inputs_size = std::mem::size_of::<INPUT>() as i32;
-                        SendInput(inputs, inputs_size);
-                    }
-                }
-            } else if !key_is_down && opposite_key_state.is_pressed {
-                opposite_key_state.is_virtual_pressed = true;
-
-                // if opposite_key_state.opposite_key_type.clone() != String::from("face_button") {
-                {
-                    let extended_flag;
-                    let scan_code = {
-                        let key_value = if cloned_key_state.opposite_key_type.clone()
-                            == String::from("face_button")
-                            || cloned_key_state.opposite_key_mapping.is_none()
-                        {
-                            cloned_key_state.opposite_key_value as u32
-                        } else {
-                            cloned_key_state.opposite_key_mapping.unwrap() as u32
-                        };
-
-                        extended_flag = if is_extended_key(key_value) {
-                            KEYEVENTF_EXTENDEDKEY
-                        } else {
-                            KEYBD_EVENT_FLAGS(0)
-                        };
-
-                        let code = MapVirtualKeyW(key_value, MAPVK_VK_TO_VSC_EX);
-
-                        code as u16
-                    };
-
-                    let ki = KEYBDINPUT {
-                        wVk: VIRTUAL_KEY(0),
-                        wScan: scan_code,
-                        dwFlags: KEYEVENTF_SCANCODE | extended_flag,
-                        time: 0,
-                        dwExtraInfo: 0,
-                    };
-
-                    let input = INPUT {
-                        r#type: INPUT_KEYBOARD,
-                        Anonymous: INPUT_0 { ki },
-                    };
-
-                    unsafe {
-                        let inputs = &[input];
-                        let inputs_size = std::mem::size_of::<INPUT>() as i32;
-                        SendInput(inputs, inputs_size);
-                    }
-                }
-            }
-        }
-    }
-
-    // Controller Rebinds
-    let mut face_buttons: u16 = 0;
-    let mut left_trigger: u8 = 0;
-    let mut right_trigger: u8 = 0;
-    let mut thumb_lx: i16 = 0;
-    let mut thumb_ly: i16 = 0;
-    let mut thumb_rx: i16 = 0;
-    let mut thumb_ry: i16 = 0;
-
-    {
-        let key_states = KEY_STATES.read().unwrap();
-        for (_, key_state) in key_states.iter().filter(|&(_, ks)| ks.is_pressed) {
-            match key_state.result_type.as_str() {
-                "face_button" => face_buttons = face_buttons | key_state.result_value as u16,
-                "trigger_l" => left_trigger = cmp::max(left_trigger, key_state.result_value as u8),
-                "trigger_r" => {
-                    right_trigger = cmp::max(right_trigger, key_state.result_value as u8)
-                }
-                "thumb_lx" => {
-                    thumb_lx = find_higher_priority(thumb_lx, key_state.result_value as i16)
-                }
-                "thumb_ly" => {
-                    thumb_ly = find_higher_priority(thumb_ly, key_state.result_value as i16)
-                }
-                "thumb_rx" => {
-                    thumb_rx = find_higher_priority(thumb_rx, key_state.result_value as i16)
-                }
-                "thumb_ry" => {
-                    thumb_ry = find_higher_priority(thumb_ry, key_state.result_value as i16)
-                }
-                _ => (),
-            }
-        }
-    }
-
-    {
-        let opposite_key_states = OPPOSITE_KEY_STATES.read().unwrap();
-
-        if opposite_key_states.contains_key(&key) {
-            let cloned_key_state = opposite_key_states.get(&key).unwrap().clone();
-
-            let opposite_key_state = opposite_key_states
-                .get(&cloned_key_state.opposite_key_value)
-                .unwrap();
-
-            if let Some(opposite_key_mapping) = opposite_key_state.opposite_key_mapping {
-                let mask = opposite_key_mapping as u16;
-
-                if opposite_key_state.is_pressed && !opposite_key_state.is_virtual_pressed {
-                    let clear_mask = !mask;
-                    face_buttons &= clear_mask;
-                }
-            }
-        }
-    }
-
-    let gamepad = vigem_client::XGamepad {
-        buttons: vigem_client::XButtons(face_buttons),
-        left_trigger: left_trigger,
-        right_trigger: right_trigger,
-        thumb_lx: thumb_lx,
-        thumb_ly: thumb_ly,
-        thumb_rx: thumb_rx,
-        thumb_ry: thumb_ry,
-    };
-
-    {
-        let mut shared_state = SHARED_STATE.write().unwrap();
-        let mut temp_target = shared_state.target.take();
-        if let Some(ref mut target) = &mut temp_target {
-            let _ = target.update(&gamepad);
-
-            shared_state.target = temp_target;
-        }
+    if output.has_gamepad_actions() {
+        update_controller(ENGINE.read().unwrap().gamepad());
     }
 
-    if disable_keyboard {
+    if output.block_input {
         return LRESULT(1);
     }
 
@@ -31,6 +31,7 @@ function App() {
       const response = await invoke("stop_interception");
       console.log(response);
       setErr("");
+      setLostDevices([]);
       await updateIsOverbindRunning();
     } catch (error) {
       console.error(error);
@@ -64,6 +65,8 @@ function App() {
   const [err, setErr] = useState("");
   const [isDirty, setIsDirty] = useState(false);
   const [isSettingsIncomplete, setIsSettingsIncomplete] = useState(false);
+  // Input devices that were unplugged while Overbind was running
+  const [lostDevices, setLostDevices] = useState<string[]>([]);
 
   useEffect(() => {
     function forwardConsole(
@@ -94,6 +97,19 @@ function App() {
     });
 
 
+    listen("bindings_reload_failed", (event) => {
+      setErr(event.payload as string);
+    });
+
+    listen("input_device_status", (event) => {
+      const { device, connected } = event.payload as { device: string; connected: boolean };
+      setLostDevices((devices) =>
+        connected
+          ? devices.filter((d) => d !== device)
+          : [...devices.filter((d) => d !== device), device],
+      );
+    });
+
     listen("tray_intercept_disable", () => {
       setIsOverbindRunning(false);
     });
@@ -148,6 +164,12 @@ function App() {
         </div>
       )}
 
+      {isOverbindRunning && lostDevices.length > 0 && (
+        <div className="text-red-500">
+          <p>Lost {lostDevices.join(", ")}, it will be picked up again once it is plugged back in.</p>
+        </div>
+      )}
+
       <div className="mt-4 flex w-full justify-center gap-2.5">
         {!isOverbindRunning ? (
           <button
@@ -206,13 +228,7 @@ function App() {
       {isEditingBinds && (
         <KeybindSettings
           onCancel={() => setIsEditingBinds(false)}
-          onSave={async () => {
-            setIsEditingBinds(false);
-            if (isOverbindRunning) {
-              await stopOverbind();
-              await runOverbind();
-            }
-          }}
+          onSave={() => setIsEditingBinds(false)}
           onErr={setErr}
         />
       )}
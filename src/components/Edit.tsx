@@ -1,23 +1,112 @@
 import { invoke } from "@tauri-apps/api/core";
+import { platform } from "@tauri-apps/plugin-os";
 import { useEffect, useState } from "react";
-import { WINDOWS_ECMA_KEYMAP, CONTROLLER_INPUTS } from "../constants";
+import {
+  WINDOWS_ECMA_KEYMAP,
+  CONTROLLER_INPUTS,
+  Binding,
+  BindingAction,
+  BindingConfig,
+  ConfigDiagnostic,
+  Ramp,
+  SocdMode,
+  Stick,
+  StickAxis,
+  StickNormalization,
+  STICK_AXIS_NAMES,
+  OUTPUT_OPTIONS,
+  controllerInputName,
+  outputOptionAction,
+  outputOptionName,
+  MacroMode,
+  MacroStep,
+} from "../constants";
 import Dropdown from "./Dropdown";
 import { InputTypeIcon } from "./InputTypeIcon";
+import Profiles from "./Profiles";
+import MacroSettings from "./MacroSettings";
+import MouseLookSettings from "./MouseLookSettings";
+import RampSettings from "./RampSettings";
 
-type BindType = "controller" | "keyboard" | "socd" | undefined;
+// Key names of MouseEvent.button values
+const MOUSE_BUTTON_NAMES: Record<number, string> = {
+  1: "MouseMiddle",
+  2: "MouseRight",
+  3: "MouseBack",
+  4: "MouseForward",
+};
+
+const ANY_DEVICE = "Any device";
+
+type BindType =
+  | "controller"
+  | "keyboard"
+  | "socd"
+  | "stick_scale"
+  | "layer_hold"
+  | "layer_toggle"
+  | "tap_hold"
+  | "turbo"
+  | "macro"
+  | undefined;
+
+const NEW_BIND_TYPES: Record<string, BindType> = {
+  Keyboard: "keyboard",
+  Controller: "controller",
+  SOCD: "socd",
+  "Stick scale": "stick_scale",
+  "Layer hold": "layer_hold",
+  "Layer toggle": "layer_toggle",
+  "Tap / hold": "tap_hold",
+  Turbo: "turbo",
+  Macro: "macro",
+};
+
+const LAYER_BIND_LABELS: Record<string, string> = {
+  layer_hold: "Hold",
+  layer_toggle: "Toggle",
+};
+
+// Stick scale binds keep their percentage in `output`, layer binds the layer's name
+const DEFAULT_STICK_SCALE = "50";
+
+const DEFAULT_CHORD_WINDOW_MS = 50;
+const DEFAULT_TAP_TIMEOUT_MS = 200;
+const DEFAULT_TURBO_RATE_HZ = 10;
+const DEFAULT_TURBO_DUTY_PERCENT = 50;
+
+const BASE_LAYER_NAME = "Base";
+const NEW_LAYER_OPTION = "New layer";
 
 interface Keybind {
   id: number;
   type: BindType;
   output: string;
   input: string;
+  // Shared by both binds of a SOCD pair, priority is stored as a key name
+  socdMode?: "last_input" | "first_input" | "neutral" | "priority";
+  socdPriority?: string;
+  // Only used by stick outputs
+  ramp?: Ramp;
+  // Keys pressed together with the input, names like `input`
+  chord?: string[];
+  // Input device the bind is limited to, any device when unset
+  device?: string;
+  // Only used by tap/hold binds
+  tap?: BindingAction;
+  hold?: BindingAction;
+  tapTimeout?: number;
+  // Only used by turbo binds
+  turbo?: { action?: BindingAction; rateHz: number; dutyPercent: number };
+  // Only used by macro binds
+  macro?: { steps: MacroStep[]; mode: MacroMode };
 }
 
-interface ConfigBind {
-  keycode: string;
-  result_type: string;
-  result_value: number;
-}
+const SOCD_MODE_NAMES: Record<string, string> = {
+  last_input: "Last input wins",
+  first_input: "First input wins",
+  neutral: "Neutral",
+};
 
 function KeybindSettings({
   onCancel,
@@ -28,18 +117,77 @@ function KeybindSettings({
   onSave: () => void;
   onErr: (error: string) => void;
 }) {
+  // Unset keys are sent as 0 so the validator can report them
+  const toBindings = (binds: Keybind[]): Binding[] =>
+    binds.map((bind) => {
+      const keycode = (name: string) => WINDOWS_ECMA_KEYMAP[name] ?? 0;
+      let action: BindingAction;
+      if (bind.type === "controller") {
+        action = CONTROLLER_INPUTS[bind.output] ?? {
+          type: "gamepad_button",
+          button: "",
+        };
+        if (action.type === "axis" && bind.ramp) {
+          action = { ...action, ramp: bind.ramp };
+        }
+      } else if (bind.type === "stick_scale") {
+        action = { type: "stick_scale", percent: parseInt(bind.output) || 0 };
+      } else if (bind.type === "layer_hold" || bind.type === "layer_toggle") {
+        action = { type: bind.type, layer: bind.output };
+      } else if (bind.type === "macro") {
+        action = {
+          type: "macro",
+          steps: bind.macro?.steps ?? [],
+          mode: bind.macro?.mode ?? "once",
+        };
+      } else if (bind.type === "turbo") {
+        action = {
+          type: "turbo",
+          action: bind.turbo!.action!,
+          rate_hz: bind.turbo!.rateHz,
+          duty_percent: bind.turbo!.dutyPercent,
+        };
+      } else if (bind.type === "tap_hold") {
+        action = {
+          type: "tap_hold",
+          tap: bind.tap!,
+          hold: bind.hold!,
+          timeout_ms: bind.tapTimeout ?? DEFAULT_TAP_TIMEOUT_MS,
+        };
+      } else if (bind.type === "socd") {
+        const mode: SocdMode =
+          bind.socdMode === "priority"
+            ? { priority: keycode(bind.socdPriority ?? "") }
+            : (bind.socdMode ?? "last_input");
+        action = { type: "socd", opposite: keycode(bind.output), mode };
+      } else {
+        action = { type: "keyboard", key: keycode(bind.output) };
+      }
+      const chord = bind.chord?.length ? { chord: bind.chord.map(keycode) } : {};
+      const device = bind.device ? { device: bind.device } : {};
+      return { keycode: keycode(bind.input), ...chord, ...device, action };
+    });
+
+  // The layer being edited, null for the base bindings
+  const [layerIndex, setLayerIndex] = useState<number | null>(null);
+
+  const withBindings = (
+    config: BindingConfig,
+    layer: number | null,
+    bindings: Binding[],
+  ): BindingConfig =>
+    layer === null
+      ? { ...config, bindings }
+      : {
+        ...config,
+        layers: config.layers?.map((l, i) => (i === layer ? { ...l, bindings } : l)),
+      };
+
+  const toConfig = (binds: Keybind[]): BindingConfig =>
+    withBindings(config, layerIndex, toBindings(binds));
+
   const handleSave = () => {
-    const configToSave = binds.map((bind) => ({
-      keycode: WINDOWS_ECMA_KEYMAP[bind.input].toString(16),
-      ...(bind.type === "controller"
-        ? CONTROLLER_INPUTS[bind.output]
-        : {
-          result_type: bind.type,
-          result_value: WINDOWS_ECMA_KEYMAP[bind.output],
-        }),
-    }));
-
-    invoke("save_config", { configs: configToSave })
+    invoke("save_config", { config: toConfig(binds) })
       .then(() => onSave())
       .catch((err) => onErr(err));
   };
@@ -48,55 +196,176 @@ function KeybindSettings({
     invoke("read_config")
       .then((response) => {
         console.log(JSON.stringify(response));
-        const configBinds = response as ConfigBind[];
-        setBindsCount(configBinds.length);
-        const newBinds = configBinds.map((configBind, i) => {
-          const keyIn = parseInt(configBind.keycode, 16);
-          const input =
-            Object.entries(WINDOWS_ECMA_KEYMAP).find(
-              ([_, value]) => value === keyIn,
-            )?.[0] ?? "";
-          let type: BindType = "controller";
-          let output: string =
-            Object.entries(CONTROLLER_INPUTS).find(
-              ([_, value]) =>
-                value.result_type === configBind.result_type &&
-                value.result_value === configBind.result_value,
-            )?.[0] ?? "";
-          if (!output) {
-            type = configBind.result_type as BindType;
-            output =
-              Object.entries(WINDOWS_ECMA_KEYMAP).find(
-                ([_, value]) => value === configBind.result_value,
-              )?.[0] ?? "";
-          }
-          return {
-            id: i,
-            type,
-            input,
-            output,
-          };
-        });
-        const linkedBinds: number[][] = [];
-        newBinds.forEach((bind) => {
-          if (bind.type === "socd") {
-            const otherBind = newBinds.find(
-              (b) =>
-                b.id !== bind.id &&
-                b.type === "socd" &&
-                b.input === bind.output,
-            );
-            if (otherBind && !linkedBinds.find((b) => b.includes(bind.id))) {
-              linkedBinds.push([bind.id, otherBind.id]);
-            }
-          }
-        });
-        setBinds(newBinds);
-        setLinkedBinds(linkedBinds);
+        const bindingConfig = response as BindingConfig;
+        setConfig(bindingConfig);
+        setLayerIndex(null);
+        loadKeybinds(bindingConfig.bindings);
       })
       .catch((err) => onErr(err));
   };
 
+  const loadKeybinds = (configBinds: Binding[]) => {
+    setBindsCount(configBinds.length);
+    const keyName = (keycode: number) =>
+      Object.entries(WINDOWS_ECMA_KEYMAP).find(
+        ([_, value]) => value === keycode,
+      )?.[0] ?? "";
+    const newBinds = configBinds.map((configBind, i) => {
+      const input = keyName(configBind.keycode);
+      const action = configBind.action;
+      let type: BindType = "controller";
+      let socdMode: Keybind["socdMode"];
+      let socdPriority: string | undefined;
+      let ramp: Ramp | undefined;
+      let tap: BindingAction | undefined;
+      let hold: BindingAction | undefined;
+      let tapTimeout: number | undefined;
+      let turbo: Keybind["turbo"];
+      let macro: Keybind["macro"];
+      // Ramps are per binding, the controller input is matched without it
+      const baseAction =
+        action.type === "axis" ? { ...action, ramp: undefined } : action;
+      let output: string =
+        Object.entries(CONTROLLER_INPUTS).find(
+          ([_, value]) =>
+            JSON.stringify(value) === JSON.stringify(baseAction),
+        )?.[0] ?? "";
+      if (action.type === "axis") {
+        ramp = action.ramp;
+      }
+      if (action.type === "socd") {
+        type = "socd";
+        output = keyName(action.opposite);
+        if (typeof action.mode === "object") {
+          socdMode = "priority";
+          socdPriority = keyName(action.mode.priority);
+        } else {
+          socdMode = action.mode;
+        }
+      } else if (action.type === "stick_scale") {
+        type = "stick_scale";
+        output = action.percent.toString();
+      } else if (action.type === "layer_hold" || action.type === "layer_toggle") {
+        type = action.type;
+        output = action.layer;
+      } else if (action.type === "macro") {
+        type = "macro";
+        macro = { steps: action.steps, mode: action.mode };
+      } else if (action.type === "turbo") {
+        type = "turbo";
+        turbo = {
+          action: action.action,
+          rateHz: action.rate_hz,
+          dutyPercent: action.duty_percent,
+        };
+      } else if (action.type === "tap_hold") {
+        type = "tap_hold";
+        tap = action.tap;
+        hold = action.hold;
+        tapTimeout = action.timeout_ms;
+      } else if (action.type === "keyboard") {
+        type = "keyboard";
+        output = keyName(action.key);
+      }
+      return {
+        id: i,
+        type,
+        input,
+        output,
+        socdMode,
+        socdPriority,
+        ramp,
+        chord: configBind.chord?.map(keyName),
+        device: configBind.device,
+        tap,
+        hold,
+        tapTimeout,
+        turbo,
+        macro,
+      };
+    });
+    const linkedBinds: number[][] = [];
+    newBinds.forEach((bind) => {
+      if (bind.type === "socd") {
+        const otherBind = newBinds.find(
+          (b) =>
+            b.id !== bind.id &&
+            b.type === "socd" &&
+            b.input === bind.output,
+        );
+        if (otherBind && !linkedBinds.find((b) => b.includes(bind.id))) {
+          linkedBinds.push([bind.id, otherBind.id]);
+        }
+      }
+    });
+    setBinds(newBinds);
+    setLinkedBinds(linkedBinds);
+  };
+
+  // Keeps the edited binds in the config and loads the other layer's
+  const selectLayer = (option: string) => {
+    const current = withBindings(config, layerIndex, toBindings(binds));
+    const layers = current.layers ?? [];
+    let next: BindingConfig = current;
+    let index = layers.findIndex((layer) => layer.name === option);
+    if (option === NEW_LAYER_OPTION) {
+      let number = layers.length + 1;
+      while (layers.some((layer) => layer.name === `Layer ${number}`)) {
+        number++;
+      }
+      next = { ...current, layers: [...layers, { name: `Layer ${number}`, bindings: [] }] };
+      index = layers.length;
+    }
+    const layer = index === -1 ? null : index;
+    setConfig(next);
+    setLayerIndex(layer);
+    loadKeybinds(layer === null ? next.bindings : next.layers![layer].bindings);
+  };
+
+  // Layer binds refer to layers by name, so they follow the rename
+  const renameLayer = (name: string) => {
+    if (layerIndex === null) {
+      return;
+    }
+    const oldName = config.layers![layerIndex].name;
+    const rename = (bindings: Binding[]) =>
+      bindings.map((binding) =>
+        (binding.action.type === "layer_hold" || binding.action.type === "layer_toggle") &&
+          binding.action.layer === oldName
+          ? { ...binding, action: { ...binding.action, layer: name } }
+          : binding,
+      );
+    setConfig({
+      ...config,
+      bindings: rename(config.bindings),
+      layers: config.layers!.map((layer, i) => ({
+        name: i === layerIndex ? name : layer.name,
+        bindings: rename(layer.bindings),
+      })),
+    });
+    setBinds(
+      binds.map((bind) =>
+        (bind.type === "layer_hold" || bind.type === "layer_toggle") &&
+          bind.output === oldName
+          ? { ...bind, output: name }
+          : bind,
+      ),
+    );
+  };
+
+  const deleteLayer = () => {
+    if (layerIndex === null) {
+      return;
+    }
+    const next = {
+      ...config,
+      layers: config.layers!.filter((_, i) => i !== layerIndex),
+    };
+    setConfig(next);
+    setLayerIndex(null);
+    loadKeybinds(next.bindings);
+  };
+
   const setSocdLinkedBinds = (
     newBinds: Keybind[],
     bindIdA: number,
@@ -126,10 +395,166 @@ function KeybindSettings({
     );
   };
 
+  const setSocdMode = (bind: Keybind, option: string) => {
+    const theseLinkedBinds = linkedBinds.find(
+      (b) => b[0] === bind.id || b[1] === bind.id,
+    ) ?? [bind.id];
+    const modeName = Object.keys(SOCD_MODE_NAMES).find(
+      (mode) => SOCD_MODE_NAMES[mode] === option,
+    ) as Keybind["socdMode"];
+    setBinds(
+      binds.map((b) =>
+        theseLinkedBinds.includes(b.id)
+          ? {
+            ...b,
+            socdMode: modeName ?? "priority",
+            socdPriority: modeName
+              ? undefined
+              : option === `${bind.input} wins`
+                ? bind.input
+                : bind.output,
+          }
+          : b,
+      ),
+    );
+  };
+
+  const setTurbo = (bind: Keybind, changes: Partial<NonNullable<Keybind["turbo"]>>) =>
+    setBinds(
+      binds.map((b) =>
+        b.id === bind.id
+          ? {
+            ...b,
+            turbo: {
+              rateHz: DEFAULT_TURBO_RATE_HZ,
+              dutyPercent: DEFAULT_TURBO_DUTY_PERCENT,
+              ...b.turbo,
+              ...changes,
+            },
+          }
+          : b,
+      ),
+    );
+
+  const socdModeLabel = (bind: Keybind) =>
+    bind.socdMode === "priority"
+      ? `${capitalizeFirstLetter(bind.socdPriority ?? "")} wins`
+      : SOCD_MODE_NAMES[bind.socdMode ?? "last_input"];
+
+  // Keys bound to each stick axis, the axis' SOCD mode is picked from these
+  const stickAxisBinds = (axis: StickAxis) =>
+    binds.filter((bind) => {
+      const action = CONTROLLER_INPUTS[bind.output];
+      return (
+        bind.type === "controller" &&
+        action?.type === "axis" &&
+        action.axis === axis &&
+        bind.input
+      );
+    });
+
+  const axisSocdLabel = (axis: StickAxis) => {
+    const mode = config.axis_socd?.[axis] ?? "last_input";
+    if (typeof mode === "object") {
+      const name = Object.entries(WINDOWS_ECMA_KEYMAP).find(
+        ([_, value]) => value === mode.priority,
+      )?.[0];
+      return `${capitalizeFirstLetter(name ?? "")} wins`;
+    }
+    return SOCD_MODE_NAMES[mode];
+  };
+
+  const setAxisSocd = (axis: StickAxis, option: string) => {
+    const modeName = Object.keys(SOCD_MODE_NAMES).find(
+      (mode) => SOCD_MODE_NAMES[mode] === option,
+    );
+    const priorityBind = stickAxisBinds(axis).find(
+      (bind) => `${bind.input} wins` === option,
+    );
+    const mode: SocdMode = modeName
+      ? (modeName as SocdMode)
+      : { priority: WINDOWS_ECMA_KEYMAP[priorityBind?.input ?? ""] ?? 0 };
+    setConfig({ ...config, axis_socd: { ...config.axis_socd, [axis]: mode } });
+  };
+
+  const NORMALIZATION_NAMES = ["Square", "Circle", "Custom diagonal"];
+  const DEFAULT_DIAGONAL = 100;
+
+  const normalizationName = (normalization?: StickNormalization) =>
+    typeof normalization === "object"
+      ? "Custom diagonal"
+      : normalization === "circle"
+        ? "Circle"
+        : "Square";
+
+  const setNormalization = (stick: Stick, normalization: StickNormalization) =>
+    setConfig({
+      ...config,
+      stick_normalization: { ...config.stick_normalization, [stick]: normalization },
+    });
+
+  const [config, setConfig] = useState<BindingConfig>({
+    version: 2,
+    bindings: [],
+  });
   const [binds, setBinds] = useState<Keybind[]>([]);
   const [bindsCount, setBindsCount] = useState(0);
   useEffect(getKeybinds, []);
 
+  // Selected input devices, binds can be limited to one of them
+  const [inputDevices, setInputDevices] = useState<string[]>([]);
+  useEffect(() => {
+    if (platform() !== "linux") {
+      return;
+    }
+    invoke("read_app_settings")
+      .then((settings: any) =>
+        setInputDevices(
+          settings.selected_inputs ?? (settings.selected_input ? [settings.selected_input] : []),
+        ),
+      )
+      .catch((err) => onErr(err));
+  }, []);
+
+  const [diagnostics, setDiagnostics] = useState<ConfigDiagnostic[]>([]);
+  useEffect(() => {
+    const isUnset = (bind: Keybind) =>
+      (bind.type === "controller" && !bind.output) ||
+      (bind.type === "tap_hold" && (!bind.tap || !bind.hold)) ||
+      (bind.type === "turbo" && !bind.turbo?.action);
+    if (binds.some(isUnset)) {
+      // An unset controller output can't be deserialized, report it without the backend
+      setDiagnostics(
+        binds.flatMap((bind, index) =>
+          isUnset(bind)
+            ? [
+              {
+                layer: layerIndex,
+                index,
+                field: "action",
+                reason: "No controller input is set",
+                severity: "error" as const,
+              },
+            ]
+            : [],
+        ),
+      );
+      return;
+    }
+    invoke("validate_config", { config: toConfig(binds) })
+      .then((response) => setDiagnostics(response as ConfigDiagnostic[]))
+      .catch((err) => onErr(err));
+  }, [binds, config]);
+
+  const bindDiagnostics = (index: number) =>
+    diagnostics.filter(
+      (diagnostic) => diagnostic.layer === layerIndex && diagnostic.index === index,
+    );
+
+  const layerDiagnostics = diagnostics.filter(
+    (diagnostic) => diagnostic.layer === layerIndex && diagnostic.index === null,
+  );
+
   const [activeKeybindId, setActiveKeybindId] = useState<
     undefined | [number, boolean]
   >(undefined);
@@ -155,36 +580,50 @@ function KeybindSettings({
         winKeyCode = WINDOWS_ECMA_KEYMAP[name];
       }
       if (winKeyCode) {
-        if (activeKeybindId !== undefined) {
-          const bind = binds.find((b) => b.id === activeKeybindId![0])!;
-          // Record the key along with active mod keys
-          const newKeybinds = binds.map((b) =>
-            b.id === bind.id
-              ? {
-                ...b,
-                input: activeKeybindId[1] ? name : b.input,
-                output: activeKeybindId[1] ? b.output : name,
-              }
-              : b,
-          );
-          setBinds(newKeybinds);
+        recordKey(name);
+      }
+    };
 
-          if (bind.type === "socd") {
-            const theseLinkedBinds = linkedBinds.find(
-              (b) => b[0] === bind.id || b[1] === bind.id,
-            );
-            setSocdLinkedBinds(
-              newKeybinds,
-              theseLinkedBinds![0],
-              theseLinkedBinds![1],
-              activeKeybindId[1],
-            );
-          }
-          setActiveKeybindId(undefined); // Reset active keybind ID
-          console.log("Removed keydown listener");
-          window.removeEventListener("keydown", handleKeyDown);
-          window.removeEventListener("keyup", handleKeyUp);
+    // The left button isn't recorded, it's needed to click around. It can be picked from the list.
+    const handleMouseDown = (event: MouseEvent) => {
+      const name = MOUSE_BUTTON_NAMES[event.button];
+      if (name) {
+        event.preventDefault();
+        recordKey(name);
+      }
+    };
+
+    const recordKey = (name: string) => {
+      if (activeKeybindId !== undefined) {
+        const bind = binds.find((b) => b.id === activeKeybindId![0])!;
+        // Record the key along with active mod keys
+        const newKeybinds = binds.map((b) =>
+          b.id === bind.id
+            ? {
+              ...b,
+              input: activeKeybindId[1] ? name : b.input,
+              output: activeKeybindId[1] ? b.output : name,
+            }
+            : b,
+        );
+        setBinds(newKeybinds);
+
+        if (bind.type === "socd") {
+          const theseLinkedBinds = linkedBinds.find(
+            (b) => b[0] === bind.id || b[1] === bind.id,
+          );
+          setSocdLinkedBinds(
+            newKeybinds,
+            theseLinkedBinds![0],
+            theseLinkedBinds![1],
+            activeKeybindId[1],
+          );
         }
+        setActiveKeybindId(undefined); // Reset active keybind ID
+        console.log("Removed keydown listener");
+        window.removeEventListener("keydown", handleKeyDown);
+        window.removeEventListener("keyup", handleKeyUp);
+        window.removeEventListener("mousedown", handleMouseDown);
       }
     };
 
@@ -199,6 +638,7 @@ function KeybindSettings({
       console.log("Adding keydown listener");
       window.addEventListener("keydown", handleKeyDown);
       window.addEventListener("keyup", handleKeyUp);
+      window.addEventListener("mousedown", handleMouseDown);
     }
 
     return () => {
@@ -206,6 +646,7 @@ function KeybindSettings({
         console.log("Removed keydown listener");
         window.removeEventListener("keydown", handleKeyDown);
         window.removeEventListener("keyup", handleKeyUp);
+        window.removeEventListener("mousedown", handleMouseDown);
       }
     };
   }, [activeKeybindId, binds]);
@@ -228,6 +669,40 @@ function KeybindSettings({
   return (
     <div className="p-4 text-white">
       <h1 className="mb-4 text-lg font-bold">Edit Keybinds</h1>
+      <Profiles onActivate={getKeybinds} onErr={onErr} />
+      <div
+        className={`mb-4 flex items-center gap-2 ${layerDiagnostics.some((d) => d.severity === "error") ? "text-rose-400" : ""}`}
+        title={layerDiagnostics.map((d) => d.reason).join("\n")}
+      >
+        <span>Layer</span>
+        <Dropdown
+          options={[
+            BASE_LAYER_NAME,
+            ...(config.layers ?? []).map((layer) => layer.name),
+            NEW_LAYER_OPTION,
+          ]}
+          onChange={selectLayer}
+        >
+          {layerIndex === null ? BASE_LAYER_NAME : config.layers![layerIndex].name}
+        </Dropdown>
+        {layerIndex !== null && (
+          <>
+            <input
+              type="text"
+              value={config.layers![layerIndex].name}
+              title="Layer name"
+              onChange={(e) => renameLayer(e.target.value)}
+              className="rounded-md bg-blue-900 px-3 py-1"
+            />
+            <button
+              onClick={deleteLayer}
+              className="rounded bg-rose-700 px-4 py-1 font-bold text-white hover:bg-rose-500"
+            >
+              Delete layer
+            </button>
+          </>
+        )}
+      </div>
       <table className="mb-4 w-full table-auto">
         <thead>
           <tr className="bg-indigo-950 bg-opacity-60">
@@ -239,10 +714,18 @@ function KeybindSettings({
           </tr>
         </thead>
         <tbody>
-          {binds.map((bind) => (
+          {binds.map((bind, index) => (
             <tr
               key={bind.id}
-              className="border-b border-indigo-950 bg-indigo-800 bg-opacity-60"
+              className={`border-b border-indigo-950 bg-indigo-800 bg-opacity-60 ${bindDiagnostics(index).some((d) => d.severity === "error")
+                ? "outline outline-2 outline-rose-500"
+                : bindDiagnostics(index).length > 0
+                  ? "outline outline-2 outline-amber-400"
+                  : ""
+                }`}
+              title={bindDiagnostics(index)
+                .map((d) => d.reason)
+                .join("\n")}
             >
               <td className="object-center">
                 <div className="flex justify-center">
@@ -265,24 +748,169 @@ function KeybindSettings({
                 </div>
               </td>
               <td className="px-4 py-2">
-                {bind.type === "controller" ? (
-                  <Dropdown
-                    options={Object.keys(CONTROLLER_INPUTS)}
-                    onChange={(option) => {
-                      const newKeybinds = binds.map((b) =>
-                        b.id === bind.id
-                          ? {
-                            ...b,
-                            output: option,
-                            type: bind.type,
+                {bind.type === "macro" ? (
+                  <MacroSettings
+                    steps={bind.macro?.steps ?? []}
+                    mode={bind.macro?.mode ?? "once"}
+                    recorded={config.recorded_macros ?? []}
+                    onChange={(steps, mode) =>
+                      setBinds(
+                        binds.map((b) => (b.id === bind.id ? { ...b, macro: { steps, mode } } : b)),
+                      )
+                    }
+                    onRecorded={(recorded) =>
+                      setConfig({
+                        ...config,
+                        recorded_macros: [
+                          ...(config.recorded_macros ?? []).filter((m) => m.name !== recorded.name),
+                          recorded,
+                        ],
+                      })
+                    }
+                    onErr={onErr}
+                  />
+                ) : bind.type === "turbo" ? (
+                  <div className="flex flex-col gap-1">
+                    <Dropdown
+                      options={OUTPUT_OPTIONS}
+                      onChange={(option) =>
+                        setTurbo(bind, { action: outputOptionAction(option) })
+                      }
+                    >
+                      {capitalizeFirstLetter(outputOptionName(bind.turbo?.action))}
+                    </Dropdown>
+                    <div className="flex items-center gap-1 text-sm">
+                      <input
+                        type="number"
+                        min={1}
+                        value={bind.turbo?.rateHz ?? DEFAULT_TURBO_RATE_HZ}
+                        title="Pulses per second"
+                        onChange={(e) =>
+                          setTurbo(bind, { rateHz: Math.max(0, parseInt(e.target.value) || 0) })
+                        }
+                        className="w-16 rounded-md bg-blue-900 px-2 py-1"
+                      />
+                      <span>Hz</span>
+                      <input
+                        type="number"
+                        min={1}
+                        max={99}
+                        value={bind.turbo?.dutyPercent ?? DEFAULT_TURBO_DUTY_PERCENT}
+                        title="Part of every pulse the output is held (%)"
+                        onChange={(e) =>
+                          setTurbo(bind, {
+                            dutyPercent: Math.max(0, parseInt(e.target.value) || 0),
+                          })
+                        }
+                        className="w-16 rounded-md bg-blue-900 px-2 py-1"
+                      />
+                      <span>% on</span>
+                    </div>
+                  </div>
+                ) : bind.type === "tap_hold" ? (
+                  <div className="flex flex-col gap-1">
+                    {(["tap", "hold"] as const).map((field) => (
+                      <div key={field} className="flex items-center gap-1">
+                        <span className="w-10">{capitalizeFirstLetter(field)}</span>
+                        <Dropdown
+                          options={Object.keys(CONTROLLER_INPUTS)}
+                          onChange={(option) =>
+                            setBinds(
+                              binds.map((b) =>
+                                b.id === bind.id
+                                  ? { ...b, [field]: CONTROLLER_INPUTS[option] }
+                                  : b,
+                              ),
+                            )
                           }
-                          : b,
-                      );
-                      setBinds(newKeybinds);
-                    }}
-                  >
-                    {capitalizeFirstLetter(bind.output)}
-                  </Dropdown>
+                        >
+                          {capitalizeFirstLetter(controllerInputName(bind[field]))}
+                        </Dropdown>
+                      </div>
+                    ))}
+                    <div className="flex items-center gap-1 text-sm">
+                      <span>Hold after</span>
+                      <input
+                        type="number"
+                        min={0}
+                        value={bind.tapTimeout ?? DEFAULT_TAP_TIMEOUT_MS}
+                        title="Tap timeout (ms)"
+                        onChange={(e) =>
+                          setBinds(
+                            binds.map((b) =>
+                              b.id === bind.id
+                                ? { ...b, tapTimeout: Math.max(0, parseInt(e.target.value) || 0) }
+                                : b,
+                            ),
+                          )
+                        }
+                        className="w-20 rounded-md bg-blue-900 px-3 py-1"
+                      />
+                      <span>ms</span>
+                    </div>
+                  </div>
+                ) : bind.type === "layer_hold" || bind.type === "layer_toggle" ? (
+                  <div className="flex items-center gap-1">
+                    <span>{LAYER_BIND_LABELS[bind.type]}</span>
+                    <Dropdown
+                      options={(config.layers ?? []).map((layer) => layer.name)}
+                      onChange={(option) =>
+                        setBinds(
+                          binds.map((b) => (b.id === bind.id ? { ...b, output: option } : b)),
+                        )
+                      }
+                    >
+                      {bind.output}
+                    </Dropdown>
+                  </div>
+                ) : bind.type === "stick_scale" ? (
+                  <div className="flex items-center gap-1">
+                    <input
+                      type="number"
+                      min={0}
+                      value={bind.output}
+                      title="Stick scale (%)"
+                      onChange={(e) =>
+                        setBinds(
+                          binds.map((b) =>
+                            b.id === bind.id ? { ...b, output: e.target.value } : b,
+                          ),
+                        )
+                      }
+                      className="w-20 rounded-md bg-blue-900 px-3 py-1"
+                    />
+                    <span>% stick</span>
+                  </div>
+                ) : bind.type === "controller" ? (
+                  <>
+                    <Dropdown
+                      options={Object.keys(CONTROLLER_INPUTS)}
+                      onChange={(option) => {
+                        const newKeybinds = binds.map((b) =>
+                          b.id === bind.id
+                            ? {
+                              ...b,
+                              output: option,
+                              type: bind.type,
+                            }
+                            : b,
+                        );
+                        setBinds(newKeybinds);
+                      }}
+                    >
+                      {capitalizeFirstLetter(bind.output)}
+                    </Dropdown>
+                    {CONTROLLER_INPUTS[bind.output]?.type === "axis" && (
+                      <RampSettings
+                        ramp={bind.ramp}
+                        onChange={(ramp) =>
+                          setBinds(
+                            binds.map((b) => (b.id === bind.id ? { ...b, ramp } : b)),
+                          )
+                        }
+                      />
+                    )}
+                  </>
                 ) : (
                   <Dropdown
                     options={Object.keys(WINDOWS_ECMA_KEYMAP)}
@@ -325,6 +953,20 @@ function KeybindSettings({
               </td>
               <td className="px-4 py-2 text-3xl">
                 {bind.type === "socd" ? "↔" : "←"}
+                {bind.type === "socd" && (
+                  <div className="text-sm">
+                    <Dropdown
+                      options={[
+                        ...Object.values(SOCD_MODE_NAMES),
+                        ...(bind.input ? [`${bind.input} wins`] : []),
+                        ...(bind.output ? [`${bind.output} wins`] : []),
+                      ]}
+                      onChange={(option) => setSocdMode(bind, option)}
+                    >
+                      {socdModeLabel(bind)}
+                    </Dropdown>
+                  </div>
+                )}
               </td>
               <td className="px-4 py-2">
                 <Dropdown
@@ -363,6 +1005,61 @@ function KeybindSettings({
                 >
                   {capitalizeFirstLetter(bind.input)}
                 </Dropdown>
+                {bind.type !== "socd" && (
+                  <div className="mt-1 flex flex-wrap items-center gap-1 text-sm">
+                    {bind.chord?.map((key) => (
+                      <button
+                        key={key}
+                        title="Remove from chord"
+                        onClick={() =>
+                          setBinds(
+                            binds.map((b) =>
+                              b.id === bind.id
+                                ? { ...b, chord: b.chord?.filter((k) => k !== key) }
+                                : b,
+                            ),
+                          )
+                        }
+                        className="rounded bg-blue-900 px-2 py-1 hover:bg-rose-700"
+                      >
+                        + {capitalizeFirstLetter(key)}
+                      </button>
+                    ))}
+                    <Dropdown
+                      options={Object.keys(WINDOWS_ECMA_KEYMAP)}
+                      width={120}
+                      onChange={(option) =>
+                        setBinds(
+                          binds.map((b) =>
+                            b.id === bind.id && option !== b.input && !b.chord?.includes(option)
+                              ? { ...b, chord: [...(b.chord ?? []), option] }
+                              : b,
+                          ),
+                        )
+                      }
+                    >
+                      + Chord key
+                    </Dropdown>
+                  </div>
+                )}
+                {!bind.chord?.length && (inputDevices.length > 1 || bind.device) && (
+                  <div className="mt-1 text-sm">
+                    <Dropdown
+                      options={[ANY_DEVICE, ...inputDevices]}
+                      width={200}
+                      onChange={(option) => {
+                        const device = option === ANY_DEVICE ? undefined : option;
+                        // Both binds of a SOCD pair read the same device
+                        const pair = linkedBinds.find((b) => b.includes(bind.id)) ?? [bind.id];
+                        setBinds(
+                          binds.map((b) => (pair.includes(b.id) ? { ...b, device } : b)),
+                        );
+                      }}
+                    >
+                      {bind.device ?? ANY_DEVICE}
+                    </Dropdown>
+                  </div>
+                )}
               </td>
               <td className="flex justify-center gap-2.5 px-0 py-2">
                 <button
@@ -409,9 +1106,9 @@ function KeybindSettings({
             >
               +
               <Dropdown
-                options={["Keyboard", "Controller", "SOCD"]}
+                options={Object.keys(NEW_BIND_TYPES)}
                 onChange={(option) => {
-                  const type = option.toLowerCase() as BindType;
+                  const type = NEW_BIND_TYPES[option];
                   if (type === "socd") {
                     setBinds([
                       ...binds,
@@ -440,7 +1137,7 @@ function KeybindSettings({
                         id: bindsCount,
                         input: "",
                         type,
-                        output: "",
+                        output: type === "stick_scale" ? DEFAULT_STICK_SCALE : "",
                       },
                     ]);
                     setBindsCount(bindsCount + 1);
@@ -457,6 +1154,91 @@ function KeybindSettings({
           </tr>
         </tbody>
       </table>
+      <div className="mb-4 flex flex-wrap items-center justify-center gap-2">
+        {(Object.keys(STICK_AXIS_NAMES) as StickAxis[])
+          .filter((axis) => stickAxisBinds(axis).length > 1)
+          .map((axis) => (
+            <div key={axis} className="flex items-center gap-2">
+              <span>{STICK_AXIS_NAMES[axis]}</span>
+              <Dropdown
+                options={[
+                  ...Object.values(SOCD_MODE_NAMES),
+                  ...stickAxisBinds(axis).map((bind) => `${bind.input} wins`),
+                ]}
+                onChange={(option) => setAxisSocd(axis, option)}
+              >
+                {axisSocdLabel(axis)}
+              </Dropdown>
+            </div>
+          ))}
+      </div>
+      <div className="mb-4 flex flex-wrap items-center justify-center gap-2">
+        {(["left", "right"] as Stick[])
+          .filter((stick) =>
+            (Object.keys(STICK_AXIS_NAMES) as StickAxis[]).some(
+              (axis) => axis.startsWith(stick) && stickAxisBinds(axis).length > 0,
+            ),
+          )
+          .map((stick) => {
+            const normalization = config.stick_normalization?.[stick];
+            return (
+              <div key={stick} className="flex items-center gap-2">
+                <span>{capitalizeFirstLetter(stick)} stick diagonals</span>
+                <Dropdown
+                  options={NORMALIZATION_NAMES}
+                  onChange={(option) =>
+                    setNormalization(
+                      stick,
+                      option === "Custom diagonal"
+                        ? { diagonal: DEFAULT_DIAGONAL }
+                        : (option.toLowerCase() as StickNormalization),
+                    )
+                  }
+                >
+                  {normalizationName(normalization)}
+                </Dropdown>
+                {typeof normalization === "object" && (
+                  <input
+                    type="number"
+                    min={1}
+                    value={normalization.diagonal}
+                    title="Diagonal length (% of the larger axis)"
+                    onChange={(e) =>
+                      setNormalization(stick, {
+                        diagonal: Math.max(0, parseInt(e.target.value) || 0),
+                      })
+                    }
+                    className="w-20 rounded-md bg-blue-900 px-3 py-1"
+                  />
+                )}
+              </div>
+            );
+          })}
+      </div>
+      {binds.some((bind) => bind.chord?.length) && (
+        <div className="mb-4 flex items-center justify-center gap-2">
+          <span>Chord window (ms)</span>
+          <input
+            type="number"
+            min={0}
+            value={config.chord_window_ms ?? DEFAULT_CHORD_WINDOW_MS}
+            title="How close together the keys of a chord have to be pressed"
+            onChange={(e) =>
+              setConfig({
+                ...config,
+                chord_window_ms: Math.max(0, parseInt(e.target.value) || 0),
+              })
+            }
+            className="w-20 rounded-md bg-blue-900 px-3 py-1"
+          />
+        </div>
+      )}
+      {platform() === "linux" && (
+        <MouseLookSettings
+          mouseLook={config.mouse_look}
+          onChange={(mouseLook) => setConfig({ ...config, mouse_look: mouseLook })}
+        />
+      )}
       <div className="flex justify-end">
         <button
           onClick={handleSave}
@@ -2,12 +2,16 @@ export function InputTypeIcon({
   type,
   badge,
 }: {
-  type: "controller" | "keyboard" | "socd";
+  type: "controller" | "keyboard" | "socd" | "stick_scale" | "layer_hold" | "layer_toggle" | "tap_hold" | "turbo" | "macro";
   badge?: string;
 }) {
   const getIcon = () => {
     switch (type) {
       case "controller":
+      case "stick_scale":
+      case "tap_hold":
+      case "turbo":
+      case "macro":
         return (
           <svg
             version="Controller"
@@ -25,6 +29,8 @@ export function InputTypeIcon({
           </svg>
         );
       case "keyboard":
+      case "layer_hold":
+      case "layer_toggle":
         return (
           <svg
             version="Keyboard"
@@ -0,0 +1,181 @@
+import { invoke } from "@tauri-apps/api/core";
+import { useState } from "react";
+import {
+  CONTROLLER_INPUTS,
+  MacroMode,
+  MacroStep,
+  OUTPUT_OPTIONS,
+  outputOptionAction,
+  outputOptionName,
+  RecordedMacro,
+} from "../constants";
+import Dropdown from "./Dropdown";
+
+const MODE_NAMES: Record<MacroMode, string> = {
+  once: "Play once",
+  repeat_while_held: "Repeat while held",
+  cancel_on_release: "Cancel on release",
+};
+
+const STEP_KINDS = ["Press", "Release"];
+
+// Steps of a macro binding, each one waits its delay after the previous step
+function MacroSettings({
+  steps,
+  mode,
+  recorded,
+  onChange,
+  onRecorded,
+  onErr,
+}: {
+  steps: MacroStep[];
+  mode: MacroMode;
+  recorded: RecordedMacro[];
+  onChange: (steps: MacroStep[], mode: MacroMode) => void;
+  onRecorded: (recorded: RecordedMacro) => void;
+  onErr: (error: string) => void;
+}) {
+  const [isRecording, setIsRecording] = useState(false);
+  const [quantizeMs, setQuantizeMs] = useState(0);
+
+  const toggleRecording = () => {
+    if (!isRecording) {
+      invoke("start_recording")
+        .then(() => setIsRecording(true))
+        .catch((err) => onErr(err));
+      return;
+    }
+
+    let number = recorded.length + 1;
+    while (recorded.some((m) => m.name === `Recording ${number}`)) {
+      number++;
+    }
+    setIsRecording(false);
+    invoke<RecordedMacro>("stop_recording", {
+      name: `Recording ${number}`,
+      quantizeMs: quantizeMs > 0 ? quantizeMs : null,
+    })
+      .then((macro) => {
+        onRecorded(macro);
+        onChange(macro.steps, mode);
+      })
+      .catch((err) => onErr(err));
+  };
+
+  const updateStep = (index: number, changes: Partial<MacroStep>) =>
+    onChange(
+      steps.map((step, i) => (i === index ? { ...step, ...changes } : step)),
+      mode,
+    );
+
+  const addStep = () => {
+    // A new step releases what the previous one pressed, that's the common case
+    const previous = steps[steps.length - 1];
+    onChange(
+      [
+        ...steps,
+        previous
+          ? { delay_ms: previous.delay_ms, action: previous.action, is_down: !previous.is_down }
+          : { delay_ms: 0, action: CONTROLLER_INPUTS["A"], is_down: true },
+      ],
+      mode,
+    );
+  };
+
+  return (
+    <div className="flex flex-col gap-1 text-sm">
+      <Dropdown
+        options={Object.values(MODE_NAMES)}
+        width={160}
+        onChange={(option) =>
+          onChange(
+            steps,
+            (Object.keys(MODE_NAMES) as MacroMode[]).find((m) => MODE_NAMES[m] === option) ??
+            "once",
+          )
+        }
+      >
+        {MODE_NAMES[mode]}
+      </Dropdown>
+      <div className="flex items-center gap-1">
+        <button
+          onClick={toggleRecording}
+          className={`rounded px-2 py-1 ${isRecording ? "bg-rose-700 hover:bg-rose-500" : "bg-slate-800 bg-opacity-60 hover:bg-slate-500"}`}
+        >
+          {isRecording ? "Stop recording" : "Record"}
+        </button>
+        <input
+          type="number"
+          min={0}
+          value={quantizeMs}
+          title="Round recorded delays to this many ms, 0 keeps them exact"
+          onChange={(e) => setQuantizeMs(Math.max(0, parseInt(e.target.value) || 0))}
+          className="w-16 rounded-md bg-blue-900 px-2 py-1"
+        />
+        <span>ms grid</span>
+        {recorded.length > 0 && (
+          <Dropdown
+            options={recorded.map((m) => m.name)}
+            width={140}
+            onChange={(option) => {
+              const macro = recorded.find((m) => m.name === option);
+              if (macro) {
+                onChange(macro.steps, mode);
+              }
+            }}
+          >
+            Load recording
+          </Dropdown>
+        )}
+      </div>
+      {steps.map((step, index) => (
+        <div key={index} className="flex items-center gap-1">
+          <input
+            type="number"
+            min={0}
+            value={step.delay_ms}
+            title="Delay after the previous step (ms)"
+            onChange={(e) =>
+              updateStep(index, { delay_ms: Math.max(0, parseInt(e.target.value) || 0) })
+            }
+            className="w-16 rounded-md bg-blue-900 px-2 py-1"
+          />
+          <span>ms</span>
+          <Dropdown
+            options={STEP_KINDS}
+            width={90}
+            onChange={(option) => updateStep(index, { is_down: option === "Press" })}
+          >
+            {step.is_down ? "Press" : "Release"}
+          </Dropdown>
+          <Dropdown
+            options={OUTPUT_OPTIONS}
+            width={160}
+            onChange={(option) => updateStep(index, { action: outputOptionAction(option) })}
+          >
+            {outputOptionName(step.action)}
+          </Dropdown>
+          <button
+            onClick={() =>
+              onChange(
+                steps.filter((_, i) => i !== index),
+                mode,
+              )
+            }
+            className="rounded bg-rose-700 px-2 py-1 font-bold hover:bg-rose-500"
+          >
+            -
+          </button>
+        </div>
+      ))}
+      <button
+        onClick={addStep}
+        className="rounded bg-slate-800 bg-opacity-60 px-2 py-1 hover:bg-slate-500"
+      >
+        + Step
+      </button>
+    </div>
+  );
+}
+
+export default MacroSettings;
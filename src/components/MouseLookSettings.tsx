@@ -0,0 +1,70 @@
+import { MouseLook } from "../constants";
+
+const DEFAULT_MOUSE_LOOK: MouseLook = {
+  sensitivity: 2000,
+  deadzone_percent: 0,
+  smoothing_ms: 20,
+  decay_ms: 50,
+};
+
+// Right stick movement from the mouse, off when unset
+function MouseLookSettings({
+  mouseLook,
+  onChange,
+}: {
+  mouseLook?: MouseLook;
+  onChange: (mouseLook?: MouseLook) => void;
+}) {
+  const numberInput = (
+    label: string,
+    key: keyof MouseLook,
+    title: string,
+    max?: number,
+  ) => (
+    <label className="flex items-center gap-1" title={title}>
+      <span>{label}</span>
+      <input
+        type="number"
+        min={0}
+        max={max}
+        value={mouseLook![key]}
+        onChange={(e) =>
+          onChange({
+            ...mouseLook!,
+            [key]: Math.min(max ?? Infinity, Math.max(0, parseInt(e.target.value) || 0)),
+          })
+        }
+        className="w-20 rounded-md bg-blue-900 px-3 py-1"
+      />
+    </label>
+  );
+
+  return (
+    <div className="mb-4 flex flex-wrap items-center justify-center gap-2">
+      <label className="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={mouseLook !== undefined}
+          onChange={() => onChange(mouseLook ? undefined : DEFAULT_MOUSE_LOOK)}
+          className="h-5 w-5 cursor-pointer"
+        />
+        <span>Mouse moves right stick</span>
+      </label>
+      {mouseLook && (
+        <>
+          {numberInput("Sensitivity", "sensitivity", "Stick value for one count per millisecond")}
+          {numberInput(
+            "Deadzone (%)",
+            "deadzone_percent",
+            "Where the stick starts, to get past the game's deadzone",
+            99,
+          )}
+          {numberInput("Smoothing (ms)", "smoothing_ms", "Mouse speed is averaged over this long")}
+          {numberInput("Decay (ms)", "decay_ms", "Longest the stick takes to center once the mouse stops")}
+        </>
+      )}
+    </div>
+  );
+}
+
+export default MouseLookSettings;
@@ -0,0 +1,143 @@
+import { invoke } from "@tauri-apps/api/core";
+import { useEffect, useState } from "react";
+import Dropdown from "./Dropdown";
+
+interface ProfileList {
+  profiles: string[];
+  active: string;
+  programs: Record<string, string>;
+}
+
+function Profiles({
+  onActivate,
+  onErr,
+}: {
+  onActivate: () => void;
+  onErr: (error: string) => void;
+}) {
+  const [profileList, setProfileList] = useState<ProfileList>({
+    profiles: [],
+    active: "",
+    programs: {},
+  });
+  const [allowedPrograms, setAllowedPrograms] = useState<string[]>([]);
+  const [nameInput, setNameInput] = useState<"create" | "rename" | undefined>(
+    undefined,
+  );
+  const [name, setName] = useState("");
+
+  const readProfiles = () =>
+    invoke("list_profiles")
+      .then((response) => setProfileList(response as ProfileList))
+      .catch((err) => onErr(err));
+
+  useEffect(() => {
+    readProfiles();
+    invoke("read_app_settings")
+      .then((response: any) => setAllowedPrograms(response.allowed_programs ?? []))
+      .catch((err) => onErr(err));
+  }, []);
+
+  const runCommand = (command: string, args: any, activated: boolean) => {
+    invoke(command, args)
+      .then(() => readProfiles())
+      .then(() => {
+        if (activated) {
+          onActivate();
+        }
+      })
+      .catch((err) => onErr(err));
+  };
+
+  const submitName = () => {
+    if (name.trim() !== "") {
+      if (nameInput === "create") {
+        // New profiles start as a copy of the active one and become active
+        invoke("create_profile", { name, copyFrom: profileList.active })
+          .then(() => runCommand("activate_profile", { name }, true))
+          .catch((err) => onErr(err));
+      } else if (nameInput === "rename") {
+        runCommand("rename_profile", { name: profileList.active, newName: name }, false);
+      }
+    }
+    setName("");
+    setNameInput(undefined);
+  };
+
+  const activePrograms = Object.entries(profileList.programs)
+    .filter(([_, profile]) => profile === profileList.active)
+    .map(([program]) => program);
+
+  const setActivePrograms = (programs: string[]) =>
+    runCommand("set_profile_programs", { name: profileList.active, programs }, false);
+
+  const buttonClassName =
+    "rounded bg-indigo-800 bg-opacity-60 px-3 py-1 font-bold text-white hover:bg-indigo-600";
+
+  return (
+    <div className="mb-4 flex flex-col items-center gap-2">
+      <div className="flex items-center justify-center gap-2">
+        <span>Profile</span>
+        <Dropdown
+          options={profileList.profiles}
+          onChange={(profile) => runCommand("activate_profile", { name: profile }, true)}
+        >
+          {profileList.active}
+        </Dropdown>
+        {nameInput ? (
+          <input
+            autoFocus
+            type="text"
+            value={name}
+            placeholder={nameInput === "create" ? "New profile name" : "New name"}
+            onChange={(e) => setName(e.target.value)}
+            onBlur={submitName}
+            onKeyDown={(e) => {
+              if (e.key === "Enter") {
+                submitName();
+              }
+            }}
+            className="rounded-md bg-blue-900 px-3 py-1"
+          />
+        ) : (
+          <>
+            <button className={buttonClassName} onClick={() => setNameInput("create")}>
+              New
+            </button>
+            <button className={buttonClassName} onClick={() => setNameInput("rename")}>
+              Rename
+            </button>
+            <button
+              className={buttonClassName}
+              onClick={() => runCommand("delete_profile", { name: profileList.active }, true)}
+            >
+              Delete
+            </button>
+          </>
+        )}
+      </div>
+      <div className="flex flex-wrap items-center justify-center gap-2">
+        <span>Used for</span>
+        {activePrograms.map((program) => (
+          <div
+            key={program}
+            className="inline-flex cursor-pointer rounded-md bg-blue-900 px-4 py-2 shadow-sm hover:bg-blue-700 hover:line-through"
+            onClick={() =>
+              setActivePrograms(activePrograms.filter((p) => p !== program))
+            }
+          >
+            {program}
+          </div>
+        ))}
+        <Dropdown
+          options={allowedPrograms.filter((p) => !activePrograms.includes(p))}
+          onChange={(program) => setActivePrograms([...activePrograms, program])}
+        >
+          {allowedPrograms.length > 0 ? "+ Allowed program" : "No allowed programs"}
+        </Dropdown>
+      </div>
+    </div>
+  );
+}
+
+export default Profiles;
@@ -0,0 +1,65 @@
+import { Ramp, RampCurve } from "../constants";
+import Dropdown from "./Dropdown";
+
+const CURVE_NAMES = ["Linear", "Exponential", "Stepped"];
+const DEFAULT_STEPS = 4;
+
+const curveName = (curve: RampCurve) =>
+  typeof curve === "object" ? "Stepped" : curve === "exponential" ? "Exponential" : "Linear";
+
+// Ramp times of a stick binding, a binding without times is applied instantly
+function RampSettings({
+  ramp,
+  onChange,
+}: {
+  ramp?: Ramp;
+  onChange: (ramp?: Ramp) => void;
+}) {
+  const current = ramp ?? { up_ms: 0, down_ms: 0, curve: "linear" as RampCurve };
+
+  const update = (changes: Partial<Ramp>) => {
+    const next = { ...current, ...changes };
+    onChange(next.up_ms > 0 || next.down_ms > 0 ? next : undefined);
+  };
+
+  const numberInput = (value: number, onValue: (value: number) => void, title: string) => (
+    <input
+      type="number"
+      min={0}
+      value={value}
+      title={title}
+      onChange={(e) => onValue(Math.max(0, parseInt(e.target.value) || 0))}
+      className="w-16 rounded-md bg-blue-900 px-2 py-1"
+    />
+  );
+
+  return (
+    <div className="mt-1 flex items-center gap-1 text-sm">
+      <span>Ramp</span>
+      {numberInput(current.up_ms, (up_ms) => update({ up_ms }), "Ramp up (ms)")}
+      {numberInput(current.down_ms, (down_ms) => update({ down_ms }), "Ramp down (ms)")}
+      <Dropdown
+        options={CURVE_NAMES}
+        width={120}
+        onChange={(option) =>
+          update({
+            curve:
+              option === "Stepped"
+                ? { stepped: DEFAULT_STEPS }
+                : (option.toLowerCase() as RampCurve),
+          })
+        }
+      >
+        {curveName(current.curve)}
+      </Dropdown>
+      {typeof current.curve === "object" &&
+        numberInput(
+          current.curve.stepped,
+          (stepped) => update({ curve: { stepped } }),
+          "Steps",
+        )}
+    </div>
+  );
+}
+
+export default RampSettings;
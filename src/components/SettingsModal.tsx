@@ -3,24 +3,31 @@ import { platform } from "@tauri-apps/plugin-os";
 import { useEffect, useState } from "react";
 import isEqual from "lodash/isEqual";
 import OptionsList from "./OptionsList";
+import SetupChecks from "./SetupChecks";
 import Dropdown from "./Dropdown";
 import { cloneDeep } from "lodash";
+import { InputDeviceInfo } from "../constants";
 
 interface Setting {
   key: string;
   name: string;
-  value: boolean | string[] | string;
+  value: boolean | string[] | string | null;
 }
 
 const settingNames = {
   close_to_tray: "Close to system tray",
   allowed_programs: "Allowed programs",
   block_kb_on_controller: "Block keyboard inputs on controller binds",
-  selected_input: "Input devices",
+  selected_inputs: "Input devices",
+  selected_mouse: "Mouse device",
   force_cursor: "Show forced cursor",
 };
 
-const dirtySettings = ["allowed_programs", "selected_input", "force_cursor"];
+const dirtySettings = ["allowed_programs", "selected_inputs", "selected_mouse", "force_cursor"];
+
+const NO_MOUSE = "None";
+
+const DETECT_TIMEOUT_MS = 10000;
 
 function SettingsModal({
   onCancel,
@@ -35,7 +42,25 @@ function SettingsModal({
 }) {
   const [originalSettings, setOriginalSettings] = useState({} as any);
   const [settings, setSettings] = useState([] as Setting[]);
-  const [inputs, setInputs] = useState([] as string[]);
+  const [devices, setDevices] = useState([] as InputDeviceInfo[]);
+  // Only keyboards, and mice for the mouse, are offered unless asked for everything
+  const [showAllDevices, setShowAllDevices] = useState(false);
+  const [isDetecting, setIsDetecting] = useState(false);
+
+  const ownDevices = devices.filter((device) => !device.is_overbind);
+  const inputs = ownDevices
+    .filter((device) => showAllDevices || device.has_letter_keys)
+    .map((device) => device.id);
+  const mice = ownDevices
+    .filter((device) => showAllDevices || device.event_types.includes("relative"))
+    .map((device) => device.id);
+  const deviceLabel = (id: string) => {
+    const device = devices.find((d) => d.id === id);
+    if (!device) {
+      return `${id} (not connected)`;
+    }
+    return device.is_grabbed ? `${device.name} (in use by another program)` : device.name;
+  };
 
   const saveSettings = () => {
     const settingsToSave = settings.reduce((acc, setting) => {
@@ -64,8 +89,14 @@ function SettingsModal({
         response["block_kb_on_controller"] = false;
       }
 
-      if (!Object.keys(response).includes("selected_input") && userPlatform === "linux") {
-        response["selected_input"] = null;
+      if (!Object.keys(response).includes("selected_inputs") && userPlatform === "linux") {
+        // Settings saved before several devices could be selected have a single one
+        response["selected_inputs"] = response["selected_input"] ? [response["selected_input"]] : [];
+      }
+      delete response["selected_input"];
+
+      if (!Object.keys(response).includes("selected_mouse") && userPlatform === "linux") {
+        response["selected_mouse"] = null;
       }
 
       if (!Object.keys(response).includes("force_cursor") && userPlatform === "linux") {
@@ -86,8 +117,8 @@ function SettingsModal({
   useEffect(() => {
     readSettings();
 
-    invoke("list_inputs").then((response: any) => {
-      setInputs(response);
+    invoke<InputDeviceInfo[]>("list_input_devices").then((response) => {
+      setDevices(response);
     });
   }, []);
 
@@ -104,6 +135,59 @@ function SettingsModal({
           className="h-6 w-6 cursor-pointer"
         />
       );
+    } else if (setting.key === "selected_inputs") {
+      const selected = setting.value as string[];
+      return (
+        <div className="flex flex-col gap-2">
+          {selected.map((input) => (
+            <div
+              key={input}
+              className="cursor-pointer rounded-md bg-blue-900 px-4 py-2 shadow-sm hover:bg-blue-700 hover:line-through"
+              onClick={() => {
+                setting.value = selected.filter((i) => i !== input);
+                setSettings([...settings]);
+              }}
+            >
+              {input}
+              <div className="text-sm text-gray-300">{deviceLabel(input)}</div>
+            </div>
+          ))}
+          <Dropdown
+            options={inputs.filter((input) => !selected.includes(input))}
+            onChange={(newInput) => {
+              setting.value = [...selected, newInput];
+              setSettings([...settings]);
+            }}
+            width={400}
+          >Add input device</Dropdown>
+          <button
+            disabled={isDetecting}
+            onClick={() => {
+              setIsDetecting(true);
+              invoke<string>("detect_input_device", { timeoutMs: DETECT_TIMEOUT_MS })
+                .then((input) => {
+                  if (!(setting.value as string[]).includes(input)) {
+                    setting.value = [...(setting.value as string[]), input];
+                    setSettings([...settings]);
+                  }
+                })
+                .catch((err) => onErr(err))
+                .finally(() => setIsDetecting(false));
+            }}
+            className="rounded-md bg-slate-800 bg-opacity-60 px-4 py-2 hover:bg-slate-500"
+          >
+            {isDetecting ? "Press a key on the keyboard to add..." : "Detect by key press"}
+          </button>
+          <label className="flex items-center justify-center gap-2 text-sm">
+            <input
+              type="checkbox"
+              checked={showAllDevices}
+              onChange={() => setShowAllDevices(!showAllDevices)}
+            />
+            Show all input devices
+          </label>
+        </div>
+      );
     } else if (Array.isArray(setting.value)) {
       return (
         <OptionsList
@@ -114,16 +198,16 @@ function SettingsModal({
           }}
         />
       );
-    } else if (setting.key === "selected_input") {
+    } else if (setting.key === "selected_mouse") {
       return (
         <Dropdown
-          options={inputs}
+          options={[NO_MOUSE, ...mice]}
           onChange={(newInput) => {
-            setting.value = newInput;
+            setting.value = newInput === NO_MOUSE ? null : newInput;
             setSettings([...settings]);
           }}
           width={400}
-        >{`${setting.value}`}</Dropdown>
+        >{setting.value ?? NO_MOUSE}</Dropdown>
       );
     };
   };
@@ -144,6 +228,7 @@ function SettingsModal({
           ))}
         </tbody>
       </table>
+      {platform() === "linux" && <SetupChecks onErr={onErr} />}
       <div className="mb-2 mt-10">
         <button
           className="mr-2 rounded bg-green-500 bg-opacity-60 px-4 py-2 font-bold text-white hover:bg-green-700"
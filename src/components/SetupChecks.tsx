@@ -0,0 +1,43 @@
+import { invoke } from "@tauri-apps/api/core";
+import { useState } from "react";
+import { SetupCheck } from "../constants";
+
+// What the system is missing for OverBind to run, checked on request
+function SetupChecks({ onErr }: { onErr: (error: string) => void }) {
+  const [checks, setChecks] = useState<SetupCheck[] | null>(null);
+
+  const runChecks = () => {
+    invoke<SetupCheck[]>("check_setup")
+      .then((response) => setChecks(response))
+      .catch((err) => onErr(err));
+  };
+
+  return (
+    <div className="mb-4 flex w-6/12 flex-col gap-2">
+      <button
+        onClick={runChecks}
+        className="rounded bg-slate-800 bg-opacity-60 px-4 py-2 hover:bg-slate-500"
+      >
+        Check setup
+      </button>
+      {checks?.map((check, index) => (
+        <div
+          key={index}
+          className={`rounded-md px-4 py-2 text-left ${check.passed
+            ? "bg-indigo-800 bg-opacity-60"
+            : check.severity === "error"
+              ? "bg-rose-900"
+              : "bg-yellow-800"
+            }`}
+        >
+          <p>
+            {check.passed ? "✓" : "✗"} {check.message}
+          </p>
+          {check.fix && <p className="text-sm text-gray-300">{check.fix}</p>}
+        </div>
+      ))}
+    </div>
+  );
+}
+
+export default SetupChecks;
@@ -106,55 +106,179 @@ export const WINDOWS_ECMA_KEYMAP: Record<string, number> = {
   '\\': 0xDC,
   ']': 0xDD,
   "'": 0xDE,
+  // Mouse buttons, bindable on linux when a mouse is selected
+  'MouseLeft': 0x01,
+  'MouseRight': 0x02,
+  'MouseMiddle': 0x04,
+  'MouseBack': 0x05,
+  'MouseForward': 0x06,
+  // The wheel has no windows keycode, OverBind uses the ones right after the keycode range
+  'WheelUp': 0x100,
+  'WheelDown': 0x101,
   // Add more mappings as needed
 }
 
 
-const FACE_BUTTONS: Record<string, number> = {
-  DPAD_UP: 0x0001,
-  DPAD_DOWN: 0x0002,
-  DPAD_LEFT: 0x0004,
-  DPAD_RIGHT: 0x0008,
-  START: 0x0010,
-  BACK: 0x0020,
-  LEFT_THUMB: 0x0040,
-  RIGHT_THUMB: 0x0080,
-  LEFT_SHOULDER: 0x0100,
-  RIGHT_SHOULDER: 0x0200,
-  GUIDE: 0x0400,
-  A: 0x1000,
-  B: 0x2000,
-  X: 0x4000,
-  Y: 0x8000,
-};
+export type SocdMode = 'last_input' | 'first_input' | 'neutral' | { priority: number };
+
+export type RampCurve = 'linear' | 'exponential' | { stepped: number };
+
+export interface Ramp {
+  up_ms: number;
+  down_ms: number;
+  curve: RampCurve;
+}
+
+export type Stick = 'left' | 'right';
+
+export type StickNormalization = 'square' | 'circle' | { diagonal: number };
+
+export type StickAxis = 'left_x' | 'left_y' | 'right_x' | 'right_y';
+
+export type MacroMode = 'once' | 'repeat_while_held' | 'cancel_on_release';
+
+export interface MacroStep {
+  delay_ms: number;
+  action: BindingAction;
+  is_down: boolean;
+}
+
+export interface MouseLook {
+  sensitivity: number;
+  deadzone_percent: number;
+  smoothing_ms: number;
+  decay_ms: number;
+}
+
+export interface RecordedMacro {
+  name: string;
+  steps: MacroStep[];
+}
+
+export type BindingAction =
+  | { type: 'keyboard'; key: number }
+  | { type: 'gamepad_button'; button: string }
+  | { type: 'axis'; axis: StickAxis; value: number; ramp?: Ramp }
+  | { type: 'trigger'; side: 'left' | 'right'; value: number }
+  | { type: 'stick_scale'; percent: number }
+  | { type: 'layer_hold'; layer: string }
+  | { type: 'layer_toggle'; layer: string }
+  | { type: 'tap_hold'; tap: BindingAction; hold: BindingAction; timeout_ms: number }
+  | { type: 'turbo'; action: BindingAction; rate_hz: number; duty_percent: number }
+  | { type: 'macro'; steps: MacroStep[]; mode: MacroMode }
+  | { type: 'socd'; opposite: number; mode: SocdMode };
+
+export interface Binding {
+  keycode: number;
+  chord?: number[];
+  device?: string;
+  action: BindingAction;
+}
+
+export interface Layer {
+  name: string;
+  bindings: Binding[];
+}
+
+export interface BindingConfig {
+  version: number;
+  bindings: Binding[];
+  layers?: Layer[];
+  axis_socd?: Partial<Record<StickAxis, SocdMode>>;
+  stick_normalization?: Partial<Record<Stick, StickNormalization>>;
+  chord_window_ms?: number;
+  recorded_macros?: RecordedMacro[];
+  mouse_look?: MouseLook;
+}
+
+export interface ConfigDiagnostic {
+  layer: number | null;
+  index: number | null;
+  field: string;
+  reason: string;
+  severity: 'error' | 'warning';
+}
 
 const STICK_DISTANCE = 29000;
 const TRIGGER_DISTANCE = 255;
 
-export const CONTROLLER_INPUTS: Record<string, { result_type: string; result_value: number }> = {
-  'DPAD UP': { result_type: 'face_button', result_value: FACE_BUTTONS.DPAD_UP },
-  'DPAD DOWN': { result_type: 'face_button', result_value: FACE_BUTTONS.DPAD_DOWN },
-  'DPAD LEFT': { result_type: 'face_button', result_value: FACE_BUTTONS.DPAD_LEFT },
-  'DPAD RIGHT': { result_type: 'face_button', result_value: FACE_BUTTONS.DPAD_RIGHT },
-  'START': { result_type: 'face_button', result_value: FACE_BUTTONS.START },
-  'BACK': { result_type: 'face_button', result_value: FACE_BUTTONS.BACK },
-  'LEFT THUMB': { result_type: 'face_button', result_value: FACE_BUTTONS.LEFT_THUMB },
-  'RIGHT THUMB': { result_type: 'face_button', result_value: FACE_BUTTONS.RIGHT_THUMB },
-  'LEFT SHOULDER': { result_type: 'face_button', result_value: FACE_BUTTONS.LEFT_SHOULDER },
-  'RIGHT SHOULDER': { result_type: 'face_button', result_value: FACE_BUTTONS.RIGHT_SHOULDER },
-  'GUIDE': { result_type: 'face_button', result_value: FACE_BUTTONS.GUIDE },
-  'A': { result_type: 'face_button', result_value: FACE_BUTTONS.A },
-  'B': { result_type: 'face_button', result_value: FACE_BUTTONS.B },
-  'X': { result_type: 'face_button', result_value: FACE_BUTTONS.X },
-  'Y': { result_type: 'face_button', result_value: FACE_BUTTONS.Y },
-  'LEFT STICK UP': { result_type: 'thumb_ly', result_value: STICK_DISTANCE },
-  'LEFT STICK DOWN': { result_type: 'thumb_ly', result_value: -STICK_DISTANCE },
-  'LEFT STICK LEFT': { result_type: 'thumb_lx', result_value: -STICK_DISTANCE },
-  'LEFT STICK RIGHT': { result_type: 'thumb_lx', result_value: STICK_DISTANCE },
-  'RIGHT STICK UP': { result_type: 'thumb_ry', result_value: STICK_DISTANCE },
-  'RIGHT STICK DOWN': { result_type: 'thumb_ry', result_value: -STICK_DISTANCE },
-  'RIGHT STICK LEFT': { result_type: 'thumb_rx', result_value: -STICK_DISTANCE },
-  'RIGHT STICK RIGHT': { result_type: 'thumb_rx', result_value: STICK_DISTANCE },
-  'LEFT TRIGGER': { result_type: 'trigger_l', result_value: TRIGGER_DISTANCE },
-  'RIGHT TRIGGER': { result_type: 'trigger_r', result_value: TRIGGER_DISTANCE },
-};
\ No newline at end of file
+export const STICK_AXIS_NAMES: Record<StickAxis, string> = {
+  left_x: 'Left stick X',
+  left_y: 'Left stick Y',
+  right_x: 'Right stick X',
+  right_y: 'Right stick Y',
+};
+
+export const CONTROLLER_INPUTS: Record<string, BindingAction> = {
+  'DPAD UP': { type: 'gamepad_button', button: 'dpad_up' },
+  'DPAD DOWN': { type: 'gamepad_button', button: 'dpad_down' },
+  'DPAD LEFT': { type: 'gamepad_button', button: 'dpad_left' },
+  'DPAD RIGHT': { type: 'gamepad_button', button: 'dpad_right' },
+  'START': { type: 'gamepad_button', button: 'start' },
+  'BACK': { type: 'gamepad_button', button: 'back' },
+  'LEFT THUMB': { type: 'gamepad_button', button: 'left_thumb' },
+  'RIGHT THUMB': { type: 'gamepad_button', button: 'right_thumb' },
+  'LEFT SHOULDER': { type: 'gamepad_button', button: 'left_shoulder' },
+  'RIGHT SHOULDER': { type: 'gamepad_button', button: 'right_shoulder' },
+  'GUIDE': { type: 'gamepad_button', button: 'guide' },
+  'A': { type: 'gamepad_button', button: 'a' },
+  'B': { type: 'gamepad_button', button: 'b' },
+  'X': { type: 'gamepad_button', button: 'x' },
+  'Y': { type: 'gamepad_button', button: 'y' },
+  'LEFT STICK UP': { type: 'axis', axis: 'left_y', value: STICK_DISTANCE },
+  'LEFT STICK DOWN': { type: 'axis', axis: 'left_y', value: -STICK_DISTANCE },
+  'LEFT STICK LEFT': { type: 'axis', axis: 'left_x', value: -STICK_DISTANCE },
+  'LEFT STICK RIGHT': { type: 'axis', axis: 'left_x', value: STICK_DISTANCE },
+  'RIGHT STICK UP': { type: 'axis', axis: 'right_y', value: STICK_DISTANCE },
+  'RIGHT STICK DOWN': { type: 'axis', axis: 'right_y', value: -STICK_DISTANCE },
+  'RIGHT STICK LEFT': { type: 'axis', axis: 'right_x', value: -STICK_DISTANCE },
+  'RIGHT STICK RIGHT': { type: 'axis', axis: 'right_x', value: STICK_DISTANCE },
+  'LEFT TRIGGER': { type: 'trigger', side: 'left', value: TRIGGER_DISTANCE },
+  'RIGHT TRIGGER': { type: 'trigger', side: 'right', value: TRIGGER_DISTANCE },
+};
+
+// Controller input name of an action, tap/hold outputs are picked from these
+export const controllerInputName = (action?: BindingAction) =>
+  Object.entries(CONTROLLER_INPUTS).find(
+    ([_, value]) => JSON.stringify(value) === JSON.stringify(action),
+  )?.[0] ?? '';
+
+// Turbo and macro outputs are either a controller input or a keyboard key
+const OUTPUT_KEY_PREFIX = 'Key ';
+
+export const OUTPUT_OPTIONS = [
+  ...Object.keys(CONTROLLER_INPUTS),
+  ...Object.keys(WINDOWS_ECMA_KEYMAP).map((key) => OUTPUT_KEY_PREFIX + key),
+];
+
+export const outputOptionName = (action?: BindingAction) =>
+  action?.type === 'keyboard'
+    ? OUTPUT_KEY_PREFIX +
+      (Object.entries(WINDOWS_ECMA_KEYMAP).find(([_, value]) => value === action.key)?.[0] ?? '')
+    : controllerInputName(action);
+
+export const outputOptionAction = (option: string): BindingAction =>
+  option.startsWith(OUTPUT_KEY_PREFIX)
+    ? { type: 'keyboard', key: WINDOWS_ECMA_KEYMAP[option.slice(OUTPUT_KEY_PREFIX.length)] }
+    : CONTROLLER_INPUTS[option];
+
+export interface InputDeviceInfo {
+  id: string;
+  name: string;
+  vendor: number;
+  product: number;
+  phys?: string;
+  path: string;
+  event_types: string[];
+  has_letter_keys: boolean;
+  is_grabbed: boolean;
+  is_overbind: boolean;
+}
+
+export interface SetupCheck {
+  name: string;
+  passed: boolean;
+  severity: "error" | "warning";
+  message: string;
+  fix?: string;
+}